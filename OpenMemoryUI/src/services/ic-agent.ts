@@ -315,6 +315,28 @@ class ICAgentService {
     return this.makeCanisterCall('GET', endpoint);
   }
 
+  // Collections (user-curated clusters)
+  async createCollection(name: string, description?: string, memory_ids?: string[]) {
+    const body = JSON.stringify({ name, description, memory_ids });
+    return this.makeCanisterCall('POST', 'collections', body);
+  }
+
+  async updateCollection(id: string, updates: { name?: string; description?: string }) {
+    return this.makeCanisterCall('PATCH', `collections/${id}`, JSON.stringify(updates));
+  }
+
+  async deleteCollection(id: string) {
+    return this.makeCanisterCall('DELETE', `collections/${id}`);
+  }
+
+  async addToCollection(id: string, memory_ids: string[]) {
+    return this.makeCanisterCall('POST', `collections/${id}/memories`, JSON.stringify({ memory_ids }));
+  }
+
+  async removeFromCollection(id: string, memory_id: string) {
+    return this.makeCanisterCall('DELETE', `collections/${id}/memories/${memory_id}`);
+  }
+
   // Categories
   async getCategories() {
     return this.makeCanisterCall('GET', 'categories');
@@ -207,8 +207,8 @@ class OpenMemoryAPI {
   // Categories
   async getCategories(): Promise<string[]> {
     try {
-      const response = await icAgent.getCategories() as { categories: string[] };
-      return response.categories || [];
+      const response = await icAgent.getCategories() as { categories: { name: string }[] };
+      return (response.categories || []).map((category) => category.name);
     } catch (error) {
       console.error('Failed to get categories:', error);
       throw new Error('Failed to get categories');
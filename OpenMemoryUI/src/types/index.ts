@@ -39,6 +39,7 @@ export interface MemoryCluster {
   memory_ids: string[];
   centroid?: number[];
   created_at: number;
+  cluster_type?: 'Automatic' | 'Manual' | 'Category' | 'Temporal' | 'Semantic';
 }
 
 export interface ClusterResponse {
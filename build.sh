@@ -71,4 +71,4 @@ echo "Example API calls:"
 echo "curl http://localhost:4943/health"
 echo "curl 'http://localhost:4943/memories/search?q=test&limit=5'"
 echo ""
-echo "For authentication, use: -H 'Authorization: Bearer test-token'"
\ No newline at end of file
+echo "For authentication, create an access token or API key and use: -H 'Authorization: Bearer om_token_...'"
\ No newline at end of file
@@ -139,7 +139,7 @@ def main():
     # Initialize client (replace with your canister URL)
     client = OpenMemoryClient(
         'https://rdmx6-jaaaa-aaaaa-aaadq-cai.ic0.app',
-        auth_token='test-token'  # Use appropriate token
+        auth_token='om_token_...'  # An access token created via /auth/tokens
     )
     
     try:
@@ -1,59 +1,98 @@
 use crate::types::*;
 use crate::utils::*;
+use crate::errors::OpenMemoryError;
 use candid::Principal;
 use ic_cdk::api::time;
 
-pub async fn authenticate_request(req: &HttpRequest) -> Result<Principal, String> {
+// The authenticated caller and what its credential allows
+#[derive(Clone, Debug)]
+pub struct AuthContext {
+    pub principal: Principal,
+    pub permissions: Vec<Permission>,
+}
+
+impl AuthContext {
+    // Credentials that act as the owner themselves carry every permission
+    pub fn full_access(principal: Principal) -> Self {
+        Self {
+            principal,
+            permissions: Permission::all(),
+        }
+    }
+    
+    pub fn has_permission(&self, permission: &Permission) -> bool {
+        self.permissions.contains(permission)
+    }
+    
+    pub fn require(&self, permission: Permission) -> Result<(), OpenMemoryError> {
+        if self.has_permission(&permission) {
+            Ok(())
+        } else {
+            Err(OpenMemoryError::permission_denied(&permission))
+        }
+    }
+}
+
+pub async fn authenticate_request(req: &HttpRequest) -> Result<AuthContext, String> {
+    // Internet Identity sessions act as the user themselves
+    if let Some(session_key) = crate::internet_identity::extract_ii_session(req) {
+        return crate::internet_identity::verify_ii_session(&session_key).map(AuthContext::full_access);
+    }
+    
     // Try Bearer token first
     if let Some(token) = extract_bearer_token(&req.headers) {
-        // Check if it's an access token (starts with "om_token_")
+        // Check if it's an access token (starts with "om_token_"); it carries its own permissions
         if token.starts_with("om_token_") {
-            return crate::storage::verify_access_token(&token);
+            let access_token = crate::storage::verify_access_token(&token)?;
+            return Ok(AuthContext {
+                principal: access_token.owner_principal,
+                permissions: access_token.permissions,
+            });
         }
-        // Fallback to legacy token verification
-        return verify_token(&token).await;
+        return Err("Invalid token".to_string());
     }
     
     // Try API Key authentication
     if let Some(api_key) = extract_api_key(&req.headers) {
-        return verify_api_key(&api_key).await;
+        return verify_api_key(&api_key);
     }
     
     Err("Missing Authorization header or API Key".to_string())
 }
 
-pub async fn authenticate_request_enhanced(req: &HttpRequest) -> Result<Principal, String> {
+pub async fn authenticate_request_enhanced(req: &HttpRequest) -> Result<AuthContext, String> {
     authenticate_request(req).await
 }
 
-pub async fn verify_api_key(api_key: &str) -> Result<Principal, String> {
-    // Simple API key verification for development
-    // In production, this should be stored securely and mapped to user principals
-    
-    match api_key {
-        "openmemory-api-key-development" => {
-            Ok(Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai")
-                .map_err(|e| format!("Invalid principal: {}", e))?)
-        }
-        "claude-code-integration-key" => {
-            Ok(Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai")
-                .map_err(|e| format!("Invalid principal: {}", e))?)
-        }
-        "om_feoQrSrz5UqCQ3DjfaXkyMv7x4mtt08O" => {
-            // Generated API key for claude code OpenMemory dev user
-            // Actual Principal ID: 4wbqy-noqfb-3dunk-64f7k-4v54w-kzvti-l24ky-jaz3f-73y36-gegjt-cqe
-            Ok(Principal::from_text("4wbqy-noqfb-3dunk-64f7k-4v54w-kzvti-l24ky-jaz3f-73y36-gegjt-cqe")
-                .map_err(|e| format!("Invalid principal: {}", e))?)
-        }
-        // Accept any API key that starts with "om_" as valid for testing
-        key if key.starts_with("om_") && key.len() > 10 => {
-            Ok(Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai")
-                .map_err(|e| format!("Invalid principal: {}", e))?)
-        }
-        _ => {
-            Err("Invalid API key".to_string())
-        }
+// The single place API keys are validated. Keys are looked up by hash and
+// carry the permissions they were created with.
+pub fn verify_api_key(api_key: &str) -> Result<AuthContext, String> {
+    if !api_key.starts_with(crate::storage::API_KEY_PREFIX) {
+        return Err("Invalid API key".to_string());
     }
+    
+    let key = crate::storage::lookup_api_key(api_key)?;
+    Ok(AuthContext {
+        principal: key.owner_principal,
+        permissions: key.permissions,
+    })
+}
+
+// API key authentication for the synchronous GET routes
+pub fn authenticate_api_key(headers: &[(String, String)]) -> Result<AuthContext, String> {
+    let api_key = extract_api_key(headers).ok_or_else(|| "API key required".to_string())?;
+    verify_api_key(&api_key)
+}
+
+// A new API key: the marker followed by 32 bytes from the canister CSPRNG
+pub async fn generate_api_key() -> Result<String, String> {
+    let secret = crate::rng::secure_random_hex(32).await?;
+    Ok(format!("{}{}", crate::storage::API_KEY_PREFIX, secret))
+}
+
+pub async fn generate_access_token() -> Result<String, String> {
+    let secret = crate::rng::secure_random_hex(32).await?;
+    Ok(format!("om_token_{}", secret))
 }
 
 pub fn extract_api_key(headers: &[(String, String)]) -> Option<String> {
@@ -66,28 +105,6 @@ pub fn extract_api_key(headers: &[(String, String)]) -> Option<String> {
     None
 }
 
-pub async fn verify_token(token: &str) -> Result<Principal, String> {
-    // Simple token verification for development
-    // In production, this should integrate with Internet Identity or proper JWT verification
-    
-    match token {
-        "test-token" => {
-            // Return a test principal for development
-            Ok(Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai")
-                .map_err(|e| format!("Invalid principal: {}", e))?)
-        }
-        "demo-user" => {
-            Ok(Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai")
-                .map_err(|e| format!("Invalid principal: {}", e))?)
-        }
-        _ => {
-            // For now, any other token is considered invalid
-            // TODO: Implement proper token verification with Internet Identity
-            Err("Invalid token".to_string())
-        }
-    }
-}
-
 pub fn generate_auth_token(user_id: Principal) -> AuthToken {
     AuthToken {
         token: generate_uuid(),
@@ -122,17 +139,6 @@ pub fn verify_jwt_token(_token: &str) -> Result<Principal, String> {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_verify_token() {
-        // Test with valid test token
-        let future = verify_token("test-token");
-        // Note: In actual tests, you'd need to use an async runtime
-        
-        // Test with invalid token
-        let future = verify_token("invalid-token");
-        // This should return an error
-    }
-
     #[test]
     fn test_is_token_expired() {
         let token = AuthToken {
@@ -150,6 +156,27 @@ mod tests {
         assert!(!is_token_expired(&token));
     }
 
+    #[test]
+    fn test_verify_api_key_rejects_unknown_formats() {
+        // Former development keys and arbitrary `om_` strings are no longer accepted
+        assert!(verify_api_key("openmemory-api-key-development").is_err());
+        assert!(verify_api_key("om_feoQrSrz5UqCQ3DjfaXkyMv7x4mtt08O").is_err());
+        assert!(authenticate_api_key(&[]).is_err());
+    }
+
+    #[test]
+    fn test_auth_context_permissions() {
+        let read_only = AuthContext {
+            principal: Principal::anonymous(),
+            permissions: vec![Permission::Read],
+        };
+        assert!(read_only.require(Permission::Read).is_ok());
+        assert_eq!(read_only.require(Permission::Delete).unwrap_err().status_code(), 403);
+        
+        let owner = AuthContext::full_access(Principal::anonymous());
+        assert!(owner.require(Permission::ManageConfig).is_ok());
+    }
+
     #[test]
     fn test_is_anonymous() {
         assert!(is_anonymous(Principal::anonymous()));
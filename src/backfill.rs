@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::time::Duration;
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use serde::{Deserialize, Serialize};
+
+// Index maintenance that touches every stored item (rebuilding the ANN
+// graphs, indexing memories stored before an index existed) must not run in
+// init or post_upgrade: once the data outgrows a single message's instruction
+// limit the upgrade itself would trap. Such work is a named job that a timer
+// runs a batch at a time in key order. The cursor lives in stable memory, so
+// a job resumes where it stopped after an upgrade. Readers check `is_running`
+// and fall back to an exact scan until the job finishes.
+
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type JobMap = StableBTreeMap<String, BackfillJob, VMem>;
+// Completion time of every job that has run to the end
+type FinishedMap = StableBTreeMap<String, u64, VMem>;
+
+/// Rebuilds the per-owner HNSW graphs from the stored vectors
+pub const VECTOR_INDEX_JOB: &str = "vector_index";
+/// Copies the embeddings kept on each memory into an empty vector store
+pub const VECTOR_MIGRATION_JOB: &str = "vector_migration";
+/// Indexes memories stored before the keyword index existed
+pub const TEXT_INDEX_JOB: &str = "text_index";
+/// Labels embeddings stored before models were tracked with their model
+pub const LEGACY_MODEL_JOB: &str = "legacy_models";
+/// Prefix of the per-user jobs that classify memories into categories
+pub const CATEGORY_JOB_PREFIX: &str = "categories:";
+
+/// Items handled per timer tick and job
+const BACKFILL_BATCH_SIZE: usize = 100;
+
+thread_local! {
+    static JOBS: RefCell<Option<JobMap>> = const { RefCell::new(None) };
+    static FINISHED: RefCell<Option<FinishedMap>> = const { RefCell::new(None) };
+    // At most one timer chain is pending; heap state, so it resets on upgrade
+    static SCHEDULED: RefCell<bool> = const { RefCell::new(false) };
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct BackfillJob {
+    pub cursor: Option<String>, // Last key handled
+    pub processed: u64,
+    pub started_at: u64,
+}
+
+impl Storable for BackfillJob {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+// Opened before the modules whose init may start a job
+pub fn init() {
+    JOBS.with(|jobs| {
+        *jobs.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_BACKFILL_JOBS)
+        ));
+    });
+    FINISHED.with(|finished| {
+        *finished.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_BACKFILL_FINISHED)
+        ));
+    });
+}
+
+// Called at the end of init and post_upgrade; timers do not survive upgrades
+pub fn resume() {
+    if JOBS.with(|jobs| jobs.borrow().as_ref().is_some_and(|jobs| !jobs.is_empty())) {
+        schedule();
+    }
+}
+
+/// Start `name` from the beginning, replacing any unfinished run of it
+pub fn start(name: &str) {
+    JOBS.with(|jobs| {
+        if let Some(ref mut jobs) = *jobs.borrow_mut() {
+            jobs.insert(name.to_string(), BackfillJob {
+                cursor: None,
+                processed: 0,
+                started_at: ic_cdk::api::time(),
+            });
+        }
+    });
+    ic_cdk::println!("Backfill job {} started", name);
+    schedule();
+}
+
+/// Start `name` unless it is running or has already run to completion
+pub fn start_once(name: &str) {
+    let finished = FINISHED.with(|finished| {
+        finished.borrow().as_ref().is_some_and(|finished| finished.contains_key(&name.to_string()))
+    });
+    if !finished && !is_running(name) {
+        start(name);
+    }
+}
+
+pub fn is_running(name: &str) -> bool {
+    JOBS.with(|jobs| jobs.borrow().as_ref().is_some_and(|jobs| jobs.contains_key(&name.to_string())))
+}
+
+fn schedule() {
+    if SCHEDULED.with(|scheduled| scheduled.replace(true)) {
+        return;
+    }
+    ic_cdk_timers::set_timer(Duration::ZERO, run_tick);
+}
+
+// One batch of every pending job, then another tick while any remain
+fn run_tick() {
+    SCHEDULED.with(|scheduled| *scheduled.borrow_mut() = false);
+
+    let pending: Vec<(String, BackfillJob)> = JOBS.with(|jobs| {
+        jobs.borrow().as_ref().map(|jobs| jobs.iter().collect()).unwrap_or_default()
+    });
+
+    for (name, mut job) in pending {
+        let (handled, next_cursor) = run_batch(&name, job.cursor.as_deref(), BACKFILL_BATCH_SIZE);
+        job.processed += handled as u64;
+
+        JOBS.with(|jobs| {
+            if let Some(ref mut jobs) = *jobs.borrow_mut() {
+                match next_cursor {
+                    Some(cursor) => {
+                        job.cursor = Some(cursor);
+                        jobs.insert(name.clone(), job.clone());
+                    }
+                    None => {
+                        jobs.remove(&name);
+                        FINISHED.with(|finished| {
+                            if let Some(ref mut finished) = *finished.borrow_mut() {
+                                finished.insert(name.clone(), ic_cdk::api::time());
+                            }
+                        });
+                    }
+                }
+            }
+        });
+
+        if !is_running(&name) {
+            ic_cdk::println!("Backfill job {} finished after {} items", name, job.processed);
+        }
+    }
+
+    resume();
+}
+
+// Handle up to `limit` items after `cursor`. Returns how many were handled
+// and the cursor to continue from, or None once the job is complete.
+fn run_batch(name: &str, cursor: Option<&str>, limit: usize) -> (usize, Option<String>) {
+    match name {
+        VECTOR_INDEX_JOB => crate::vector_store::AdvancedVectorStore::rebuild_index_batch(cursor, limit),
+        VECTOR_MIGRATION_JOB => crate::vector_store::AdvancedVectorStore::migrate_batch(cursor, limit),
+        TEXT_INDEX_JOB => crate::text_index::TextIndex::index_batch(cursor, limit),
+        LEGACY_MODEL_JOB => crate::storage::label_legacy_batch(cursor, limit),
+        _ => match name.strip_prefix(CATEGORY_JOB_PREFIX).and_then(|user| Principal::from_text(user).ok()) {
+            Some(user) => crate::categories::classify_batch(user, cursor, limit),
+            None => {
+                ic_cdk::println!("Dropping unknown backfill job {}", name);
+                (0, None)
+            }
+        },
+    }
+}
@@ -0,0 +1,187 @@
+use crate::types::*;
+use crate::errors::{OpenMemoryError, Result};
+use crate::validation::SearchRequest;
+use candid::Principal;
+use ic_cdk_macros::{query, update};
+
+// Typed candid interface for canister-to-canister integrations. The caller
+// is identified by `ic_cdk::caller()` and owns everything it creates; the
+// HTTP gateway interface stays available alongside it.
+
+fn authenticated_caller() -> Result<Principal> {
+    let caller = ic_cdk::caller();
+    if crate::auth::is_anonymous(caller) {
+        return Err(OpenMemoryError::authentication("Anonymous callers are not allowed", "caller"));
+    }
+    Ok(caller)
+}
+
+// The caller's memory, or NotFound so other users' ids are not revealed
+fn owned_memory(caller: Principal, id: &str) -> Result<Memory> {
+    match crate::storage::get_memory(id) {
+        Ok(Some(memory)) if memory.user_id == caller => Ok(memory),
+        Ok(_) => Err(OpenMemoryError::not_found("memory", id)),
+        Err(e) => Err(OpenMemoryError::storage(e, "get_memory")),
+    }
+}
+
+#[update]
+async fn add_memory(request: AddMemoryRequest) -> Result<AddMemoryResponse> {
+    let caller = authenticated_caller()?;
+    crate::validation::validate_add_memory_request(&request)?;
+    crate::validation::validate_user_quota(caller, crate::storage::get_user_memory_count(caller))?;
+
+    let embedding = crate::embedding::generate_embedding_for_user(&request.content, caller)
+        .await
+        .map_err(|e| OpenMemoryError::internal(format!("Failed to generate embedding: {}", e), Some("add_memory")))?;
+
+    let timestamp = ic_cdk::api::time();
+    let memory = Memory {
+        id: crate::utils::generate_uuid(),
+        user_id: caller,
+        content: request.content.trim().to_string(),
+        embedding,
+        metadata: request.metadata.unwrap_or_default(),
+        tags: request.tags.unwrap_or_default(),
+        created_at: timestamp,
+        updated_at: timestamp,
+        embedding_model: crate::embedding::embedding_model_for_user(caller),
+    };
+
+    crate::storage::store_memory(memory.clone())
+        .await
+        .map_err(|e| OpenMemoryError::storage(e, "store_memory"))?;
+
+    Ok(AddMemoryResponse {
+        id: memory.id,
+        created_at: memory.created_at,
+    })
+}
+
+#[query]
+fn get_memory(id: String) -> Result<Memory> {
+    let caller = authenticated_caller()?;
+    owned_memory(caller, &id)
+}
+
+#[query]
+fn list_memories(limit: Option<usize>, offset: Option<usize>) -> Result<ListMemoriesResponse> {
+    let caller = authenticated_caller()?;
+    let (limit, offset) = crate::validation::validate_pagination(limit, offset)?;
+
+    let memories = crate::storage::list_user_memories(caller, offset, limit)
+        .map_err(|e| OpenMemoryError::storage(e, "list_user_memories"))?;
+
+    Ok(ListMemoriesResponse {
+        memories,
+        total_count: crate::storage::get_user_memory_count(caller),
+        offset,
+        limit,
+    })
+}
+
+// An update call because the query embedding may need an HTTP outcall
+#[update]
+async fn search(request: SearchRequest) -> Result<Vec<SearchResult>> {
+    let caller = authenticated_caller()?;
+    crate::validation::validate_search_request(&request)?;
+
+    let filters = crate::search::SearchFilters::from_request(caller, &request)?;
+    crate::search::hybrid_search(
+        &request.query,
+        request.limit.unwrap_or(10),
+        caller,
+        &filters,
+        request.mode.unwrap_or_default(),
+        request.semantic_weight.unwrap_or(0.5),
+    )
+    .await
+    .map_err(|e| OpenMemoryError::internal(format!("Search failed: {}", e), Some("search")))
+}
+
+#[update]
+async fn delete_memory(id: String) -> Result<()> {
+    let caller = authenticated_caller()?;
+    match crate::storage::delete_memory(&id, caller).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(OpenMemoryError::not_found("memory", id)),
+        Err(e) => Err(OpenMemoryError::storage(e, "delete_memory")),
+    }
+}
+
+#[update]
+async fn save_conversation(request: SaveConversationRequest) -> Result<Conversation> {
+    let caller = authenticated_caller()?;
+    crate::validation::validate_save_conversation_request(&request)?;
+
+    let timestamp = ic_cdk::api::time();
+    let conversation = Conversation {
+        id: crate::utils::generate_uuid(),
+        user_id: caller,
+        title: request.title.trim().to_string(),
+        content: request.content.trim().to_string(),
+        source: request.source.unwrap_or_else(|| "candid".to_string()),
+        metadata: request.metadata.unwrap_or_default(),
+        word_count: request.content.split_whitespace().count() as u32,
+        created_at: timestamp,
+        updated_at: timestamp,
+    };
+
+    crate::storage::save_conversation(conversation.clone())
+        .await
+        .map_err(|e| OpenMemoryError::storage(e, "save_conversation"))?;
+    Ok(conversation)
+}
+
+#[query]
+fn list_conversations(limit: Option<usize>, offset: Option<usize>) -> Result<Vec<Conversation>> {
+    let caller = authenticated_caller()?;
+    let (limit, offset) = crate::validation::validate_pagination(limit, offset)?;
+    crate::storage::get_user_conversations(caller, limit, offset)
+        .map_err(|e| OpenMemoryError::storage(e, "get_user_conversations"))
+}
+
+// Access tokens let the caller's principal be used from HTTP clients such as the CLI
+#[update]
+async fn create_token(request: CreateTokenRequest) -> Result<CreateTokenResponse> {
+    let caller = authenticated_caller()?;
+    crate::validation::validate_create_token_request(&request)?;
+
+    let token = crate::auth::generate_access_token()
+        .await
+        .map_err(|e| OpenMemoryError::internal(format!("Failed to generate token: {}", e), Some("create_token")))?;
+    let permissions = request.permissions.unwrap_or_else(|| vec![Permission::Read, Permission::Write]);
+
+    let access_token = crate::storage::create_access_token(
+        caller,
+        &token,
+        request.description,
+        permissions,
+        request.expires_in_days.unwrap_or(30),
+    )
+    .map_err(|e| OpenMemoryError::storage(e, "create_access_token"))?;
+
+    Ok(CreateTokenResponse {
+        token,
+        id: crate::storage::access_token_id(&access_token.token),
+        expires_at: access_token.expires_at,
+        permissions: access_token.permissions,
+    })
+}
+
+#[query]
+fn list_tokens() -> Result<Vec<TokenInfo>> {
+    let caller = authenticated_caller()?;
+    crate::storage::get_user_tokens(caller)
+        .map_err(|e| OpenMemoryError::storage(e, "get_user_tokens"))
+}
+
+#[update]
+fn revoke_token(token_id: String) -> Result<()> {
+    let caller = authenticated_caller()?;
+    match crate::storage::revoke_access_token(&token_id, caller) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(OpenMemoryError::not_found("token", &token_id)),
+        Err(e) => Err(OpenMemoryError::storage(e, "revoke_access_token")),
+    }
+}
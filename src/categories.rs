@@ -0,0 +1,979 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use serde::{Deserialize, Serialize};
+use crate::clustering::{dot, normalize};
+use crate::errors::{OpenMemoryError, Result};
+use crate::types::{CreateCategoryRequest, Memory, UpdateCategoryRequest};
+
+// Per-user category taxonomy. A user's taxonomy starts from the predefined
+// categories the first time it is used, and their existing memories are
+// classified by a background job. New memories are classified on insert (and
+// edited ones re-classified) by keyword matches and by embedding similarity to
+// each category's centroid; the assignment keeps the point folded into the
+// centroid so that removing the memory takes exactly that point back out.
+// Each category is stored as its own entry, so classifying a memory rewrites
+// only the category it lands in.
+
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type LegacyTaxonomyMap = StableBTreeMap<Principal, UserCategories, VMem>;
+// Keyed by `category_key`
+type CategoryMap = StableBTreeMap<String, MemoryCategory, VMem>;
+// Users whose taxonomy has been created, with the creation time
+type TaxonomyOwnerMap = StableBTreeMap<Principal, u64, VMem>;
+type AssignmentMap = StableBTreeMap<String, CategoryAssignment, VMem>;
+
+/// Maximum number of categories in one user's taxonomy
+pub const MAX_CATEGORIES_PER_USER: usize = 100;
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f32 = 0.7;
+// Matching this many keywords (or all of them, if fewer) gives full keyword confidence
+const FULL_CONFIDENCE_KEYWORD_MATCHES: usize = 3;
+
+thread_local! {
+    // Whole taxonomies of the previous layout, split up on first use
+    static LEGACY_TAXONOMIES: RefCell<Option<LegacyTaxonomyMap>> = const { RefCell::new(None) };
+    static CATEGORIES: RefCell<Option<CategoryMap>> = const { RefCell::new(None) };
+    static TAXONOMY_OWNERS: RefCell<Option<TaxonomyOwnerMap>> = const { RefCell::new(None) };
+    // Memory id -> the category it was classified into
+    static ASSIGNMENTS: RefCell<Option<AssignmentMap>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct MemoryCategory {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub keywords: Vec<String>,
+    pub confidence_threshold: f32,
+    pub memory_count: usize,       // Memories assigned directly to this category
+    pub parent_category: Option<String>,
+    pub subcategories: Vec<String>,
+    pub centroid: Vec<f32>,              // Mean of the members' unit embeddings
+    pub centroid_model: Option<String>,  // Embedding model the centroid was built from
+    pub centroid_count: u64,             // Members folded into the centroid
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct UserCategories {
+    pub categories: Vec<MemoryCategory>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CategoryAssignment {
+    pub user_id: Principal,
+    pub category_id: String,
+    pub confidence: f32,
+    pub centroid_model: Option<String>, // Set when the memory's embedding was folded into the centroid
+    pub folded_point: Option<Vec<f32>>, // The unit embedding that was folded in
+}
+
+impl Storable for UserCategories {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for MemoryCategory {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for CategoryAssignment {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl MemoryCategory {
+    fn new(id: &str, name: &str, description: &str, keywords: &[&str], confidence_threshold: f32, now: u64) -> Self {
+        MemoryCategory {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: description.to_string(),
+            keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+            confidence_threshold,
+            memory_count: 0,
+            parent_category: None,
+            subcategories: Vec::new(),
+            centroid: Vec::new(),
+            centroid_model: None,
+            centroid_count: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+// Called from init and post_upgrade
+pub fn init() {
+    LEGACY_TAXONOMIES.with(|t| {
+        *t.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_CATEGORIES)
+        ));
+    });
+
+    CATEGORIES.with(|c| {
+        *c.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_CATEGORY_ENTRIES)
+        ));
+    });
+
+    TAXONOMY_OWNERS.with(|o| {
+        *o.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_TAXONOMY_OWNERS)
+        ));
+    });
+
+    ASSIGNMENTS.with(|a| {
+        *a.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_CATEGORY_ASSIGNMENTS)
+        ));
+    });
+}
+
+// Every user starts with these top-level categories
+fn default_categories(now: u64) -> Vec<MemoryCategory> {
+    vec![
+        MemoryCategory::new(
+            "tech",
+            "Technology",
+            "Technical information, programming, software, and tech concepts",
+            &["programming", "software", "technology", "code", "algorithm", "database", "api", "framework", "library"],
+            0.7,
+            now,
+        ),
+        MemoryCategory::new(
+            "business",
+            "Business",
+            "Business concepts, strategy, management, and professional topics",
+            &["business", "strategy", "management", "marketing", "finance", "company", "revenue", "investment", "market"],
+            0.7,
+            now,
+        ),
+        MemoryCategory::new(
+            "personal",
+            "Personal",
+            "Personal notes, thoughts, experiences, and private information",
+            &["personal", "private", "diary", "thought", "idea", "reflection", "experience", "memory", "feeling"],
+            0.6,
+            now,
+        ),
+        MemoryCategory::new(
+            "reference",
+            "Reference",
+            "Reference materials, documentation, and factual information",
+            &["reference", "documentation", "guide", "manual", "fact", "definition", "instruction", "tutorial", "how-to"],
+            0.7,
+            now,
+        ),
+    ]
+}
+
+// Category ids are slugs and principal text never contains '/', so a
+// user's categories share the prefix "{user}/"
+fn category_key(user_id: Principal, category_id: &str) -> String {
+    format!("{}/{}", user_id.to_text(), category_id)
+}
+
+// A taxonomy saved as one entry by an earlier version becomes one entry per
+// category the first time the user's categories are read
+fn split_legacy_taxonomy(user_id: Principal) {
+    let legacy = LEGACY_TAXONOMIES.with(|t| t.borrow_mut().as_mut().and_then(|taxonomies| taxonomies.remove(&user_id)));
+    let Some(legacy) = legacy else { return };
+
+    for category in &legacy.categories {
+        save_category(user_id, category);
+    }
+    let created_at = legacy.categories.iter().map(|category| category.created_at).min().unwrap_or(0);
+    TAXONOMY_OWNERS.with(|o| {
+        if let Some(ref mut owners) = *o.borrow_mut() {
+            owners.insert(user_id, created_at);
+        }
+    });
+}
+
+// The user's categories in creation order, or None before the taxonomy exists
+fn load_taxonomy(user_id: Principal) -> Option<Vec<MemoryCategory>> {
+    split_legacy_taxonomy(user_id);
+    let exists = TAXONOMY_OWNERS.with(|o| o.borrow().as_ref().is_some_and(|owners| owners.contains_key(&user_id)));
+    if !exists {
+        return None;
+    }
+
+    let prefix = category_key(user_id, "");
+    let mut categories: Vec<MemoryCategory> = CATEGORIES.with(|c| {
+        c.borrow()
+            .as_ref()
+            .map(|categories| {
+                categories
+                    .range(prefix.clone()..)
+                    .take_while(|(key, _)| key.starts_with(&prefix))
+                    .map(|(_, category)| category)
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+    categories.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+    Some(categories)
+}
+
+fn load_category(user_id: Principal, category_id: &str) -> Option<MemoryCategory> {
+    split_legacy_taxonomy(user_id);
+    CATEGORIES.with(|c| c.borrow().as_ref().and_then(|categories| categories.get(&category_key(user_id, category_id))))
+}
+
+fn save_category(user_id: Principal, category: &MemoryCategory) {
+    CATEGORIES.with(|c| {
+        if let Some(ref mut categories) = *c.borrow_mut() {
+            categories.insert(category_key(user_id, &category.id), category.clone());
+        }
+    });
+}
+
+// Write back the listed categories of `categories`, leaving the rest untouched
+fn save_categories<'a>(user_id: Principal, categories: &[MemoryCategory], ids: impl IntoIterator<Item = &'a str>) {
+    let ids: BTreeSet<&str> = ids.into_iter().collect();
+    for category in categories.iter().filter(|category| ids.contains(category.id.as_str())) {
+        save_category(user_id, category);
+    }
+}
+
+fn get_assignment(memory_id: &str) -> Option<CategoryAssignment> {
+    ASSIGNMENTS.with(|a| a.borrow().as_ref().and_then(|assignments| assignments.get(&memory_id.to_string())))
+}
+
+fn save_assignment(memory_id: &str, assignment: CategoryAssignment) {
+    ASSIGNMENTS.with(|a| {
+        if let Some(ref mut assignments) = *a.borrow_mut() {
+            assignments.insert(memory_id.to_string(), assignment);
+        }
+    });
+}
+
+fn remove_assignment(memory_id: &str) -> Option<CategoryAssignment> {
+    ASSIGNMENTS.with(|a| a.borrow_mut().as_mut().and_then(|assignments| assignments.remove(&memory_id.to_string())))
+}
+
+// The user's taxonomy, created from the predefined categories on first use.
+// Existing memories are classified in the background.
+fn ensure_taxonomy(user_id: Principal) -> Vec<MemoryCategory> {
+    if let Some(categories) = load_taxonomy(user_id) {
+        return categories;
+    }
+
+    let now = ic_cdk::api::time();
+    let categories = default_categories(now);
+    for category in &categories {
+        save_category(user_id, category);
+    }
+    TAXONOMY_OWNERS.with(|o| {
+        if let Some(ref mut owners) = *o.borrow_mut() {
+            owners.insert(user_id, now);
+        }
+    });
+    start_classification(user_id);
+    ic_cdk::println!("Created category taxonomy for {}", user_id);
+    categories
+}
+
+/// Name of the background job that classifies a user's unassigned memories
+pub fn classification_job(user_id: Principal) -> String {
+    format!("{}{}", crate::backfill::CATEGORY_JOB_PREFIX, user_id.to_text())
+}
+
+fn start_classification(user_id: Principal) {
+    if crate::storage::get_user_memory_count(user_id) > 0 {
+        crate::backfill::start(&classification_job(user_id));
+    }
+}
+
+// One step of the background classification: the user's memories at
+// positions `cursor..cursor + limit` of their id list that have no category
+// yet. Memories deleted meanwhile can shift a few ids past the cursor; they
+// stay unassigned until the next run.
+pub fn classify_batch(user_id: Principal, cursor: Option<&str>, limit: usize) -> (usize, Option<String>) {
+    let Some(mut categories) = load_taxonomy(user_id) else { return (0, None) };
+    let ids = crate::storage::user_memory_ids(user_id);
+    let start = cursor.and_then(|cursor| cursor.parse().ok()).unwrap_or(0).min(ids.len());
+    let end = (start + limit).min(ids.len());
+
+    let assigned = classify_unassigned(user_id, &mut categories, &ids[start..end], ic_cdk::api::time());
+    save_categories(user_id, &categories, assigned.iter().map(String::as_str));
+    (end - start, (end < ids.len()).then(|| end.to_string()))
+}
+
+pub fn get_categories(user_id: Principal) -> Vec<MemoryCategory> {
+    ensure_taxonomy(user_id)
+}
+
+pub fn get_category(user_id: Principal, category_id: &str) -> Result<MemoryCategory> {
+    ensure_taxonomy(user_id)
+        .into_iter()
+        .find(|category| category.id == category_id)
+        .ok_or_else(|| OpenMemoryError::not_found("category", category_id))
+}
+
+pub fn assigned_category(memory_id: &str) -> Option<String> {
+    get_assignment(memory_id).map(|assignment| assignment.category_id)
+}
+
+// The user's memories assigned directly to the category
+pub fn category_memory_ids(user_id: Principal, category_id: &str) -> Vec<String> {
+    crate::storage::user_memory_ids(user_id)
+        .into_iter()
+        .filter(|id| assigned_category(id).as_deref() == Some(category_id))
+        .collect()
+}
+
+pub fn create_category(user_id: Principal, request: CreateCategoryRequest) -> Result<MemoryCategory> {
+    let mut categories = ensure_taxonomy(user_id);
+    if categories.len() >= MAX_CATEGORIES_PER_USER {
+        return Err(OpenMemoryError::validation(
+            format!("Category limit reached (max {})", MAX_CATEGORIES_PER_USER),
+            None::<String>,
+        ));
+    }
+    let name = request.name.trim().to_string();
+    ensure_unique_name(&categories, &name, None)?;
+
+    let parent = request.parent_category.filter(|parent| !parent.is_empty());
+    if let Some(ref parent) = parent {
+        validate_parent(&categories, None, parent)?;
+    }
+
+    let now = ic_cdk::api::time();
+    let mut category = MemoryCategory::new(
+        &new_category_id(&name, &categories),
+        &name,
+        request.description.as_deref().unwrap_or(""),
+        &[],
+        request.confidence_threshold.unwrap_or(DEFAULT_CONFIDENCE_THRESHOLD),
+        now,
+    );
+    category.keywords = normalize_keywords(request.keywords.unwrap_or_default());
+    category.parent_category = parent.clone();
+    let category_id = category.id.clone();
+    categories.push(category);
+    if let Some(ref parent) = parent {
+        link_subcategory(&mut categories, parent, &category_id);
+    }
+
+    // Memories no category claimed so far may belong to the new one
+    save_categories(user_id, &categories, [category_id.as_str()].into_iter().chain(parent.as_deref()));
+    start_classification(user_id);
+    get_category(user_id, &category_id)
+}
+
+pub fn update_category(user_id: Principal, category_id: &str, request: UpdateCategoryRequest) -> Result<MemoryCategory> {
+    let mut categories = ensure_taxonomy(user_id);
+    let index = category_index(&categories, category_id)?;
+    let now = ic_cdk::api::time();
+    // Parents whose subcategory lists change
+    let mut touched: Vec<String> = Vec::new();
+
+    if let Some(name) = request.name {
+        let name = name.trim().to_string();
+        ensure_unique_name(&categories, &name, Some(category_id))?;
+        categories[index].name = name;
+    }
+    if let Some(description) = request.description {
+        categories[index].description = description;
+    }
+
+    if let Some(parent) = request.parent_category {
+        let parent = Some(parent).filter(|parent| !parent.is_empty());
+        if let Some(ref parent) = parent {
+            validate_parent(&categories, Some(category_id), parent)?;
+        }
+        if let Some(old_parent) = categories[index].parent_category.take() {
+            unlink_subcategory(&mut categories, &old_parent, category_id);
+            touched.push(old_parent);
+        }
+        if let Some(ref parent) = parent {
+            link_subcategory(&mut categories, parent, category_id);
+            touched.push(parent.clone());
+        }
+        categories[index].parent_category = parent;
+    }
+
+    let reclassify = request.keywords.is_some() || request.confidence_threshold.is_some();
+    if let Some(keywords) = request.keywords {
+        categories[index].keywords = normalize_keywords(keywords);
+    }
+    if let Some(threshold) = request.confidence_threshold {
+        categories[index].confidence_threshold = threshold;
+    }
+    categories[index].updated_at = now;
+
+    let category = categories[index].clone();
+    save_categories(user_id, &categories, touched.iter().map(String::as_str).chain([category_id]));
+    if reclassify {
+        start_classification(user_id);
+    }
+    Ok(category)
+}
+
+// Subcategories move up to the deleted category's parent, and so do its
+// memories; memories of a deleted top-level category are classified again
+// among the remaining categories
+pub fn delete_category(user_id: Principal, category_id: &str) -> Result<()> {
+    let mut categories = ensure_taxonomy(user_id);
+    let index = category_index(&categories, category_id)?;
+    let category = categories.remove(index);
+    let now = ic_cdk::api::time();
+
+    if let Some(ref parent) = category.parent_category {
+        unlink_subcategory(&mut categories, parent, category_id);
+    }
+    for child_id in &category.subcategories {
+        if let Some(child) = categories.iter_mut().find(|child| &child.id == child_id) {
+            child.parent_category = category.parent_category.clone();
+            child.updated_at = now;
+        }
+        if let Some(ref parent) = category.parent_category {
+            link_subcategory(&mut categories, parent, child_id);
+        }
+    }
+
+    let members = category_memory_ids(user_id, category_id);
+    match category.parent_category.as_deref() {
+        Some(parent_id) => {
+            for memory_id in &members {
+                if let Some(mut assignment) = get_assignment(memory_id) {
+                    // The parent's centroid never included these embeddings
+                    assignment.category_id = parent_id.to_string();
+                    assignment.centroid_model = None;
+                    assignment.folded_point = None;
+                    save_assignment(memory_id, assignment);
+                }
+            }
+            if let Some(parent) = categories.iter_mut().find(|parent| parent.id == parent_id) {
+                parent.memory_count += members.len();
+                parent.updated_at = now;
+            }
+        }
+        None => {
+            for memory_id in &members {
+                remove_assignment(memory_id);
+            }
+        }
+    }
+
+    CATEGORIES.with(|c| {
+        if let Some(ref mut stored) = *c.borrow_mut() {
+            stored.remove(&category_key(user_id, category_id));
+        }
+    });
+    let touched = category.subcategories.iter().map(String::as_str).chain(category.parent_category.as_deref());
+    save_categories(user_id, &categories, touched);
+
+    if category.parent_category.is_none() && !members.is_empty() {
+        start_classification(user_id);
+    }
+    Ok(())
+}
+
+// Classify a newly stored memory and count it in its category. Returns the
+// category id, if any category was confident enough.
+pub fn classify_new_memory(memory: &Memory) -> Option<String> {
+    let mut categories = ensure_taxonomy(memory.user_id);
+    if let Some(category_id) = assigned_category(&memory.id) {
+        return Some(category_id);
+    }
+
+    let current_model = crate::embedding::embedding_model_for_user(memory.user_id);
+    let assignment = assign(&mut categories, memory, current_model.as_deref(), ic_cdk::api::time())?;
+    let category_id = assignment.category_id.clone();
+    save_assignment(&memory.id, assignment);
+    save_categories(memory.user_id, &categories, [category_id.as_str()]);
+    Some(category_id)
+}
+
+// Classify an edited memory again: take it out of its old category, using
+// the point that was folded in rather than the new embedding, then classify
+// the new content and embedding
+pub fn reclassify_memory(memory: &Memory) -> Option<String> {
+    // Without a taxonomy nothing was assigned yet; the memory is classified
+    // with the rest when the taxonomy is created
+    let mut categories = load_taxonomy(memory.user_id)?;
+    let now = ic_cdk::api::time();
+    let old_category_id = remove_assignment(&memory.id).map(|assignment| {
+        unassign(&mut categories, &assignment, memory, now);
+        assignment.category_id
+    });
+
+    let current_model = crate::embedding::embedding_model_for_user(memory.user_id);
+    let assignment = assign(&mut categories, memory, current_model.as_deref(), now);
+    let category_id = assignment.as_ref().map(|assignment| assignment.category_id.clone());
+    if let Some(assignment) = assignment {
+        save_assignment(&memory.id, assignment);
+    }
+    save_categories(memory.user_id, &categories, old_category_id.as_deref().into_iter().chain(category_id.as_deref()));
+    category_id
+}
+
+// Take a deleted memory out of its category's count and centroid
+pub fn remove_memory(memory: &Memory) {
+    let Some(assignment) = remove_assignment(&memory.id) else { return };
+    let Some(mut category) = load_category(memory.user_id, &assignment.category_id) else { return };
+
+    unassign(std::slice::from_mut(&mut category), &assignment, memory, ic_cdk::api::time());
+    save_category(memory.user_id, &category);
+}
+
+// Assign those of the given memories that have no category yet. Returns the
+// categories that gained members.
+fn classify_unassigned(user_id: Principal, categories: &mut [MemoryCategory], memory_ids: &[String], now: u64) -> BTreeSet<String> {
+    let current_model = crate::embedding::embedding_model_for_user(user_id);
+    let mut classified = BTreeSet::new();
+
+    for memory_id in memory_ids {
+        if get_assignment(memory_id).is_some() {
+            continue;
+        }
+        let Some(memory) = crate::storage::get_memory(memory_id).ok().flatten() else { continue };
+        if let Some(assignment) = assign(categories, &memory, current_model.as_deref(), now) {
+            classified.insert(assignment.category_id.clone());
+            save_assignment(memory_id, assignment);
+        }
+    }
+
+    classified
+}
+
+// Classify the memory, count it in the chosen category and fold its embedding
+// into the category centroid. A centroid built with another model is restarted
+// once a memory embedded with the user's current model arrives.
+fn assign(
+    categories: &mut [MemoryCategory],
+    memory: &Memory,
+    current_model: Option<&str>,
+    now: u64,
+) -> Option<CategoryAssignment> {
+    let tokens = memory_tokens(memory);
+    let point = memory_point(memory);
+    let embedding = point.as_ref().map(|(point, model)| (point.as_slice(), model.as_str()));
+    let (index, confidence) = classify(categories, &tokens, embedding)?;
+
+    let category = &mut categories[index];
+    category.memory_count += 1;
+    category.updated_at = now;
+
+    let mut folded = None;
+    if let Some((point, model)) = point {
+        if category.centroid_model.as_deref() == Some(model.as_str()) && category.centroid.len() == point.len() {
+            category.centroid = add_to_mean(&category.centroid, category.centroid_count, &point);
+            category.centroid_count += 1;
+            folded = Some((model, point));
+        } else if current_model == Some(model.as_str()) {
+            category.centroid = point.clone();
+            category.centroid_count = 1;
+            category.centroid_model = Some(model.clone());
+            folded = Some((model, point));
+        }
+    }
+
+    let (centroid_model, folded_point) = folded.unzip();
+    Some(CategoryAssignment {
+        user_id: memory.user_id,
+        category_id: category.id.clone(),
+        confidence,
+        centroid_model,
+        folded_point,
+    })
+}
+
+fn unassign(categories: &mut [MemoryCategory], assignment: &CategoryAssignment, memory: &Memory, now: u64) {
+    let Some(category) = categories.iter_mut().find(|category| category.id == assignment.category_id) else { return };
+    category.memory_count = category.memory_count.saturating_sub(1);
+    category.updated_at = now;
+
+    // Only an embedding that went into the current centroid can be taken out of it
+    if assignment.centroid_model.is_none() || assignment.centroid_model != category.centroid_model {
+        return;
+    }
+    // Assignments made before the folded point was kept fall back to the
+    // memory's embedding, which is only right if it has not changed since
+    let point = match assignment.folded_point {
+        Some(ref point) => Some(point.clone()),
+        None => memory_point(memory)
+            .filter(|(_, model)| Some(model) == category.centroid_model.as_ref())
+            .map(|(point, _)| point),
+    };
+    if let Some(point) = point.filter(|point| point.len() == category.centroid.len()) {
+        category.centroid = remove_from_mean(&category.centroid, category.centroid_count, &point);
+        category.centroid_count = category.centroid_count.saturating_sub(1);
+        if category.centroid_count == 0 {
+            category.centroid_model = None;
+        }
+    }
+}
+
+// Unit embedding and its model, for memories that have one
+fn memory_point(memory: &Memory) -> Option<(Vec<f32>, String)> {
+    let model = memory.embedding_model.clone()?;
+    if memory.embedding.is_empty() {
+        return None;
+    }
+    Some((normalize(&memory.embedding), model))
+}
+
+fn memory_tokens(memory: &Memory) -> HashSet<String> {
+    let mut tokens: HashSet<String> = crate::text_index::tokenize(&memory.content).into_iter().collect();
+    for tag in &memory.tags {
+        tokens.extend(crate::text_index::tokenize(tag));
+    }
+    tokens
+}
+
+// Best category whose confidence reaches its threshold. Confidence is the
+// higher of the keyword score and the cosine similarity to the category
+// centroid; ties go to the more specific category.
+fn classify(
+    categories: &[MemoryCategory],
+    tokens: &HashSet<String>,
+    embedding: Option<(&[f32], &str)>,
+) -> Option<(usize, f32)> {
+    let mut best: Option<(usize, f32, usize)> = None;
+
+    for (index, category) in categories.iter().enumerate() {
+        let keyword = keyword_score(&category.keywords, tokens);
+        let semantic = embedding
+            .map(|(point, model)| centroid_similarity(category, point, model))
+            .unwrap_or(0.0);
+        let confidence = keyword.max(semantic);
+        if confidence < category.confidence_threshold {
+            continue;
+        }
+
+        let depth = category_depth(categories, index);
+        let better = match best {
+            None => true,
+            Some((_, best_confidence, best_depth)) => {
+                confidence > best_confidence || (confidence == best_confidence && depth > best_depth)
+            }
+        };
+        if better {
+            best = Some((index, confidence, depth));
+        }
+    }
+
+    best.map(|(index, confidence, _)| (index, confidence))
+}
+
+// A keyword matches when all of its tokens occur in the memory, so "how-to"
+// or "machine learning" match as phrases
+fn keyword_score(keywords: &[String], tokens: &HashSet<String>) -> f32 {
+    if keywords.is_empty() {
+        return 0.0;
+    }
+
+    let matched = keywords
+        .iter()
+        .filter(|keyword| {
+            let parts = crate::text_index::tokenize(keyword);
+            !parts.is_empty() && parts.iter().all(|part| tokens.contains(part))
+        })
+        .count();
+    let needed = keywords.len().min(FULL_CONFIDENCE_KEYWORD_MATCHES);
+    (matched as f32 / needed as f32).min(1.0)
+}
+
+fn centroid_similarity(category: &MemoryCategory, point: &[f32], model: &str) -> f32 {
+    if category.centroid_model.as_deref() != Some(model) || category.centroid.len() != point.len() {
+        return 0.0;
+    }
+    dot(&normalize(&category.centroid), point)
+}
+
+// Mean of `count` vectors after adding `point`
+fn add_to_mean(mean: &[f32], count: u64, point: &[f32]) -> Vec<f32> {
+    let n = count as f32;
+    mean.iter().zip(point).map(|(m, p)| (m * n + p) / (n + 1.0)).collect()
+}
+
+// Mean of `count` vectors after removing `point`, one of them
+fn remove_from_mean(mean: &[f32], count: u64, point: &[f32]) -> Vec<f32> {
+    if count <= 1 {
+        return Vec::new();
+    }
+    let n = count as f32;
+    mean.iter().zip(point).map(|(m, p)| (m * n - p) / (n - 1.0)).collect()
+}
+
+fn category_depth(categories: &[MemoryCategory], index: usize) -> usize {
+    let mut depth = 0;
+    let mut parent = categories[index].parent_category.as_deref();
+    while let Some(parent_id) = parent {
+        depth += 1;
+        if depth > categories.len() {
+            break;
+        }
+        parent = categories
+            .iter()
+            .find(|category| category.id == parent_id)
+            .and_then(|category| category.parent_category.as_deref());
+    }
+    depth
+}
+
+fn category_index(categories: &[MemoryCategory], category_id: &str) -> Result<usize> {
+    categories
+        .iter()
+        .position(|category| category.id == category_id)
+        .ok_or_else(|| OpenMemoryError::not_found("category", category_id))
+}
+
+// The parent must exist and must not be the category itself or one of its
+// subcategories, which would make the hierarchy a cycle
+fn validate_parent(categories: &[MemoryCategory], category_id: Option<&str>, parent_id: &str) -> Result<()> {
+    if !categories.iter().any(|category| category.id == parent_id) {
+        return Err(OpenMemoryError::validation(
+            format!("Parent category '{}' does not exist", parent_id),
+            Some("parent_category"),
+        ));
+    }
+
+    let Some(category_id) = category_id else { return Ok(()) };
+    let mut ancestor = Some(parent_id);
+    let mut steps = 0;
+    while let Some(ancestor_id) = ancestor {
+        if ancestor_id == category_id {
+            return Err(OpenMemoryError::validation(
+                "A category cannot be nested under itself or one of its subcategories",
+                Some("parent_category"),
+            ));
+        }
+        steps += 1;
+        if steps > categories.len() {
+            break;
+        }
+        ancestor = categories
+            .iter()
+            .find(|category| category.id == ancestor_id)
+            .and_then(|category| category.parent_category.as_deref());
+    }
+    Ok(())
+}
+
+fn ensure_unique_name(categories: &[MemoryCategory], name: &str, except_id: Option<&str>) -> Result<()> {
+    let taken = categories
+        .iter()
+        .any(|category| Some(category.id.as_str()) != except_id && category.name.eq_ignore_ascii_case(name));
+    if taken {
+        return Err(OpenMemoryError::validation(
+            format!("A category named '{}' already exists", name),
+            Some("name"),
+        ));
+    }
+    Ok(())
+}
+
+fn link_subcategory(categories: &mut [MemoryCategory], parent_id: &str, child_id: &str) {
+    if let Some(parent) = categories.iter_mut().find(|category| category.id == parent_id) {
+        if !parent.subcategories.iter().any(|id| id == child_id) {
+            parent.subcategories.push(child_id.to_string());
+        }
+    }
+}
+
+fn unlink_subcategory(categories: &mut [MemoryCategory], parent_id: &str, child_id: &str) {
+    if let Some(parent) = categories.iter_mut().find(|category| category.id == parent_id) {
+        parent.subcategories.retain(|id| id != child_id);
+    }
+}
+
+// URL-safe slug of the name, suffixed to be unique within the taxonomy
+fn new_category_id(name: &str, categories: &[MemoryCategory]) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let base = if slug.is_empty() { "category".to_string() } else { slug };
+
+    let mut id = base.clone();
+    let mut suffix = 2;
+    while categories.iter().any(|category| category.id == id) {
+        id = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+    id
+}
+
+fn normalize_keywords(keywords: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(keywords.len());
+    for keyword in keywords {
+        let keyword = keyword.trim().to_lowercase();
+        if !keyword.is_empty() && !normalized.contains(&keyword) {
+            normalized.push(keyword);
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(id: &str, keywords: &[&str], parent: Option<&str>) -> MemoryCategory {
+        let mut category = MemoryCategory::new(id, id, "", keywords, 0.6, 0);
+        category.parent_category = parent.map(str::to_string);
+        category
+    }
+
+    fn tokens(text: &str) -> HashSet<String> {
+        crate::text_index::tokenize(text).into_iter().collect()
+    }
+
+    #[test]
+    fn test_keyword_score() {
+        let keywords: Vec<String> = ["database", "api", "how-to", "framework"].iter().map(|k| k.to_string()).collect();
+        assert_eq!(keyword_score(&keywords, &tokens("Nothing relevant here")), 0.0);
+        assert!((keyword_score(&keywords, &tokens("The API returns JSON")) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(keyword_score(&keywords, &tokens("A how-to for the database API")), 1.0);
+        // "how" and "to" on their own are not the hyphenated keyword
+        assert_eq!(keyword_score(&["how-to".to_string()], &tokens("how to install")), 0.0);
+        assert_eq!(keyword_score(&["api".to_string()], &tokens("api docs")), 1.0);
+        assert_eq!(keyword_score(&[], &tokens("api")), 0.0);
+    }
+
+    #[test]
+    fn test_classify_prefers_confident_and_specific_categories() {
+        let categories = vec![
+            category("tech", &["code", "software", "database"], None),
+            category("databases", &["database", "index", "query"], Some("tech")),
+            category("personal", &["diary", "feeling"], None),
+        ];
+
+        // Equal keyword confidence goes to the subcategory
+        let (index, confidence) = classify(&categories, &tokens("database code software index query"), None).unwrap();
+        assert_eq!(categories[index].id, "databases");
+        assert_eq!(confidence, 1.0);
+
+        // Below every threshold
+        assert!(classify(&categories, &tokens("database tuning"), None).is_none());
+
+        // Embedding similarity to a centroid counts even without keywords
+        let mut categories = categories;
+        categories[2].centroid = vec![0.0, 1.0];
+        categories[2].centroid_model = Some("model".to_string());
+        categories[2].centroid_count = 1;
+        let (index, _) = classify(&categories, &tokens("a quiet evening"), Some((&[0.0, 1.0], "model"))).unwrap();
+        assert_eq!(categories[index].id, "personal");
+        // but only for embeddings from the centroid's model
+        assert!(classify(&categories, &tokens("a quiet evening"), Some((&[0.0, 1.0], "other"))).is_none());
+    }
+
+    #[test]
+    fn test_mean_add_and_remove_round_trip() {
+        let mean = add_to_mean(&[1.0, 0.0], 1, &[0.0, 1.0]);
+        assert_eq!(mean, vec![0.5, 0.5]);
+        assert_eq!(remove_from_mean(&mean, 2, &[0.0, 1.0]), vec![1.0, 0.0]);
+        assert!(remove_from_mean(&[1.0, 0.0], 1, &[1.0, 0.0]).is_empty());
+    }
+
+    #[test]
+    fn test_unassign_removes_the_folded_point_after_an_edit() {
+        let mut categories = vec![category("personal", &["diary"], None)];
+        let mut memory = Memory {
+            id: "m1".to_string(),
+            user_id: Principal::anonymous(),
+            content: "diary".to_string(),
+            embedding: vec![3.0, 4.0],
+            metadata: Default::default(),
+            tags: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+            embedding_model: Some("model".to_string()),
+        };
+        let first = assign(&mut categories, &memory, Some("model"), 0).unwrap();
+        assert_eq!(first.folded_point, Some(vec![0.6, 0.8]));
+        memory.id = "m2".to_string();
+        memory.embedding = vec![1.0, 0.0];
+        assign(&mut categories, &memory, Some("model"), 0).unwrap();
+        assert_eq!(categories[0].centroid_count, 2);
+
+        // m1 was re-embedded since; its original point is what leaves the mean
+        memory.id = "m1".to_string();
+        memory.embedding = vec![0.0, 1.0];
+        unassign(&mut categories, &first, &memory, 0);
+        assert_eq!(categories[0].centroid_count, 1);
+        assert_eq!(categories[0].memory_count, 1);
+        assert!(categories[0].centroid.iter().zip([1.0, 0.0]).all(|(a, b)| (a - b).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_validate_parent_rejects_missing_parents_and_cycles() {
+        let categories = vec![
+            category("tech", &[], None),
+            category("programming", &[], Some("tech")),
+            category("rust", &[], Some("programming")),
+        ];
+
+        assert!(validate_parent(&categories, None, "tech").is_ok());
+        assert!(validate_parent(&categories, Some("rust"), "tech").is_ok());
+        assert!(validate_parent(&categories, None, "missing").is_err());
+        assert!(validate_parent(&categories, Some("tech"), "tech").is_err());
+        assert!(validate_parent(&categories, Some("tech"), "rust").is_err());
+        assert_eq!(category_depth(&categories, 2), 2);
+    }
+
+    #[test]
+    fn test_new_category_id_is_unique_slug() {
+        let categories = vec![category("machine-learning", &[], None)];
+        assert_eq!(new_category_id("Reading List", &categories), "reading-list");
+        assert_eq!(new_category_id("Machine  Learning!", &categories), "machine-learning-2");
+        assert_eq!(new_category_id("日本語", &categories), "category");
+    }
+
+    #[test]
+    fn test_legacy_taxonomy_is_split_into_category_entries() {
+        init();
+        let user = Principal::from_slice(&[1]);
+        let mut tech = category("tech", &["code"], None);
+        tech.created_at = 1;
+        let mut rust = category("rust", &["cargo"], Some("tech"));
+        rust.created_at = 2;
+        LEGACY_TAXONOMIES.with(|t| {
+            t.borrow_mut().as_mut().unwrap().insert(user, UserCategories { categories: vec![tech, rust] });
+        });
+
+        let categories = load_taxonomy(user).unwrap();
+        assert_eq!(categories.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec!["tech", "rust"]);
+        assert!(LEGACY_TAXONOMIES.with(|t| t.borrow().as_ref().unwrap().is_empty()));
+
+        // Saving one category leaves the others as they were
+        let mut rust = load_category(user, "rust").unwrap();
+        rust.memory_count = 5;
+        save_category(user, &rust);
+        assert_eq!(load_category(user, "rust").unwrap().memory_count, 5);
+        assert_eq!(load_category(user, "tech").unwrap().memory_count, 0);
+        assert!(load_taxonomy(Principal::from_slice(&[2])).is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::types::*;
+use candid::Principal;
+use ic_http_certification::{
+    utils::add_v2_certificate_header, DefaultCelBuilder, DefaultResponseCertification, HttpCertification,
+    HttpCertificationPath, HttpCertificationTree, HttpCertificationTreeEntry, StatusCode,
+    CERTIFICATE_EXPRESSION_HEADER_NAME,
+};
+use serde_json::json;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// Certified responses for the `http_request` query path. A query is answered
+// by a single replica, so public GET responses are certified (HTTP
+// certification v2) for the HTTP gateway to verify; anything that cannot be
+// certified is upgraded to an update call instead. The tree lives on the heap
+// and is rebuilt from stable memory in init and post_upgrade.
+
+pub const HEALTH_PATH: &str = "/health";
+
+struct CertifiedResponse {
+    response: HttpResponse,
+    certification: HttpCertification,
+}
+
+thread_local! {
+    static TREE: RefCell<HttpCertificationTree> = RefCell::new(HttpCertificationTree::default());
+    static RESPONSES: RefCell<HashMap<String, CertifiedResponse>> = RefCell::new(HashMap::new());
+}
+
+pub const DIGEST_PATH_PREFIX: &str = "/digests/";
+
+/// Path of a certified listing digest. The id is random and only returned to
+/// the owner, so the path does not reveal whose listing it is.
+pub fn digest_path(path_id: &str) -> String {
+    format!("{}{}", DIGEST_PATH_PREFIX, path_id)
+}
+
+// Called from init and post_upgrade, after storage is open
+pub fn init() {
+    TREE.with(|tree| tree.borrow_mut().clear());
+    RESPONSES.with(|responses| responses.borrow_mut().clear());
+
+    // Only digests that were already computed; the rest are certified when
+    // their owner next stores, deletes or lists memories
+    let listings = crate::storage::list_listing_digests();
+    for listing in &listings {
+        insert_listing_digest(listing);
+    }
+    insert_health();
+    commit();
+
+    ic_cdk::println!("Certified responses initialized for {} listings", listings.len());
+}
+
+// Re-certify after a user's memories change: their digest and the global count
+pub fn certify_user(user: Principal) {
+    insert_listing_digest(&crate::storage::user_listing_digest(user));
+    insert_health();
+    commit();
+}
+
+/// The user's listing digest and its certified path, certifying it first if
+/// it was never certified (e.g. memories stored before digests were tracked)
+pub fn user_digest(user: Principal) -> (String, Option<String>) {
+    let listing = crate::storage::user_listing_digest(user);
+    let path = listing.path_id.as_deref().map(digest_path);
+    if let Some(path) = &path {
+        if !RESPONSES.with(|responses| responses.borrow().contains_key(path)) {
+            insert_listing_digest(&listing);
+            commit();
+        }
+    }
+    (listing.digest, path)
+}
+
+fn insert_health() {
+    let health = HealthResponse {
+        status: "healthy".to_string(),
+        // Time of certification; the body must not change between certifications
+        timestamp: ic_cdk::api::time(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        memory_count: crate::storage::get_memory_count(),
+    };
+    insert(HEALTH_PATH, &health);
+}
+
+// No principal or memory count in the body: the digest alone lets the owner
+// check a listing without telling anyone else how much they store
+fn insert_listing_digest(listing: &ListingDigest) {
+    let Some(path_id) = &listing.path_id else { return };
+    let body = json!({
+        "digest": listing.digest,
+        "certified_at": ic_cdk::api::time(),
+    });
+    insert(&digest_path(path_id), &body);
+}
+
+fn insert<T: serde::Serialize>(path: &str, body: &T) {
+    let (response, certification) = certified_response(body);
+
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        let tree_path = HttpCertificationPath::exact(path);
+        RESPONSES.with(|responses| {
+            let mut responses = responses.borrow_mut();
+            if let Some(previous) = responses.remove(path) {
+                tree.delete(&HttpCertificationTreeEntry::new(&tree_path, previous.certification));
+            }
+            tree.insert(&HttpCertificationTreeEntry::new(&tree_path, certification));
+            responses.insert(path.to_string(), CertifiedResponse { response, certification });
+        });
+    });
+}
+
+// A 200 JSON response and its certification. Only the status, body and
+// Content-Type are certified; the CORS headers are served alongside.
+fn certified_response<T: serde::Serialize>(body: &T) -> (HttpResponse, HttpCertification) {
+    let cel_expr = DefaultCelBuilder::response_only_certification()
+        .with_response_certification(DefaultResponseCertification::certified_response_headers(vec![
+            "Content-Type",
+        ]))
+        .build();
+
+    let mut headers = crate::utils::create_cors_headers();
+    headers.push((CERTIFICATE_EXPRESSION_HEADER_NAME.to_string(), cel_expr.to_string()));
+    let body = serde_json::to_vec(body).unwrap_or_default();
+
+    let certified = ic_http_certification::HttpResponse::builder()
+        .with_status_code(StatusCode::OK)
+        .with_headers(headers.clone())
+        .with_body(body.clone())
+        .build();
+    let certification = HttpCertification::response_only(&cel_expr, &certified, None)
+        .expect("response-only certification of a JSON body cannot fail");
+
+    let response = HttpResponse {
+        status_code: 200,
+        headers,
+        body,
+        upgrade: None,
+    };
+    (response, certification)
+}
+
+fn commit() {
+    let root_hash = TREE.with(|tree| tree.borrow().root_hash());
+    ic_cdk::api::set_certified_data(&root_hash);
+}
+
+/// The certified response for `path` with its IC-Certificate header, or None
+/// when the path is not certified or no certificate is available
+pub fn serve(path: &str) -> Option<HttpResponse> {
+    let data_certificate = ic_cdk::api::data_certificate()?;
+
+    RESPONSES.with(|responses| {
+        let responses = responses.borrow();
+        let certified = responses.get(path)?;
+
+        let tree_path = HttpCertificationPath::exact(path);
+        let entry = HttpCertificationTreeEntry::new(&tree_path, certified.certification);
+        let witness = TREE.with(|tree| tree.borrow().witness(&entry, path)).ok()?;
+
+        let mut response = ic_http_certification::HttpResponse::builder()
+            .with_status_code(StatusCode::OK)
+            .with_headers(certified.response.headers.clone())
+            .with_body(certified.response.body.clone())
+            .build();
+        add_v2_certificate_header(&data_certificate, &mut response, &witness, &tree_path.to_expr_path());
+
+        Some(HttpResponse {
+            status_code: 200,
+            headers: response.headers().to_vec(),
+            body: certified.response.body.clone(),
+            upgrade: None,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recertifying_replaces_tree_entry() {
+        let path = "/digests/0123456789abcdef0123456789abcdef";
+        insert(path, &json!({ "digest": "a" }));
+        let first_root = TREE.with(|tree| tree.borrow().root_hash());
+        let first = RESPONSES.with(|r| r.borrow().get(path).unwrap().certification);
+
+        insert(path, &json!({ "digest": "b" }));
+        let second_root = TREE.with(|tree| tree.borrow().root_hash());
+        assert_ne!(first_root, second_root);
+
+        // The stale entry was removed: the tree matches one holding only the new body
+        let current = RESPONSES.with(|r| r.borrow().get(path).unwrap().certification);
+        assert_ne!(first, current);
+        let mut fresh = HttpCertificationTree::default();
+        fresh.insert(&HttpCertificationTreeEntry::new(HttpCertificationPath::exact(path), current));
+        assert_eq!(fresh.root_hash(), second_root);
+
+        let body = RESPONSES.with(|r| r.borrow().get(path).unwrap().response.body.clone());
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["digest"], "b");
+        assert_eq!(digest_path("0123456789abcdef0123456789abcdef"), path);
+    }
+}
@@ -1,17 +1,38 @@
 use crate::types::*;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
-use candid::Principal;
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-// Memory clustering and categorization system
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type ClusterMap = StableBTreeMap<String, MemoryCluster, VMem>;
+type UserClusterMap = StableBTreeMap<Principal, UserClusters, VMem>;
+// Assignments, centroids and silhouette score of one k-means run
+type KMeansFit = (Vec<usize>, Vec<Vec<f32>>, f32);
+
+// Memory clustering system. Clusters are kept in stable
+// memory per user; each run of `compute_clusters` replaces the user's
+// automatically generated clusters.
 thread_local! {
-    static CLUSTERS: RefCell<HashMap<String, MemoryCluster>> = RefCell::new(HashMap::new());
-    static CATEGORIES: RefCell<HashMap<String, MemoryCategory>> = RefCell::new(HashMap::new());
-    static USER_CLUSTERS: RefCell<HashMap<Principal, Vec<String>>> = RefCell::new(HashMap::new());
+    static CLUSTERS: RefCell<Option<ClusterMap>> = const { RefCell::new(None) };
+    static USER_CLUSTERS: RefCell<Option<UserClusterMap>> = const { RefCell::new(None) };
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// Words too common to describe a cluster
+const DESCRIPTION_STOP_WORDS: &[&str] = &[
+    "about", "after", "also", "been", "before", "being", "could", "does", "each", "from",
+    "have", "into", "just", "like", "more", "most", "much", "only", "other", "over",
+    "should", "some", "such", "than", "that", "their", "them", "then", "there", "these",
+    "they", "this", "those", "very", "were", "what", "when", "where", "which", "while",
+    "will", "with", "would", "your",
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
 pub struct MemoryCluster {
     pub id: String,
     pub name: String,
@@ -25,7 +46,7 @@ pub struct MemoryCluster {
     pub cluster_type: ClusterType,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType, PartialEq)]
 pub enum ClusterType {
     Automatic,     // AI-generated clusters
     Manual,        // User-created clusters
@@ -34,27 +55,61 @@ pub enum ClusterType {
     Semantic,      // Semantic similarity clusters
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct MemoryCategory {
-    pub id: String,
-    pub name: String,
-    pub description: String,
-    pub keywords: Vec<String>,
-    pub confidence_threshold: f32,
-    pub memory_count: usize,
-    pub parent_category: Option<String>,
-    pub subcategories: Vec<String>,
-}
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ClusteringResult {
     pub clusters: Vec<MemoryCluster>,
     pub unclustered_memories: Vec<String>,
     pub clustering_score: f32,
     pub method_used: ClusteringMethod,
+    pub dendrogram: Option<Dendrogram>, // Hierarchical clustering only
+}
+
+// One agglomeration step. Leaves are nodes 0..n and the i-th merge creates
+// node n + i, as in SciPy's linkage matrix.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct DendrogramMerge {
+    pub left: usize,
+    pub right: usize,
+    pub similarity: f32, // Average cosine similarity between the two merged clusters
+    pub size: usize,     // Leaves under the new node
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Dendrogram {
+    pub leaves: Vec<String>, // Memory id of each leaf node
+    pub merges: Vec<DendrogramMerge>,
+}
+
+impl Dendrogram {
+    // Cluster label per leaf after applying every merge at or above `threshold`.
+    // Average linkage never increases similarity, so this is a prefix of merges.
+    pub fn cut(&self, threshold: f32) -> Vec<usize> {
+        let leaf_count = self.leaves.len();
+        let mut parent: Vec<usize> = (0..leaf_count + self.merges.len()).collect();
+
+        for (i, merge) in self.merges.iter().enumerate() {
+            if merge.similarity < threshold {
+                break;
+            }
+            let node = leaf_count + i;
+            parent[merge.left] = node;
+            parent[merge.right] = node;
+        }
+
+        let roots: Vec<usize> = (0..leaf_count)
+            .map(|leaf| {
+                let mut node = leaf;
+                while parent[node] != node {
+                    node = parent[node];
+                }
+                node
+            })
+            .collect();
+        canonical_labels(&roots)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType, PartialEq)]
 pub enum ClusteringMethod {
     KMeans,
     DBSCAN,
@@ -64,109 +119,229 @@ pub enum ClusteringMethod {
     Temporal,
 }
 
+impl ClusteringMethod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "kmeans" => Some(ClusteringMethod::KMeans),
+            "dbscan" => Some(ClusteringMethod::DBSCAN),
+            "hierarchical" => Some(ClusteringMethod::Hierarchical),
+            "contentbased" | "content" => Some(ClusteringMethod::ContentBased),
+            "tagbased" | "tags" | "tag" => Some(ClusteringMethod::TagBased),
+            "temporal" | "time" => Some(ClusteringMethod::Temporal),
+            _ => None,
+        }
+    }
+}
+
+// The outcome of a user's last clustering run
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType, Default)]
+pub struct UserClusters {
+    pub cluster_ids: Vec<String>,
+    pub unclustered_memories: Vec<String>,
+    pub method: Option<ClusteringMethod>,
+    pub clustering_score: f32,
+    pub computed_at: u64,
+}
+
+// Parameters for `ClusteringEngine::compute_clusters`; unused ones are ignored
+#[derive(Clone, Debug, Default)]
+pub struct ClusteringOptions {
+    pub k: Option<usize>,
+    pub time_period: Option<TimePeriod>,
+    pub eps: Option<f32>,                  // DBSCAN neighbourhood radius in cosine distance
+    pub min_points: Option<usize>,         // DBSCAN core point threshold, the point included
+    pub similarity_threshold: Option<f32>, // Hierarchical dendrogram cut
+    pub seed: Option<u64>,                 // Makes randomized steps reproducible
+}
+
+/// Default DBSCAN radius: neighbours have cosine similarity of at least 0.75
+pub const DEFAULT_DBSCAN_EPS: f32 = 0.25;
+pub const DEFAULT_DBSCAN_MIN_POINTS: usize = 3;
+/// Default similarity at which the dendrogram is cut
+pub const DEFAULT_HIERARCHICAL_THRESHOLD: f32 = 0.75;
+/// Most memories DBSCAN and hierarchical clustering accept; both compare every
+/// pair of points within a single message
+pub const MAX_PAIRWISE_CLUSTERING_POINTS: usize = 500;
+/// Largest k tried when k-means chooses k itself
+pub const MAX_AUTO_K: usize = 10;
+/// Largest k a caller may request
+pub const MAX_K: usize = 50;
+// k-means is fitted on at most this many points; the rest are then assigned
+// to the nearest fitted centroid
+const KMEANS_SAMPLE_SIZE: usize = 500;
+const MAX_KMEANS_ITERATIONS: usize = 100;
+// Points scored per silhouette evaluation; bounds the O(n^2) cost
+const SILHOUETTE_SAMPLE_SIZE: usize = 200;
+
+impl Storable for MemoryCluster {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for UserClusters {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
 pub struct ClusteringEngine;
 
 impl ClusteringEngine {
-    // Initialize predefined categories
-    pub fn init_categories() {
-        let predefined_categories = vec![
-            MemoryCategory {
-                id: "tech".to_string(),
-                name: "Technology".to_string(),
-                description: "Technical information, programming, software, and tech concepts".to_string(),
-                keywords: vec![
-                    "programming".to_string(), "software".to_string(), "technology".to_string(),
-                    "code".to_string(), "algorithm".to_string(), "database".to_string(),
-                    "API".to_string(), "framework".to_string(), "library".to_string(),
-                ],
-                confidence_threshold: 0.7,
-                memory_count: 0,
-                parent_category: None,
-                subcategories: vec!["programming".to_string(), "infrastructure".to_string()],
-            },
-            MemoryCategory {
-                id: "business".to_string(),
-                name: "Business".to_string(),
-                description: "Business concepts, strategy, management, and professional topics".to_string(),
-                keywords: vec![
-                    "business".to_string(), "strategy".to_string(), "management".to_string(),
-                    "marketing".to_string(), "finance".to_string(), "company".to_string(),
-                    "revenue".to_string(), "investment".to_string(), "market".to_string(),
-                ],
-                confidence_threshold: 0.7,
-                memory_count: 0,
-                parent_category: None,
-                subcategories: vec!["strategy".to_string(), "finance".to_string()],
-            },
-            MemoryCategory {
-                id: "personal".to_string(),
-                name: "Personal".to_string(),
-                description: "Personal notes, thoughts, experiences, and private information".to_string(),
-                keywords: vec![
-                    "personal".to_string(), "private".to_string(), "diary".to_string(),
-                    "thought".to_string(), "idea".to_string(), "reflection".to_string(),
-                    "experience".to_string(), "memory".to_string(), "feeling".to_string(),
-                ],
-                confidence_threshold: 0.6,
-                memory_count: 0,
-                parent_category: None,
-                subcategories: vec!["thoughts".to_string(), "experiences".to_string()],
-            },
-            MemoryCategory {
-                id: "reference".to_string(),
-                name: "Reference".to_string(),
-                description: "Reference materials, documentation, and factual information".to_string(),
-                keywords: vec![
-                    "reference".to_string(), "documentation".to_string(), "guide".to_string(),
-                    "manual".to_string(), "fact".to_string(), "definition".to_string(),
-                    "instruction".to_string(), "tutorial".to_string(), "how-to".to_string(),
-                ],
-                confidence_threshold: 0.7,
-                memory_count: 0,
-                parent_category: None,
-                subcategories: vec!["docs".to_string(), "tutorials".to_string()],
-            },
-        ];
+    // Called from init and post_upgrade
+    pub fn init() {
+        CLUSTERS.with(|c| {
+            *c.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_CLUSTERS)
+            ));
+        });
+
+        USER_CLUSTERS.with(|uc| {
+            *uc.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_USER_CLUSTERS)
+            ));
+        });
+    }
+
+    // Cluster all of a user's memories and store the result in place of their
+    // previous automatic clusters
+    pub fn compute_clusters(
+        user_id: Principal,
+        method: ClusteringMethod,
+        options: ClusteringOptions,
+    ) -> Result<ClusteringResult, String> {
+        let memory_ids = crate::storage::user_memory_ids(user_id);
+        if memory_ids.is_empty() {
+            return Err("No memories to cluster".to_string());
+        }
+
+        let result = match method {
+            ClusteringMethod::KMeans => {
+                Self::cluster_memories_kmeans(user_id, memory_ids, options.k, options.seed.unwrap_or(0))?
+            }
+            ClusteringMethod::ContentBased => Self::cluster_by_content(user_id, memory_ids)?,
+            ClusteringMethod::TagBased => Self::cluster_by_tags(user_id, memory_ids)?,
+            ClusteringMethod::Temporal => {
+                Self::cluster_by_time(user_id, memory_ids, options.time_period.unwrap_or(TimePeriod::Week))?
+            }
+            ClusteringMethod::DBSCAN => Self::cluster_memories_dbscan(
+                user_id,
+                memory_ids,
+                options.eps.unwrap_or(DEFAULT_DBSCAN_EPS),
+                options.min_points.unwrap_or(DEFAULT_DBSCAN_MIN_POINTS),
+                options.seed.unwrap_or(0),
+            )?,
+            ClusteringMethod::Hierarchical => Self::cluster_memories_hierarchical(
+                user_id,
+                memory_ids,
+                options.similarity_threshold.unwrap_or(DEFAULT_HIERARCHICAL_THRESHOLD),
+            )?,
+        };
+
+        Self::store_clustering_result(user_id, &result);
+        Ok(result)
+    }
+
+    fn store_clustering_result(user_id: Principal, result: &ClusteringResult) {
+        let previous = Self::get_user_clustering(user_id).unwrap_or_default();
+
+        // Manual clusters are curated by the user and survive recomputation
+        let mut cluster_ids = Vec::new();
+        CLUSTERS.with(|c| {
+            if let Some(ref mut clusters) = *c.borrow_mut() {
+                for id in &previous.cluster_ids {
+                    match clusters.get(id) {
+                        Some(cluster) if cluster.cluster_type == ClusterType::Manual => cluster_ids.push(id.clone()),
+                        Some(_) => {
+                            clusters.remove(id);
+                        }
+                        None => {}
+                    }
+                }
+                for cluster in &result.clusters {
+                    clusters.insert(cluster.id.clone(), cluster.clone());
+                    if !cluster_ids.contains(&cluster.id) {
+                        cluster_ids.push(cluster.id.clone());
+                    }
+                }
+            }
+        });
 
-        CATEGORIES.with(|c| {
-            let mut categories = c.borrow_mut();
-            for category in predefined_categories {
-                categories.insert(category.id.clone(), category);
+        let user_clusters = UserClusters {
+            cluster_ids,
+            unclustered_memories: result.unclustered_memories.clone(),
+            method: Some(result.method_used.clone()),
+            clustering_score: result.clustering_score,
+            computed_at: ic_cdk::api::time(),
+        };
+        USER_CLUSTERS.with(|uc| {
+            if let Some(ref mut user_clusters_map) = *uc.borrow_mut() {
+                user_clusters_map.insert(user_id, user_clusters);
             }
         });
 
-        ic_cdk::println!("Initialized {} predefined categories", CATEGORIES.with(|c| c.borrow().len()));
+        ic_cdk::println!(
+            "Stored {} {:?} clusters for user {}",
+            result.clusters.len(),
+            result.method_used,
+            user_id
+        );
+    }
+
+    pub fn get_user_clustering(user_id: Principal) -> Option<UserClusters> {
+        USER_CLUSTERS.with(|uc| uc.borrow().as_ref().and_then(|map| map.get(&user_id)))
     }
 
     // Automatic clustering using K-means algorithm
+    // Spherical k-means. Without `k`, the k with the best silhouette score is used.
     pub fn cluster_memories_kmeans(
         user_id: Principal,
         memory_ids: Vec<String>,
-        k: usize,
+        k: Option<usize>,
+        seed: u64,
     ) -> Result<ClusteringResult, String> {
-        if memory_ids.len() < k {
+        if k.is_some_and(|k| memory_ids.len() < k) {
             return Err("Not enough memories for clustering".to_string());
         }
 
-        // Get embeddings for all memories
-        let mut embeddings = Vec::new();
-        let mut valid_memory_ids = Vec::new();
+        let (valid_memory_ids, embeddings, unclustered) = Self::collect_embeddings(user_id, memory_ids);
 
-        for memory_id in memory_ids {
-            if let Ok(Some(memory)) = crate::storage::get_memory(&memory_id) {
-                if !memory.embedding.is_empty() {
-                    embeddings.push(memory.embedding);
-                    valid_memory_ids.push(memory_id);
-                }
-            }
+        if embeddings.is_empty() || k.is_some_and(|k| embeddings.len() < k) {
+            return Err("Not enough memories with embeddings for clustering".to_string());
         }
 
-        if embeddings.is_empty() {
-            return Err("No memories with embeddings found".to_string());
+        if k.is_some_and(|k| k > MAX_K) {
+            return Err(format!("k must be at most {}", MAX_K));
         }
 
-        // Simple K-means implementation
-        let (cluster_assignments, centroids) = Self::kmeans(&embeddings, k)?;
+        let sample: Vec<Vec<f32>> = Self::sample_indices(embeddings.len(), KMEANS_SAMPLE_SIZE, seed)
+            .into_iter()
+            .map(|i| embeddings[i].clone())
+            .collect();
+        let (_, centroids, clustering_score) = match k {
+            Some(k) => {
+                let (assignments, centroids) = Self::kmeans(&sample, k, seed)?;
+                let score = Self::calculate_clustering_score(&sample, &assignments);
+                (assignments, centroids, score)
+            }
+            None => Self::select_k(&sample, seed)?,
+        };
+        let cluster_assignments: Vec<usize> = embeddings
+            .iter()
+            .map(|embedding| Self::nearest_centroid(&normalize(embedding), &centroids))
+            .collect();
         
         // Create clusters
         let mut clusters = Vec::new();
@@ -179,10 +354,11 @@ impl ClusteringEngine {
                 .collect();
 
             if !cluster_memory_ids.is_empty() {
+                let terms = Self::cluster_terms(&cluster_memory_ids);
                 let cluster = MemoryCluster {
-                    id: format!("cluster_{}_{}", user_id.to_string(), i),
-                    name: format!("Cluster {}", i + 1),
-                    description: Self::generate_cluster_description(&cluster_memory_ids),
+                    id: format!("cluster_{user_id}_{i}"),
+                    name: Self::cluster_name(&terms, i),
+                    description: Self::generate_cluster_description(&cluster_memory_ids, &terms),
                     memory_ids: cluster_memory_ids,
                     centroid: centroid.clone(),
                     tags: Self::extract_cluster_tags(&valid_memory_ids, &cluster_assignments, i),
@@ -195,40 +371,401 @@ impl ClusteringEngine {
             }
         }
 
-        // Calculate clustering score
-        let clustering_score = Self::calculate_clustering_score(&embeddings, &cluster_assignments, &centroids);
-
         Ok(ClusteringResult {
             clusters,
-            unclustered_memories: Vec::new(),
+            unclustered_memories: unclustered,
             clustering_score,
             method_used: ClusteringMethod::KMeans,
+            dendrogram: None,
         })
     }
 
-    // Content-based clustering using categories
-    pub fn cluster_by_content(
+    // Density-based clustering over cosine distance. Memories in no dense
+    // region are returned as unclustered.
+    pub fn cluster_memories_dbscan(
         user_id: Principal,
         memory_ids: Vec<String>,
+        eps: f32,
+        min_points: usize,
+        seed: u64,
     ) -> Result<ClusteringResult, String> {
-        let mut category_clusters: HashMap<String, Vec<String>> = HashMap::new();
-        let mut unclustered = Vec::new();
+        let (valid_memory_ids, embeddings, mut unclustered) = Self::collect_embeddings(user_id, memory_ids);
+        if embeddings.is_empty() {
+            return Err("No memories with embeddings found".to_string());
+        }
+        Self::check_pairwise_limit("DBSCAN", embeddings.len())?;
+
+        let assignments = Self::dbscan(&embeddings, eps, min_points, seed);
+        unclustered.extend(
+            assignments
+                .iter()
+                .zip(&valid_memory_ids)
+                .filter(|(label, _)| label.is_none())
+                .map(|(_, id)| id.clone()),
+        );
+
+        let clusters = Self::build_clusters(user_id, "dbscan", &valid_memory_ids, &embeddings, &assignments);
+        Ok(ClusteringResult {
+            clustering_score: Self::assigned_clustering_score(&embeddings, &assignments),
+            clusters,
+            unclustered_memories: unclustered,
+            method_used: ClusteringMethod::DBSCAN,
+            dendrogram: None,
+        })
+    }
+
+    // Agglomerative clustering with average linkage, cut at `similarity_threshold`.
+    // Memories left on their own are returned as unclustered.
+    pub fn cluster_memories_hierarchical(
+        user_id: Principal,
+        memory_ids: Vec<String>,
+        similarity_threshold: f32,
+    ) -> Result<ClusteringResult, String> {
+        let (valid_memory_ids, embeddings, mut unclustered) = Self::collect_embeddings(user_id, memory_ids);
+        if embeddings.is_empty() {
+            return Err("No memories with embeddings found".to_string());
+        }
+        Self::check_pairwise_limit("Hierarchical clustering", embeddings.len())?;
 
+        let dendrogram = Dendrogram {
+            merges: Self::agglomerate(&embeddings),
+            leaves: valid_memory_ids.clone(),
+        };
+        let labels = dendrogram.cut(similarity_threshold);
+
+        let mut sizes: HashMap<usize, usize> = HashMap::new();
+        for label in &labels {
+            *sizes.entry(*label).or_default() += 1;
+        }
+        let assignments: Vec<Option<usize>> = labels
+            .iter()
+            .map(|label| if sizes[label] > 1 { Some(*label) } else { None })
+            .collect();
+        unclustered.extend(
+            assignments
+                .iter()
+                .zip(&valid_memory_ids)
+                .filter(|(label, _)| label.is_none())
+                .map(|(_, id)| id.clone()),
+        );
+
+        let clusters = Self::build_clusters(user_id, "hierarchical", &valid_memory_ids, &embeddings, &assignments);
+        Ok(ClusteringResult {
+            clustering_score: Self::assigned_clustering_score(&embeddings, &assignments),
+            clusters,
+            unclustered_memories: unclustered,
+            method_used: ClusteringMethod::Hierarchical,
+            dendrogram: Some(dendrogram),
+        })
+    }
+
+    fn check_pairwise_limit(method: &str, points: usize) -> Result<(), String> {
+        if points > MAX_PAIRWISE_CLUSTERING_POINTS {
+            return Err(format!(
+                "{} supports at most {} memories with embeddings ({} found); use kmeans instead",
+                method, MAX_PAIRWISE_CLUSTERING_POINTS, points
+            ));
+        }
+        Ok(())
+    }
+
+    // Embeddings comparable with the user's current model, their memory ids,
+    // and the ids of memories that cannot take part in vector clustering
+    fn collect_embeddings(user_id: Principal, memory_ids: Vec<String>) -> (Vec<String>, Vec<Vec<f32>>, Vec<String>) {
+        let mut embeddings = Vec::new();
+        let mut valid_memory_ids = Vec::new();
+
+        // Vectors from different embedding models are not comparable
+        let model = crate::embedding::embedding_model_for_user(user_id);
+        let mut unclustered = Vec::new();
         for memory_id in memory_ids {
             if let Ok(Some(memory)) = crate::storage::get_memory(&memory_id) {
-                if let Some(category_id) = Self::classify_memory(&memory) {
-                    category_clusters.entry(category_id).or_insert_with(Vec::new).push(memory_id);
+                let comparable = model.is_none() || memory.embedding_model == model;
+                let same_dimension = embeddings.first().is_none_or(|first: &Vec<f32>| first.len() == memory.embedding.len());
+                if !memory.embedding.is_empty() && comparable && same_dimension {
+                    embeddings.push(memory.embedding);
+                    valid_memory_ids.push(memory_id);
                 } else {
                     unclustered.push(memory_id);
                 }
             }
         }
 
+        (valid_memory_ids, embeddings, unclustered)
+    }
+
+    // One cluster per label, in label order
+    fn build_clusters(
+        user_id: Principal,
+        prefix: &str,
+        memory_ids: &[String],
+        embeddings: &[Vec<f32>],
+        assignments: &[Option<usize>],
+    ) -> Vec<MemoryCluster> {
+        let cluster_count = assignments.iter().flatten().max().map(|max| max + 1).unwrap_or(0);
+        let now = ic_cdk::api::time();
+
+        (0..cluster_count)
+            .filter_map(|label| {
+                let members: Vec<usize> = (0..memory_ids.len()).filter(|&i| assignments[i] == Some(label)).collect();
+                if members.is_empty() {
+                    return None;
+                }
+                let cluster_memory_ids: Vec<String> = members.iter().map(|&i| memory_ids[i].clone()).collect();
+                let points: Vec<&Vec<f32>> = members.iter().map(|&i| &embeddings[i]).collect();
+                let terms = Self::cluster_terms(&cluster_memory_ids);
+                let tags = cluster_memory_ids
+                    .iter()
+                    .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+                    .flat_map(|memory| memory.tags)
+                    .collect();
+
+                Some(MemoryCluster {
+                    id: format!("{}_{}_{}", prefix, user_id.to_text(), label),
+                    name: Self::cluster_name(&terms, label),
+                    description: Self::generate_cluster_description(&cluster_memory_ids, &terms),
+                    memory_ids: cluster_memory_ids,
+                    centroid: Self::calculate_centroid(&points),
+                    tags,
+                    created_at: now,
+                    updated_at: now,
+                    user_id,
+                    cluster_type: ClusterType::Semantic,
+                })
+            })
+            .collect()
+    }
+
+    // Score over the points that belong to a cluster
+    fn assigned_clustering_score(embeddings: &[Vec<f32>], assignments: &[Option<usize>]) -> f32 {
+        let (points, labels): (Vec<Vec<f32>>, Vec<usize>) = embeddings
+            .iter()
+            .zip(assignments)
+            .filter_map(|(embedding, label)| label.map(|label| (embedding.clone(), label)))
+            .unzip();
+        Self::calculate_clustering_score(&points, &labels)
+    }
+
+    // Add a newly stored memory to the nearest of the user's k-means clusters,
+    // moving that cluster's centroid towards it. Returns the cluster id.
+    pub fn assign_new_memory(memory: &Memory) -> Option<String> {
+        if memory.embedding.is_empty() || memory.embedding_model.is_none() {
+            return None;
+        }
+        if memory.embedding_model != crate::embedding::embedding_model_for_user(memory.user_id) {
+            return None;
+        }
+
+        let point = normalize(&memory.embedding);
+        let mut cluster = Self::get_user_clusters(memory.user_id)
+            .into_iter()
+            .filter(|cluster| cluster.cluster_type == ClusterType::Automatic)
+            .filter(|cluster| cluster.centroid.len() == point.len() && !cluster.memory_ids.contains(&memory.id))
+            .fold(None, |best: Option<(MemoryCluster, f32)>, cluster| {
+                let similarity = dot(&point, &cluster.centroid);
+                match best {
+                    Some((_, best_similarity)) if best_similarity >= similarity => best,
+                    _ => Some((cluster, similarity)),
+                }
+            })
+            .map(|(cluster, _)| cluster)?;
+
+        cluster.centroid = Self::fold_into_centroid(&cluster.centroid, cluster.memory_ids.len(), &point);
+        cluster.memory_ids.push(memory.id.clone());
+        cluster.tags.extend(memory.tags.iter().cloned());
+        cluster.updated_at = ic_cdk::api::time();
+        let cluster_id = cluster.id.clone();
+
+        CLUSTERS.with(|c| {
+            if let Some(ref mut clusters) = *c.borrow_mut() {
+                clusters.insert(cluster_id.clone(), cluster);
+            }
+        });
+        Some(cluster_id)
+    }
+
+    // Unit centroid after adding one unit vector to a cluster of `member_count`
+    fn fold_into_centroid(centroid: &[f32], member_count: usize, point: &[f32]) -> Vec<f32> {
+        let weight = member_count as f32;
+        let sum: Vec<f32> = centroid.iter().zip(point).map(|(c, p)| c * weight + p).collect();
+        normalize(&sum)
+    }
+
+    // `size` distinct indices below `n` chosen by `seed`, in ascending order;
+    // all of them when there are no more than `size`
+    fn sample_indices(n: usize, size: usize, seed: u64) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..n).collect();
+        if n <= size {
+            return indices;
+        }
+
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        for i in 0..size {
+            let j = i + (rng.next_u64() % (n - i) as u64) as usize;
+            indices.swap(i, j);
+        }
+        indices.truncate(size);
+        indices.sort_unstable();
+        indices
+    }
+
+    // Try k = 2..=MAX_AUTO_K and keep the clustering with the best silhouette score
+    fn select_k(embeddings: &[Vec<f32>], seed: u64) -> Result<KMeansFit, String> {
+        let max_k = MAX_AUTO_K.min(embeddings.len().saturating_sub(1));
+        if max_k < 2 {
+            let (assignments, centroids) = Self::kmeans(embeddings, 1, seed)?;
+            return Ok((assignments, centroids, 0.0));
+        }
+
+        let mut best: Option<KMeansFit> = None;
+        for k in 2..=max_k {
+            let (assignments, centroids) = Self::kmeans(embeddings, k, seed)?;
+            let score = Self::calculate_clustering_score(embeddings, &assignments);
+            if best.as_ref().is_none_or(|(_, _, best_score)| score > *best_score) {
+                best = Some((assignments, centroids, score));
+            }
+        }
+        best.ok_or_else(|| "No clustering found".to_string())
+    }
+
+    // DBSCAN with cosine distance. Points are visited in an order shuffled by
+    // `seed`, which decides the cluster of border points reachable from two
+    // clusters; labels are then numbered by first appearance in input order.
+    fn dbscan(embeddings: &[Vec<f32>], eps: f32, min_points: usize, seed: u64) -> Vec<Option<usize>> {
+        let normalized: Vec<Vec<f32>> = embeddings.iter().map(|e| normalize(e)).collect();
+        let n = normalized.len();
+        let neighbours: Vec<Vec<usize>> = (0..n)
+            .map(|i| {
+                (0..n)
+                    .filter(|&j| 1.0 - dot(&normalized[i], &normalized[j]) <= eps)
+                    .collect()
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..n).collect();
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        for i in (1..n).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+
+        let mut labels: Vec<Option<usize>> = vec![None; n];
+        let mut visited = vec![false; n];
+        let mut next_label = 0;
+        for &point in &order {
+            if visited[point] {
+                continue;
+            }
+            visited[point] = true;
+            if neighbours[point].len() < min_points {
+                continue; // Noise unless a later core point reaches it
+            }
+
+            let label = next_label;
+            next_label += 1;
+            labels[point] = Some(label);
+            let mut queue: Vec<usize> = neighbours[point].clone();
+            while let Some(q) = queue.pop() {
+                if labels[q].is_none() {
+                    labels[q] = Some(label);
+                }
+                if !visited[q] {
+                    visited[q] = true;
+                    if neighbours[q].len() >= min_points {
+                        queue.extend(neighbours[q].iter().copied());
+                    }
+                }
+            }
+        }
+
+        let mut renumbered: HashMap<usize, usize> = HashMap::new();
+        labels
+            .iter()
+            .map(|label| {
+                label.map(|label| {
+                    let next = renumbered.len();
+                    *renumbered.entry(label).or_insert(next)
+                })
+            })
+            .collect()
+    }
+
+    // Average-linkage agglomeration over cosine similarity. Ties go to the
+    // pair with the lowest indices, so the dendrogram depends only on the input.
+    fn agglomerate(embeddings: &[Vec<f32>]) -> Vec<DendrogramMerge> {
+        let normalized: Vec<Vec<f32>> = embeddings.iter().map(|e| normalize(e)).collect();
+        let n = normalized.len();
+        let mut similarity: Vec<Vec<f32>> = (0..n)
+            .map(|i| (0..n).map(|j| dot(&normalized[i], &normalized[j])).collect())
+            .collect();
+        // Active clusters: (node id, size)
+        let mut active: Vec<Option<(usize, usize)>> = (0..n).map(|i| Some((i, 1))).collect();
+        let mut merges = Vec::with_capacity(n.saturating_sub(1));
+
+        for step in 0..n.saturating_sub(1) {
+            let mut best: Option<(usize, usize, f32)> = None;
+            for i in 0..n {
+                if active[i].is_none() {
+                    continue;
+                }
+                for j in (i + 1)..n {
+                    if active[j].is_none() {
+                        continue;
+                    }
+                    if best.is_none_or(|(_, _, s)| similarity[i][j] > s) {
+                        best = Some((i, j, similarity[i][j]));
+                    }
+                }
+            }
+            let Some((i, j, s)) = best else { break };
+            let (left, left_size) = active[i].unwrap();
+            let (right, right_size) = active[j].unwrap();
+
+            // Lance-Williams update for average linkage; slot i holds the new cluster
+            for k in 0..n {
+                if k == i || k == j || active[k].is_none() {
+                    continue;
+                }
+                let merged = (similarity[i][k] * left_size as f32 + similarity[j][k] * right_size as f32)
+                    / (left_size + right_size) as f32;
+                similarity[i][k] = merged;
+                similarity[k][i] = merged;
+            }
+            active[i] = Some((n + step, left_size + right_size));
+            active[j] = None;
+            merges.push(DendrogramMerge {
+                left: left.min(right),
+                right: left.max(right),
+                similarity: s,
+                size: left_size + right_size,
+            });
+        }
+
+        merges
+    }
+
+    // Content-based clustering: one cluster per category of the user's taxonomy
+    pub fn cluster_by_content(
+        user_id: Principal,
+        memory_ids: Vec<String>,
+    ) -> Result<ClusteringResult, String> {
+        let mut category_clusters: HashMap<String, Vec<String>> = HashMap::new();
+        let mut unclustered = Vec::new();
+
+        // Loading the taxonomy classifies any memories stored before it existed
+        let categories = crate::categories::get_categories(user_id);
+        for memory_id in memory_ids {
+            match crate::categories::assigned_category(&memory_id) {
+                Some(category_id) => category_clusters.entry(category_id).or_default().push(memory_id),
+                None => unclustered.push(memory_id),
+            }
+        }
+
         let mut clusters = Vec::new();
         for (category_id, memory_ids) in category_clusters {
-            if let Some(category) = CATEGORIES.with(|c| c.borrow().get(&category_id).cloned()) {
+            if let Some(category) = categories.iter().find(|category| category.id == category_id).cloned() {
                 let cluster = MemoryCluster {
-                    id: format!("content_{}_{}", user_id.to_string(), category_id),
+                    id: format!("content_{user_id}_{category_id}"),
                     name: category.name.clone(),
                     description: category.description.clone(),
                     memory_ids: memory_ids.clone(),
@@ -248,6 +785,7 @@ impl ClusteringEngine {
             unclustered_memories: unclustered,
             clustering_score: 0.8, // Fixed score for content-based clustering
             method_used: ClusteringMethod::ContentBased,
+            dendrogram: None,
         })
     }
 
@@ -266,7 +804,7 @@ impl ClusteringEngine {
                 } else {
                     // Use the most common tag for this memory
                     let primary_tag = memory.tags[0].clone();
-                    tag_clusters.entry(primary_tag).or_insert_with(Vec::new).push(memory_id);
+                    tag_clusters.entry(primary_tag).or_default().push(memory_id);
                 }
             }
         }
@@ -274,7 +812,7 @@ impl ClusteringEngine {
         let mut clusters = Vec::new();
         for (tag, memory_ids) in tag_clusters {
             let cluster = MemoryCluster {
-                id: format!("tag_{}_{}", user_id.to_string(), tag),
+                id: format!("tag_{user_id}_{tag}"),
                 name: format!("#{}", tag),
                 description: format!("Memories tagged with '{}'", tag),
                 memory_ids: memory_ids.clone(),
@@ -293,6 +831,7 @@ impl ClusteringEngine {
             unclustered_memories: unclustered,
             clustering_score: 0.9, // High score for tag-based clustering
             method_used: ClusteringMethod::TagBased,
+            dendrogram: None,
         })
     }
 
@@ -307,16 +846,16 @@ impl ClusteringEngine {
         for memory_id in memory_ids {
             if let Ok(Some(memory)) = crate::storage::get_memory(&memory_id) {
                 let time_key = Self::get_time_cluster_key(memory.created_at, &time_period);
-                time_clusters.entry(time_key).or_insert_with(Vec::new).push(memory_id);
+                time_clusters.entry(time_key).or_default().push(memory_id);
             }
         }
 
         let mut clusters = Vec::new();
         for (time_key, memory_ids) in time_clusters {
             let cluster = MemoryCluster {
-                id: format!("time_{}_{}", user_id.to_string(), time_key),
+                id: format!("time_{user_id}_{time_key}"),
                 name: Self::format_time_cluster_name(&time_key, &time_period),
-                description: format!("Memories from {}", time_key),
+                description: Self::generate_cluster_description(&memory_ids, &Self::cluster_terms(&memory_ids)),
                 memory_ids: memory_ids.clone(),
                 centroid: Self::calculate_content_centroid(&memory_ids),
                 tags: HashSet::new(),
@@ -333,6 +872,7 @@ impl ClusteringEngine {
             unclustered_memories: Vec::new(),
             clustering_score: 1.0, // Perfect score for temporal clustering
             method_used: ClusteringMethod::Temporal,
+            dendrogram: None,
         })
     }
 
@@ -342,14 +882,20 @@ impl ClusteringEngine {
         let user_id = cluster.user_id;
 
         CLUSTERS.with(|c| {
-            c.borrow_mut().insert(cluster_id.clone(), cluster);
-        });
+            c.borrow_mut()
+                .as_mut()
+                .ok_or("Cluster storage not initialized")?
+                .insert(cluster_id.clone(), cluster);
+            Ok::<(), String>(())
+        })?;
 
         USER_CLUSTERS.with(|uc| {
-            let mut user_clusters = uc.borrow_mut();
-            let clusters = user_clusters.entry(user_id).or_insert_with(Vec::new);
-            if !clusters.contains(&cluster_id) {
-                clusters.push(cluster_id);
+            if let Some(ref mut user_clusters_map) = *uc.borrow_mut() {
+                let mut user_clusters = user_clusters_map.get(&user_id).unwrap_or_default();
+                if !user_clusters.cluster_ids.contains(&cluster_id) {
+                    user_clusters.cluster_ids.push(cluster_id);
+                    user_clusters_map.insert(user_id, user_clusters);
+                }
             }
         });
 
@@ -358,110 +904,280 @@ impl ClusteringEngine {
 
     // Get user clusters
     pub fn get_user_clusters(user_id: Principal) -> Vec<MemoryCluster> {
+        let cluster_ids = Self::get_user_clustering(user_id)
+            .map(|user_clusters| user_clusters.cluster_ids)
+            .unwrap_or_default();
+
+        CLUSTERS.with(|c| {
+            c.borrow()
+                .as_ref()
+                .map(|clusters| cluster_ids.iter().filter_map(|id| clusters.get(id)).collect())
+                .unwrap_or_default()
+        })
+    }
+
+    // User-curated collections: Manual clusters stored next to the automatic
+    // ones, so they survive recomputation and show up in `get_user_clusters`
+
+    pub fn create_collection(
+        user_id: Principal,
+        name: String,
+        description: String,
+        memory_ids: Vec<String>,
+    ) -> crate::errors::Result<MemoryCluster> {
+        let memory_ids = Self::owned_memory_ids(user_id, memory_ids)?;
+        let now = ic_cdk::api::time();
+        let collection = MemoryCluster {
+            id: format!("collection_{}", crate::utils::generate_uuid()),
+            name,
+            description,
+            tags: Self::member_tags(&memory_ids),
+            memory_ids,
+            centroid: Vec::new(),
+            created_at: now,
+            updated_at: now,
+            user_id,
+            cluster_type: ClusterType::Manual,
+        };
+        Self::store_cluster(collection.clone())
+            .map_err(|e| crate::errors::OpenMemoryError::storage(e, "create_collection"))?;
+        Ok(collection)
+    }
+
+    // Any of the user's clusters, automatic or manual
+    pub fn get_user_cluster(user_id: Principal, cluster_id: &str) -> crate::errors::Result<MemoryCluster> {
+        CLUSTERS
+            .with(|c| c.borrow().as_ref().and_then(|clusters| clusters.get(&cluster_id.to_string())))
+            .filter(|cluster| cluster.user_id == user_id)
+            .ok_or_else(|| crate::errors::OpenMemoryError::not_found("cluster", cluster_id))
+    }
+
+    pub fn get_collection(user_id: Principal, collection_id: &str) -> crate::errors::Result<MemoryCluster> {
+        Self::get_user_cluster(user_id, collection_id)
+            .ok()
+            .filter(|cluster| cluster.cluster_type == ClusterType::Manual)
+            .ok_or_else(|| crate::errors::OpenMemoryError::not_found("collection", collection_id))
+    }
+
+    pub fn get_user_collections(user_id: Principal) -> Vec<MemoryCluster> {
+        Self::get_user_clusters(user_id)
+            .into_iter()
+            .filter(|cluster| cluster.cluster_type == ClusterType::Manual)
+            .collect()
+    }
+
+    pub fn update_collection(
+        user_id: Principal,
+        collection_id: &str,
+        name: Option<String>,
+        description: Option<String>,
+    ) -> crate::errors::Result<MemoryCluster> {
+        let mut collection = Self::get_collection(user_id, collection_id)?;
+        if let Some(name) = name {
+            collection.name = name;
+        }
+        if let Some(description) = description {
+            collection.description = description;
+        }
+        Ok(Self::save_cluster(collection))
+    }
+
+    pub fn delete_collection(user_id: Principal, collection_id: &str) -> crate::errors::Result<()> {
+        Self::get_collection(user_id, collection_id)?;
+
+        CLUSTERS.with(|c| {
+            if let Some(ref mut clusters) = *c.borrow_mut() {
+                clusters.remove(&collection_id.to_string());
+            }
+        });
         USER_CLUSTERS.with(|uc| {
-            let user_clusters = uc.borrow();
-            if let Some(cluster_ids) = user_clusters.get(&user_id) {
-                CLUSTERS.with(|c| {
-                    let clusters = c.borrow();
-                    cluster_ids
-                        .iter()
-                        .filter_map(|id| clusters.get(id).cloned())
-                        .collect()
-                })
-            } else {
-                Vec::new()
+            if let Some(ref mut user_clusters_map) = *uc.borrow_mut() {
+                if let Some(mut user_clusters) = user_clusters_map.get(&user_id) {
+                    user_clusters.cluster_ids.retain(|id| id != collection_id);
+                    user_clusters_map.insert(user_id, user_clusters);
+                }
             }
-        })
+        });
+        Ok(())
     }
 
-    // Classify memory into category
-    fn classify_memory(memory: &Memory) -> Option<String> {
-        CATEGORIES.with(|c| {
-            let categories = c.borrow();
-            let content_lower = memory.content.to_lowercase();
-            
-            for (category_id, category) in categories.iter() {
-                let mut score = 0.0;
-                let keyword_count = category.keywords.len() as f32;
-                
-                for keyword in &category.keywords {
-                    if content_lower.contains(&keyword.to_lowercase()) {
-                        score += 1.0 / keyword_count;
-                    }
+    pub fn add_to_collection(
+        user_id: Principal,
+        collection_id: &str,
+        memory_ids: Vec<String>,
+    ) -> crate::errors::Result<MemoryCluster> {
+        let mut collection = Self::get_collection(user_id, collection_id)?;
+        for memory_id in Self::owned_memory_ids(user_id, memory_ids)? {
+            if !collection.memory_ids.contains(&memory_id) {
+                collection.memory_ids.push(memory_id);
+            }
+        }
+        collection.tags = Self::member_tags(&collection.memory_ids);
+        Ok(Self::save_cluster(collection))
+    }
+
+    pub fn remove_from_collection(
+        user_id: Principal,
+        collection_id: &str,
+        memory_id: &str,
+    ) -> crate::errors::Result<MemoryCluster> {
+        let mut collection = Self::get_collection(user_id, collection_id)?;
+        let before = collection.memory_ids.len();
+        collection.memory_ids.retain(|id| id != memory_id);
+        if collection.memory_ids.len() == before {
+            return Err(crate::errors::OpenMemoryError::not_found("memory", memory_id));
+        }
+        collection.tags = Self::member_tags(&collection.memory_ids);
+        Ok(Self::save_cluster(collection))
+    }
+
+    // Drop a deleted memory from every cluster and collection of its owner
+    pub fn remove_memory(user_id: Principal, memory_id: &str) {
+        let Some(mut user_clusters) = Self::get_user_clustering(user_id) else { return };
+
+        for mut cluster in Self::get_user_clusters(user_id) {
+            if cluster.memory_ids.iter().any(|id| id == memory_id) {
+                cluster.memory_ids.retain(|id| id != memory_id);
+                Self::save_cluster(cluster);
+            }
+        }
+
+        let before = user_clusters.unclustered_memories.len();
+        user_clusters.unclustered_memories.retain(|id| id != memory_id);
+        if user_clusters.unclustered_memories.len() != before {
+            USER_CLUSTERS.with(|uc| {
+                if let Some(ref mut user_clusters_map) = *uc.borrow_mut() {
+                    user_clusters_map.insert(user_id, user_clusters);
                 }
-                
-                // Check tags
-                for tag in &memory.tags {
-                    if category.keywords.contains(&tag.to_lowercase()) {
-                        score += 0.5 / keyword_count;
+            });
+        }
+    }
+
+    fn save_cluster(mut cluster: MemoryCluster) -> MemoryCluster {
+        cluster.updated_at = ic_cdk::api::time();
+        CLUSTERS.with(|c| {
+            if let Some(ref mut clusters) = *c.borrow_mut() {
+                clusters.insert(cluster.id.clone(), cluster.clone());
+            }
+        });
+        cluster
+    }
+
+    // Deduplicated ids, each of which must be one of the user's memories
+    fn owned_memory_ids(user_id: Principal, memory_ids: Vec<String>) -> crate::errors::Result<Vec<String>> {
+        let mut owned: Vec<String> = Vec::with_capacity(memory_ids.len());
+        for memory_id in memory_ids {
+            match crate::storage::get_memory(&memory_id) {
+                Ok(Some(memory)) if memory.user_id == user_id => {
+                    if !owned.contains(&memory_id) {
+                        owned.push(memory_id);
                     }
                 }
-                
-                if score >= category.confidence_threshold {
-                    return Some(category_id.clone());
-                }
+                _ => return Err(crate::errors::OpenMemoryError::not_found("memory", memory_id)),
             }
-            
-            None
-        })
+        }
+        Ok(owned)
+    }
+
+    fn member_tags(memory_ids: &[String]) -> HashSet<String> {
+        memory_ids
+            .iter()
+            .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+            .flat_map(|memory| memory.tags)
+            .collect()
     }
 
     // Helper functions
-    fn kmeans(embeddings: &[Vec<f32>], k: usize) -> Result<(Vec<usize>, Vec<Vec<f32>>), String> {
-        if embeddings.is_empty() || k == 0 {
+
+    // Spherical k-means: points are normalized, assigned by cosine similarity,
+    // and centroids are renormalized means. Stops when no assignment changes.
+    fn kmeans(embeddings: &[Vec<f32>], k: usize, seed: u64) -> Result<(Vec<usize>, Vec<Vec<f32>>), String> {
+        if embeddings.is_empty() || k == 0 || k > embeddings.len() {
             return Err("Invalid input for k-means".to_string());
         }
 
-        let _dim = embeddings[0].len();
-        let mut centroids = Self::initialize_centroids(embeddings, k);
-        let mut assignments = vec![0; embeddings.len()];
-        
-        // Simple k-means with fixed iterations
-        for _ in 0..10 {
-            // Assign points to clusters
-            for (i, embedding) in embeddings.iter().enumerate() {
-                let mut best_cluster = 0;
-                let mut best_distance = f32::INFINITY;
-                
-                for (j, centroid) in centroids.iter().enumerate() {
-                    let distance = Self::euclidean_distance(embedding, centroid);
-                    if distance < best_distance {
-                        best_distance = distance;
-                        best_cluster = j;
-                    }
+        let points: Vec<Vec<f32>> = embeddings.iter().map(|e| normalize(e)).collect();
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut centroids = Self::initialize_centroids(&points, k, &mut rng);
+        let mut assignments = vec![usize::MAX; points.len()];
+
+        for _ in 0..MAX_KMEANS_ITERATIONS {
+            let mut changed = false;
+            for (i, point) in points.iter().enumerate() {
+                let nearest = Self::nearest_centroid(point, &centroids);
+                if assignments[i] != nearest {
+                    assignments[i] = nearest;
+                    changed = true;
                 }
-                
-                assignments[i] = best_cluster;
             }
-            
-            // Update centroids
-            for j in 0..k {
-                let cluster_points: Vec<&Vec<f32>> = embeddings
+            if !changed {
+                break;
+            }
+
+            for (j, centroid) in centroids.iter_mut().enumerate() {
+                let members: Vec<&Vec<f32>> = points
                     .iter()
-                    .enumerate()
-                    .filter(|(i, _)| assignments[*i] == j)
-                    .map(|(_, embedding)| embedding)
+                    .zip(&assignments)
+                    .filter(|(_, &cluster)| cluster == j)
+                    .map(|(point, _)| point)
                     .collect();
-                
-                if !cluster_points.is_empty() {
-                    centroids[j] = Self::calculate_centroid(&cluster_points);
+                // An emptied cluster keeps its centroid
+                if !members.is_empty() {
+                    *centroid = normalize(&Self::calculate_centroid(&members));
                 }
             }
         }
-        
+
         Ok((assignments, centroids))
     }
 
-    fn initialize_centroids(embeddings: &[Vec<f32>], k: usize) -> Vec<Vec<f32>> {
-        // Simple random initialization
-        let mut centroids = Vec::new();
-        let step = embeddings.len() / k;
-        
-        for i in 0..k {
-            let idx = (i * step).min(embeddings.len() - 1);
-            centroids.push(embeddings[idx].clone());
+    // Most similar centroid; ties go to the lowest index
+    fn nearest_centroid(point: &[f32], centroids: &[Vec<f32>]) -> usize {
+        let mut best_cluster = 0;
+        let mut best_similarity = f32::NEG_INFINITY;
+        for (j, centroid) in centroids.iter().enumerate() {
+            let similarity = dot(point, centroid);
+            if similarity > best_similarity {
+                best_similarity = similarity;
+                best_cluster = j;
+            }
         }
-        
+        best_cluster
+    }
+
+    // k-means++ seeding: each further centroid is drawn with probability
+    // proportional to its squared cosine distance from the nearest chosen one
+    fn initialize_centroids(points: &[Vec<f32>], k: usize, rng: &mut ChaCha20Rng) -> Vec<Vec<f32>> {
+        let n = points.len();
+        let first = (rng.next_u64() % n as u64) as usize;
+        let mut centroids = vec![points[first].clone()];
+        let mut distances: Vec<f64> = points.iter().map(|p| Self::cosine_distance(p, &centroids[0]) as f64).collect();
+
+        while centroids.len() < k {
+            let weights: Vec<f64> = distances.iter().map(|d| d.max(0.0).powi(2)).collect();
+            let total: f64 = weights.iter().sum();
+            let next = if total <= 0.0 {
+                // Every point coincides with a centroid already
+                (rng.next_u64() % n as u64) as usize
+            } else {
+                let mut target = unit_interval(rng) * total;
+                let mut chosen = n - 1;
+                for (i, weight) in weights.iter().enumerate() {
+                    if *weight > 0.0 && target < *weight {
+                        chosen = i;
+                        break;
+                    }
+                    target -= weight;
+                }
+                chosen
+            };
+
+            centroids.push(points[next].clone());
+            for (i, point) in points.iter().enumerate() {
+                distances[i] = distances[i].min(Self::cosine_distance(point, &points[next]) as f64);
+            }
+        }
+
         centroids
     }
 
@@ -487,39 +1203,119 @@ impl ClusteringEngine {
         centroid
     }
 
-    fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
-        a.iter()
-            .zip(b.iter())
-            .map(|(x, y)| (x - y).powi(2))
-            .sum::<f32>()
-            .sqrt()
+    fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+        let norm = dot(a, a).sqrt() * dot(b, b).sqrt();
+        if norm == 0.0 {
+            return 1.0;
+        }
+        1.0 - dot(a, b) / norm
     }
 
-    fn calculate_clustering_score(
-        embeddings: &[Vec<f32>], 
-        assignments: &[usize], 
-        centroids: &[Vec<f32>]
-    ) -> f32 {
-        let mut total_distance = 0.0;
+    // Mean silhouette coefficient over cosine distance, from -1 (wrong
+    // clusters) to 1 (compact, well separated). At most
+    // SILHOUETTE_SAMPLE_SIZE evenly spaced points are scored.
+    fn calculate_clustering_score(embeddings: &[Vec<f32>], assignments: &[usize]) -> f32 {
+        let points: Vec<Vec<f32>> = embeddings.iter().map(|e| normalize(e)).collect();
+        let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, cluster) in assignments.iter().enumerate() {
+            members.entry(*cluster).or_default().push(i);
+        }
+        if members.len() < 2 {
+            return 0.0;
+        }
+
+        let step = points.len().div_ceil(SILHOUETTE_SAMPLE_SIZE).max(1);
+        let mut total = 0.0;
         let mut count = 0;
-        
-        for (i, embedding) in embeddings.iter().enumerate() {
-            let cluster_id = assignments[i];
-            if cluster_id < centroids.len() {
-                total_distance += Self::euclidean_distance(embedding, &centroids[cluster_id]);
-                count += 1;
+        for i in (0..points.len()).step_by(step) {
+            let own = &members[&assignments[i]];
+            count += 1;
+            if own.len() < 2 {
+                continue; // Silhouette of a singleton is 0
+            }
+
+            let mean_distance = |indices: &Vec<usize>| {
+                let sum: f32 = indices.iter().filter(|&&j| j != i).map(|&j| 1.0 - dot(&points[i], &points[j])).sum();
+                let others = indices.iter().filter(|&&j| j != i).count();
+                sum / others as f32
+            };
+            let a = mean_distance(own);
+            let b = members
+                .iter()
+                .filter(|(cluster, _)| **cluster != assignments[i])
+                .map(|(_, indices)| mean_distance(indices))
+                .fold(f32::INFINITY, f32::min);
+            let denominator = a.max(b);
+            if denominator > 0.0 {
+                total += (b - a) / denominator;
             }
         }
-        
+
         if count > 0 {
-            1.0 / (1.0 + total_distance / count as f32)
+            total / count as f32
         } else {
             0.0
         }
     }
 
-    fn generate_cluster_description(memory_ids: &[String]) -> String {
-        format!("Automatically generated cluster with {} memories", memory_ids.len())
+    // The most frequent content words and tags across a cluster's memories
+    fn cluster_terms(memory_ids: &[String]) -> Vec<String> {
+        let documents: Vec<Vec<String>> = memory_ids
+            .iter()
+            .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+            .map(|memory| {
+                let mut terms = crate::text_index::tokenize(&memory.content);
+                terms.extend(memory.tags.iter().map(|tag| tag.to_lowercase()));
+                terms
+            })
+            .collect();
+        Self::top_terms(&documents, 3)
+    }
+
+    // Terms appearing in the most documents, ties broken alphabetically
+    fn top_terms(documents: &[Vec<String>], limit: usize) -> Vec<String> {
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        for terms in documents {
+            let unique: HashSet<&str> = terms
+                .iter()
+                .map(String::as_str)
+                .filter(|term| term.chars().count() > 3)
+                .filter(|term| !term.chars().all(|c| c.is_ascii_digit()))
+                .filter(|term| !DESCRIPTION_STOP_WORDS.contains(term))
+                .collect();
+            for term in unique {
+                *document_frequency.entry(term).or_insert(0) += 1;
+            }
+        }
+
+        let mut ranked: Vec<(&str, usize)> = document_frequency.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked.into_iter().take(limit).map(|(term, _)| term.to_string()).collect()
+    }
+
+    fn cluster_name(terms: &[String], index: usize) -> String {
+        if terms.is_empty() {
+            return format!("Cluster {}", index + 1);
+        }
+        terms
+            .iter()
+            .take(2)
+            .map(|term| {
+                let mut chars = term.chars();
+                chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+            })
+            .collect::<Vec<String>>()
+            .join(" & ")
+    }
+
+    fn generate_cluster_description(memory_ids: &[String], terms: &[String]) -> String {
+        let count = memory_ids.len();
+        let noun = if count == 1 { "memory" } else { "memories" };
+        match terms {
+            [] => format!("{} {}", count, noun),
+            [only] => format!("{} {} about {}", count, noun, only),
+            [init @ .., last] => format!("{} {} about {} and {}", count, noun, init.join(", "), last),
+        }
     }
 
     fn extract_cluster_tags(
@@ -579,6 +1375,34 @@ impl ClusteringEngine {
     }
 }
 
+// Number groups 0, 1, ... in order of first appearance
+fn canonical_labels(keys: &[usize]) -> Vec<usize> {
+    let mut renumbered: HashMap<usize, usize> = HashMap::new();
+    keys.iter()
+        .map(|key| {
+            let next = renumbered.len();
+            *renumbered.entry(*key).or_insert(next)
+        })
+        .collect()
+}
+
+pub(crate) fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Uniform sample from [0, 1)
+fn unit_interval(rng: &mut ChaCha20Rng) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TimePeriod {
     Day,
@@ -587,25 +1411,162 @@ pub enum TimePeriod {
     Year,
 }
 
+impl TimePeriod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "day" | "daily" => Some(TimePeriod::Day),
+            "week" | "weekly" => Some(TimePeriod::Week),
+            "month" | "monthly" => Some(TimePeriod::Month),
+            "year" | "yearly" => Some(TimePeriod::Year),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_euclidean_distance() {
-        let a = vec![0.0, 0.0];
-        let b = vec![3.0, 4.0];
-        let distance = ClusteringEngine::euclidean_distance(&a, &b);
-        assert!((distance - 5.0).abs() < 1e-6);
+    fn test_cosine_distance() {
+        let a = vec![1.0, 0.0];
+        let b = vec![0.0, 4.0];
+        assert!((ClusteringEngine::cosine_distance(&a, &b) - 1.0).abs() < 1e-6);
+        // Magnitude does not matter
+        assert!(ClusteringEngine::cosine_distance(&a, &[3.0, 0.0]).abs() < 1e-6);
+        assert!((ClusteringEngine::cosine_distance(&a, &[-2.0, 0.0]) - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_kmeans_is_seeded_and_converges() {
+        let embeddings: Vec<Vec<f32>> = two_groups_and_outlier().into_iter().filter(|e| e[2] < 1.0).collect();
+        let (assignments, centroids) = ClusteringEngine::kmeans(&embeddings, 2, 7).unwrap();
+        assert_eq!(ClusteringEngine::kmeans(&embeddings, 2, 7).unwrap(), (assignments.clone(), centroids.clone()));
+
+        // The two groups are separated whichever point seeds the first centroid
+        for seed in 0..5 {
+            let (labels, _) = ClusteringEngine::kmeans(&embeddings, 2, seed).unwrap();
+            assert_eq!(canonical_labels(&labels), vec![0, 1, 0, 1, 0, 1]);
+        }
+        for centroid in &centroids {
+            assert!((dot(centroid, centroid) - 1.0).abs() < 1e-5);
+        }
+        assert!(ClusteringEngine::kmeans(&embeddings, 7, 0).is_err());
+    }
+
+    #[test]
+    fn test_silhouette_selects_k() {
+        let embeddings: Vec<Vec<f32>> = two_groups_and_outlier().into_iter().filter(|e| e[2] < 1.0).collect();
+        let separated = ClusteringEngine::calculate_clustering_score(&embeddings, &[0, 1, 0, 1, 0, 1]);
+        let mixed = ClusteringEngine::calculate_clustering_score(&embeddings, &[0, 0, 1, 1, 0, 1]);
+        assert!(separated > 0.8);
+        assert!(mixed < 0.0);
+        assert_eq!(ClusteringEngine::calculate_clustering_score(&embeddings, &[0; 6]), 0.0);
+
+        let (assignments, centroids, score) = ClusteringEngine::select_k(&embeddings, 3).unwrap();
+        assert_eq!(centroids.len(), 2);
+        assert_eq!(canonical_labels(&assignments), vec![0, 1, 0, 1, 0, 1]);
+        assert!((score - separated).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sample_indices_is_seeded_and_distinct() {
+        assert_eq!(ClusteringEngine::sample_indices(3, 5, 1), vec![0, 1, 2]);
+
+        let sample = ClusteringEngine::sample_indices(1000, 50, 7);
+        assert_eq!(sample.len(), 50);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(sample.iter().all(|&i| i < 1000));
+        assert_eq!(sample, ClusteringEngine::sample_indices(1000, 50, 7));
+        assert_ne!(sample, ClusteringEngine::sample_indices(1000, 50, 8));
+    }
+
+    #[test]
+    fn test_fold_into_centroid() {
+        let centroid = ClusteringEngine::fold_into_centroid(&[1.0, 0.0], 3, &[0.0, 1.0]);
+        let expected = normalize(&[3.0, 1.0]);
+        assert!(centroid.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
     }
 
     #[test]
     fn test_calculate_centroid() {
-        let points = vec![
-            &vec![1.0, 2.0],
-            &vec![3.0, 4.0],
-        ];
+        let a = vec![1.0, 2.0];
+        let b = vec![3.0, 4.0];
+        let points = vec![&a, &b];
         let centroid = ClusteringEngine::calculate_centroid(&points);
         assert_eq!(centroid, vec![2.0, 3.0]);
     }
+
+    // Two tight groups around the x and y axes and one outlier on z
+    fn two_groups_and_outlier() -> Vec<Vec<f32>> {
+        vec![
+            vec![1.0, 0.1, 0.0],
+            vec![0.0, 1.0, 0.1],
+            vec![1.0, 0.0, 0.1],
+            vec![0.1, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+            vec![0.9, 0.1, 0.0],
+            vec![0.0, 0.9, 0.1],
+        ]
+    }
+
+    #[test]
+    fn test_dbscan_separates_dense_regions_from_noise() {
+        let embeddings = two_groups_and_outlier();
+        let labels = ClusteringEngine::dbscan(&embeddings, 0.1, 3, 42);
+        assert_eq!(labels, vec![Some(0), Some(1), Some(0), Some(1), None, Some(0), Some(1)]);
+
+        // Same input and seed, same result; the seed never changes these core-only clusters
+        assert_eq!(ClusteringEngine::dbscan(&embeddings, 0.1, 3, 42), labels);
+        assert_eq!(ClusteringEngine::dbscan(&embeddings, 0.1, 3, 7), labels);
+
+        // Too strict a density leaves everything as noise
+        assert!(ClusteringEngine::dbscan(&embeddings, 0.1, 4, 42).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_dendrogram_cut_at_similarity_threshold() {
+        let embeddings = two_groups_and_outlier();
+        let merges = ClusteringEngine::agglomerate(&embeddings);
+        assert_eq!(merges.len(), embeddings.len() - 1);
+        assert_eq!(merges.last().unwrap().size, embeddings.len());
+        assert!(merges.windows(2).all(|pair| pair[0].similarity >= pair[1].similarity));
+        assert_eq!(ClusteringEngine::agglomerate(&embeddings), merges);
+
+        let dendrogram = Dendrogram {
+            leaves: (0..embeddings.len()).map(|i| i.to_string()).collect(),
+            merges,
+        };
+        assert_eq!(dendrogram.cut(0.9), vec![0, 1, 0, 1, 2, 0, 1]);
+        assert_eq!(dendrogram.cut(-1.0), vec![0; 7]);
+        assert_eq!(dendrogram.cut(1.01), vec![0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_clustering_method_from_name() {
+        assert_eq!(ClusteringMethod::from_name("k-means"), Some(ClusteringMethod::KMeans));
+        assert_eq!(ClusteringMethod::from_name("content_based"), Some(ClusteringMethod::ContentBased));
+        assert_eq!(ClusteringMethod::from_name("TagBased"), Some(ClusteringMethod::TagBased));
+        assert_eq!(ClusteringMethod::from_name("temporal"), Some(ClusteringMethod::Temporal));
+        assert_eq!(ClusteringMethod::from_name("spectral"), None);
+    }
+
+    #[test]
+    fn test_generated_description_uses_shared_terms() {
+        let documents = vec![
+            vec!["wasm".to_string(), "canister".to_string(), "upgrade".to_string(), "this".to_string()],
+            vec!["canister".to_string(), "memory".to_string(), "this".to_string()],
+            vec!["canister".to_string(), "memory".to_string(), "2024".to_string()],
+        ];
+        let terms = ClusteringEngine::top_terms(&documents, 3);
+        assert_eq!(terms, vec!["canister", "memory", "upgrade"]);
+
+        let ids: Vec<String> = (0..3).map(|i| i.to_string()).collect();
+        assert_eq!(
+            ClusteringEngine::generate_cluster_description(&ids, &terms),
+            "3 memories about canister, memory and upgrade"
+        );
+        assert_eq!(ClusteringEngine::cluster_name(&terms, 0), "Canister & Memory");
+        assert_eq!(ClusteringEngine::cluster_name(&[], 1), "Cluster 2");
+    }
 }
\ No newline at end of file
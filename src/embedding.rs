@@ -1,123 +1,554 @@
 use crate::types::*;
 use ic_cdk::api::management_canister::http_request::{
     CanisterHttpRequestArgument, HttpMethod, HttpHeader, HttpResponse as CanisterHttpResponse,
+    TransformArgs, TransformContext,
 };
 use candid::Principal;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
 
 const OPENAI_API_URL: &str = "https://api.openai.com/v1/embeddings";
 const OPENROUTER_API_URL: &str = "https://openrouter.ai/api/v1/embeddings";
 const DEFAULT_OPENAI_MODEL: &str = "text-embedding-ada-002";
 const DEFAULT_OPENROUTER_MODEL: &str = "text-embedding-ada-002";
-const MAX_RESPONSE_BYTES: u64 = 8192;
+pub const LOCAL_HASH_MODEL: &str = "local-hash-v1";
 
-pub async fn generate_embedding_for_user(text: &str, user_id: Principal) -> Result<Vec<f32>, String> {
-    if text.trim().is_empty() {
-        return Err("Text cannot be empty".to_string());
+// Batch sizing for OpenAI-compatible outcalls
+const MAX_OUTCALL_RESPONSE_BYTES: u64 = 2_000_000; // IC limit for an HTTPS outcall response
+const MAX_REQUEST_BODY_BYTES: usize = 1_000_000;
+const MAX_BATCH_INPUTS: usize = 2048; // OpenAI limit on inputs per request
+// OpenAI pretty-prints one float per line ("      -0.0069352817,\n"); budget
+// for that plus per-item fields, and the headers and envelope of the response
+const RESPONSE_BYTES_PER_DIMENSION: u64 = 24;
+const RESPONSE_BYTES_PER_ITEM: u64 = 128;
+const RESPONSE_ENVELOPE_BYTES: u64 = 4096;
+/// Largest embedding dimension whose response fits in one outcall
+pub const MAX_EMBEDDING_DIMENSION: u32 = ((MAX_OUTCALL_RESPONSE_BYTES - RESPONSE_ENVELOPE_BYTES - RESPONSE_BYTES_PER_ITEM)
+    / RESPONSE_BYTES_PER_DIMENSION) as u32;
+
+// Canister query method that normalizes outcall responses for consensus
+pub const TRANSFORM_METHOD: &str = "transform_embedding_response";
+// Embedding values are rounded to 6 decimals so replicas that received
+// slightly different floats from the provider still agree on the response
+const EMBEDDING_ROUNDING_SCALE: f64 = 1e6;
+// HTTPS outcall pricing on a 13-node application subnet
+const SUBNET_SIZE: u128 = 13;
+const OUTCALL_BASE_CYCLES: u128 = 3_000_000;
+const OUTCALL_PER_NODE_CYCLES: u128 = 60_000;
+const OUTCALL_REQUEST_BYTE_CYCLES: u128 = 400;
+const OUTCALL_RESPONSE_BYTE_CYCLES: u128 = 800;
+
+// Cycles paid for embedding outcalls since the last upgrade
+#[derive(serde::Serialize, Clone, Default)]
+pub struct OutcallStats {
+    pub outcalls: u64,
+    pub failed_outcalls: u64,
+    pub cycles_attached: u128,
+    pub cycles_refunded: u128,
+}
+
+thread_local! {
+    static OUTCALL_STATS: RefCell<OutcallStats> = RefCell::new(OutcallStats::default());
+}
+
+pub type EmbeddingFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<f32>, String>> + 'a>>;
+pub type BatchEmbeddingFuture<'a> = Pin<Box<dyn Future<Output = Vec<Result<Vec<f32>, String>>> + 'a>>;
+
+// Source of embeddings for a user. Implementations must return vectors of the
+// vector store's configured dimension.
+pub trait EmbeddingProvider {
+    fn name(&self) -> &str;
+    fn model(&self) -> &str;
+    fn embed<'a>(&'a self, text: &'a str) -> EmbeddingFuture<'a>;
+
+    // One result per input, in input order. Providers that support array
+    // inputs override this to embed many texts per request.
+    fn embed_batch<'a>(&'a self, texts: &'a [String]) -> BatchEmbeddingFuture<'a> {
+        Box::pin(async move {
+            let mut results = Vec::with_capacity(texts.len());
+            for text in texts {
+                results.push(self.embed(text).await);
+            }
+            results
+        })
     }
-    
+}
+
+// Any endpoint speaking the OpenAI embeddings protocol: OpenAI itself,
+// OpenRouter, or a self-hosted server (Ollama, vLLM, LocalAI)
+pub struct OpenAiCompatibleProvider {
+    name: String,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+    extra_headers: Vec<HttpHeader>,
+    dimension: Option<usize>, // Configured output dimension of a model `model_dimension` does not know
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn openai(api_key: String, model: String) -> Self {
+        Self {
+            name: "OpenAI".to_string(),
+            url: OPENAI_API_URL.to_string(),
+            api_key: Some(api_key),
+            model: non_empty_or(model, DEFAULT_OPENAI_MODEL),
+            extra_headers: Vec::new(),
+            dimension: None,
+        }
+    }
+
+    pub fn openrouter(api_key: String, model: String) -> Self {
+        Self {
+            name: "OpenRouter".to_string(),
+            url: OPENROUTER_API_URL.to_string(),
+            api_key: Some(api_key),
+            model: non_empty_or(model, DEFAULT_OPENROUTER_MODEL),
+            // OpenRouter specific headers
+            extra_headers: vec![
+                HttpHeader {
+                    name: "HTTP-Referer".to_string(),
+                    value: "https://openmemory.ai".to_string(),
+                },
+                HttpHeader {
+                    name: "X-Title".to_string(),
+                    value: "OpenMemory".to_string(),
+                },
+            ],
+            dimension: None,
+        }
+    }
+
+    // `base_url` is the API root, e.g. `http://localhost:11434/v1`
+    pub fn custom(base_url: &str, api_key: Option<String>, model: String, dimension: Option<usize>) -> Self {
+        Self {
+            name: "OpenAICompatible".to_string(),
+            url: format!("{}/embeddings", base_url.trim_end_matches('/')),
+            api_key,
+            model,
+            extra_headers: Vec::new(),
+            dimension,
+        }
+    }
+
+    // Dimension responses are sized for: the model actually being called
+    fn response_dimension(&self) -> usize {
+        self.dimension
+            .or_else(|| model_dimension(&self.model))
+            .unwrap_or_else(|| crate::vector_store::AdvancedVectorStore::get_config().dimension)
+    }
+
+    // Embed every text, packing as many inputs per outcall as the response
+    // limit allows. A failed batch is retried item by item so one bad input
+    // does not fail its neighbours.
+    async fn embed_all(&self, texts: &[String]) -> Vec<Result<Vec<f32>, String>> {
+        let mut results: Vec<Result<Vec<f32>, String>> = texts
+            .iter()
+            .map(|text| {
+                if text.trim().is_empty() {
+                    Err("Text cannot be empty".to_string())
+                } else {
+                    Ok(Vec::new())
+                }
+            })
+            .collect();
+
+        let pending: Vec<usize> = (0..texts.len()).filter(|&i| results[i].is_ok()).collect();
+        let pending_texts: Vec<String> = pending.iter().map(|&i| texts[i].clone()).collect();
+        let dimension = self.response_dimension();
+
+        for batch in plan_batches(&pending_texts, dimension) {
+            let inputs = &pending_texts[batch.clone()];
+            match self.request_embeddings(inputs, dimension).await {
+                Ok(embeddings) => {
+                    for (offset, embedding) in embeddings.into_iter().enumerate() {
+                        results[pending[batch.start + offset]] = Ok(embedding);
+                    }
+                }
+                Err(e) if inputs.len() > 1 => {
+                    ic_cdk::println!("Batch of {} embeddings failed, retrying individually: {}", inputs.len(), e);
+                    for (offset, input) in inputs.iter().enumerate() {
+                        results[pending[batch.start + offset]] = self
+                            .request_embeddings(std::slice::from_ref(input), dimension)
+                            .await
+                            .map(|mut embeddings| embeddings.remove(0));
+                    }
+                }
+                Err(e) => results[pending[batch.start]] = Err(e),
+            }
+        }
+
+        results
+    }
+
+    // One outcall for all inputs; embeddings are returned in input order
+    async fn request_embeddings(&self, inputs: &[String], dimension: usize) -> Result<Vec<Vec<f32>>, String> {
+        let request_body = EmbeddingRequest {
+            model: self.model.clone(),
+            input: inputs.to_vec(),
+            encoding_format: "float".to_string(),
+        };
+        
+        let body_bytes = serde_json::to_vec(&request_body)
+            .map_err(|e| format!("Failed to serialize request: {}", e))?;
+
+        let mut headers = vec![
+            HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            },
+        ];
+        if let Some(ref api_key) = self.api_key {
+            headers.push(HttpHeader {
+                name: "Authorization".to_string(),
+                value: format!("Bearer {}", api_key),
+            });
+        }
+        headers.extend(self.extra_headers.iter().cloned());
+        
+        let http_request = CanisterHttpRequestArgument {
+            url: self.url.clone(),
+            method: HttpMethod::POST,
+            body: Some(body_bytes),
+            max_response_bytes: Some(response_bytes_for(inputs.len(), dimension)),
+            headers,
+            transform: Some(TransformContext::from_name(TRANSFORM_METHOD.to_string(), Vec::new())),
+        };
+        
+        let cycles = outcall_cycles(&http_request);
+        ic_cdk::println!(
+            "Generating {} embedding(s) via {} (attaching {} cycles)",
+            inputs.len(), self.name, cycles
+        );
+        
+        let result: Result<(CanisterHttpResponse,), _> = ic_cdk::api::call::call_with_payment128(
+            Principal::management_canister(),
+            "http_request",
+            (http_request,),
+            cycles,
+        )
+        .await;
+        
+        let refunded = ic_cdk::api::call::msg_cycles_refunded128();
+        OUTCALL_STATS.with(|stats| {
+            let mut stats = stats.borrow_mut();
+            stats.outcalls += 1;
+            stats.cycles_attached += cycles;
+            stats.cycles_refunded += refunded;
+            if result.is_err() {
+                stats.failed_outcalls += 1;
+            }
+        });
+        
+        let (response,) = result.map_err(|e| format!("HTTP request failed: {:?}", e))?;
+        
+        if response.status != 200u16 {
+            let error_body = String::from_utf8_lossy(&response.body);
+            return Err(format!("{} API error ({}): {}", self.name, response.status, error_body));
+        }
+        
+        let embedding_response: EmbeddingResponse = 
+            serde_json::from_slice(&response.body)
+                .map_err(|e| format!("Failed to parse response: {}", e))?;
+        
+        // Map results back by index; servers that omit it answer in order
+        let mut embeddings: Vec<Option<Vec<f32>>> = vec![None; inputs.len()];
+        for (position, data) in embedding_response.data.into_iter().enumerate() {
+            let index = data.index.unwrap_or(position);
+            if let Some(slot) = embeddings.get_mut(index) {
+                *slot = Some(data.embedding);
+            }
+        }
+        
+        let embeddings = embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embedding)| embedding.ok_or(format!("No embedding data received for input {}", index)))
+            .collect::<Result<Vec<_>, String>>()?;
+        
+        ic_cdk::println!("Successfully generated {} embedding(s)", embeddings.len());
+        Ok(embeddings)
+    }
+}
+
+// Split texts into consecutive batches whose estimated response fits in one
+// outcall and whose request body stays bounded
+fn plan_batches(texts: &[String], dimension: usize) -> Vec<std::ops::Range<usize>> {
+    let max_items_by_response = ((MAX_OUTCALL_RESPONSE_BYTES - RESPONSE_ENVELOPE_BYTES)
+        / (dimension as u64 * RESPONSE_BYTES_PER_DIMENSION + RESPONSE_BYTES_PER_ITEM))
+        .max(1) as usize;
+    let max_items = max_items_by_response.min(MAX_BATCH_INPUTS);
+
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut body_bytes = 0;
+    for (i, text) in texts.iter().enumerate() {
+        // JSON escaping can grow a string; budget generously
+        let text_bytes = text.len() * 2 + 4;
+        if i > start && (i - start >= max_items || body_bytes + text_bytes > MAX_REQUEST_BODY_BYTES) {
+            batches.push(start..i);
+            start = i;
+            body_bytes = 0;
+        }
+        body_bytes += text_bytes;
+    }
+    if start < texts.len() {
+        batches.push(start..texts.len());
+    }
+    batches
+}
+
+// Native output dimension of well-known embedding models. OpenRouter ids
+// carry a vendor prefix ("openai/text-embedding-3-small").
+pub fn model_dimension(model: &str) -> Option<usize> {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    match name {
+        "text-embedding-ada-002" | "text-embedding-3-small" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        "nomic-embed-text" | "bge-base-en-v1.5" => Some(768),
+        "all-minilm" | "all-MiniLM-L6-v2" | "bge-small-en-v1.5" => Some(384),
+        "mxbai-embed-large" | "bge-large-en-v1.5" => Some(1024),
+        LOCAL_HASH_MODEL => Some(crate::vector_store::AdvancedVectorStore::get_config().dimension),
+        _ => None,
+    }
+}
+
+// Cycles to attach to an HTTPS outcall; unused cycles are refunded
+fn outcall_cycles(request: &CanisterHttpRequestArgument) -> u128 {
+    let header_bytes: usize = request.headers.iter().map(|h| h.name.len() + h.value.len()).sum();
+    let transform_bytes = request.transform.as_ref()
+        .map(|t| t.function.0.method.len() + t.context.len())
+        .unwrap_or(0);
+    let request_bytes = (request.url.len()
+        + header_bytes
+        + request.body.as_ref().map(|b| b.len()).unwrap_or(0)
+        + transform_bytes) as u128;
+    let response_bytes = request.max_response_bytes.unwrap_or(MAX_OUTCALL_RESPONSE_BYTES) as u128;
+
+    (OUTCALL_BASE_CYCLES + OUTCALL_PER_NODE_CYCLES * SUBNET_SIZE) * SUBNET_SIZE
+        + OUTCALL_REQUEST_BYTE_CYCLES * SUBNET_SIZE * request_bytes
+        + OUTCALL_RESPONSE_BYTE_CYCLES * SUBNET_SIZE * response_bytes
+}
+
+pub fn get_outcall_stats() -> OutcallStats {
+    OUTCALL_STATS.with(|stats| stats.borrow().clone())
+}
+
+// Keep only what replicas can agree on: the status and the embedding payload.
+// Headers (dates, request ids, rate-limit counters) and fields such as usage
+// are dropped; error bodies are reduced to their message.
+pub fn trim_embedding_response(args: TransformArgs) -> CanisterHttpResponse {
+    let response = args.response;
+
+    let body = if response.status == 200u16 {
+        match serde_json::from_slice::<EmbeddingResponse>(&response.body) {
+            Ok(mut parsed) => {
+                for value in parsed.data.iter_mut().flat_map(|item| item.embedding.iter_mut()) {
+                    *value = round_embedding_value(*value);
+                }
+                serde_json::to_vec(&parsed).unwrap_or_default()
+            }
+            Err(_) => Vec::new(),
+        }
+    } else {
+        serde_json::from_slice::<serde_json::Value>(&response.body)
+            .ok()
+            .and_then(|value| value["error"]["message"].as_str().map(|m| m.as_bytes().to_vec()))
+            .unwrap_or_default()
+    };
+
+    CanisterHttpResponse {
+        status: response.status,
+        headers: Vec::new(),
+        body,
+    }
+}
+
+fn round_embedding_value(value: f32) -> f32 {
+    ((value as f64 * EMBEDDING_ROUNDING_SCALE).round() / EMBEDDING_ROUNDING_SCALE) as f32
+}
+
+fn response_bytes_for(items: usize, dimension: usize) -> u64 {
+    (RESPONSE_ENVELOPE_BYTES + items as u64 * (dimension as u64 * RESPONSE_BYTES_PER_DIMENSION + RESPONSE_BYTES_PER_ITEM))
+        .min(MAX_OUTCALL_RESPONSE_BYTES)
+}
+
+impl EmbeddingProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> EmbeddingFuture<'a> {
+        Box::pin(async move {
+            let texts = [text.to_string()];
+            self.embed_all(&texts).await.remove(0)
+        })
+    }
+
+    fn embed_batch<'a>(&'a self, texts: &'a [String]) -> BatchEmbeddingFuture<'a> {
+        Box::pin(self.embed_all(texts))
+    }
+}
+
+// Deterministic in-canister embedder based on feature hashing. Needs no
+// network or API key; words, word bigrams and character trigrams are hashed
+// into signed buckets so texts sharing vocabulary land close together.
+pub struct LocalHashEmbedder {
+    dimension: usize,
+}
+
+impl LocalHashEmbedder {
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension }
+    }
+
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0f32; self.dimension];
+        if self.dimension == 0 {
+            return embedding;
+        }
+
+        let words = crate::text_index::tokenize(text);
+        for word in &words {
+            self.add_feature(&mut embedding, "w", word, 1.0);
+
+            let padded: Vec<char> = format!(" {} ", word).chars().collect();
+            for trigram in padded.windows(3) {
+                self.add_feature(&mut embedding, "c", &trigram.iter().collect::<String>(), 0.5);
+            }
+        }
+        for pair in words.windows(2) {
+            self.add_feature(&mut embedding, "b", &format!("{} {}", pair[0], pair[1]), 0.75);
+        }
+
+        normalize_embedding(&mut embedding);
+        embedding
+    }
+
+    fn add_feature(&self, embedding: &mut [f32], kind: &str, feature: &str, weight: f32) {
+        let digest = Sha256::digest(format!("{}:{}", kind, feature).as_bytes());
+        let bucket = u64::from_le_bytes(digest[..8].try_into().unwrap()) as usize % self.dimension;
+        let sign = if digest[8] & 1 == 0 { 1.0 } else { -1.0 };
+        embedding[bucket] += sign * weight;
+    }
+}
+
+impl EmbeddingProvider for LocalHashEmbedder {
+    fn name(&self) -> &str {
+        "Local"
+    }
+
+    fn model(&self) -> &str {
+        LOCAL_HASH_MODEL
+    }
+
+    fn embed<'a>(&'a self, text: &'a str) -> EmbeddingFuture<'a> {
+        let embedding = self.embed_text(text);
+        Box::pin(async move { Ok(embedding) })
+    }
+}
+
+fn non_empty_or(value: String, default: &str) -> String {
+    if value.trim().is_empty() {
+        default.to_string()
+    } else {
+        value
+    }
+}
+
+// Build the provider selected in the user's configuration
+pub fn provider_for_user(user_id: Principal) -> Result<Box<dyn EmbeddingProvider>, String> {
     let user_config = match crate::storage::get_user_config(user_id) {
         Ok(Some(config)) => config,
         Ok(None) => return Err("No API configuration found. Please set your API key in settings.".to_string()),
         Err(e) => return Err(format!("Failed to get user config: {}", e)),
     };
 
-    let (api_key, api_url, model) = match user_config.api_provider {
-        crate::types::ApiProvider::OpenAI => {
+    provider_for_config(user_config)
+}
+
+pub fn provider_for_config(user_config: UserConfig) -> Result<Box<dyn EmbeddingProvider>, String> {
+    match user_config.api_provider {
+        ApiProvider::OpenAI => {
             let key = user_config.openai_api_key
                 .ok_or("OpenAI API key not configured. Please set your API key in settings.")?;
-            (key, OPENAI_API_URL.to_string(), user_config.embedding_model)
+            Ok(Box::new(OpenAiCompatibleProvider::openai(key, user_config.embedding_model)))
         }
-        crate::types::ApiProvider::OpenRouter => {
+        ApiProvider::OpenRouter => {
             let key = user_config.openrouter_api_key
                 .ok_or("OpenRouter API key not configured. Please set your API key in settings.")?;
-            (key, OPENROUTER_API_URL.to_string(), user_config.embedding_model)
+            Ok(Box::new(OpenAiCompatibleProvider::openrouter(key, user_config.embedding_model)))
+        }
+        ApiProvider::OpenAICompatible => {
+            let base_url = user_config.api_base_url
+                .ok_or("API base URL not configured. Please set it in settings.")?;
+            Ok(Box::new(OpenAiCompatibleProvider::custom(
+                &base_url,
+                user_config.compatible_api_key,
+                user_config.embedding_model,
+                user_config.embedding_dimension.map(|dimension| dimension as usize),
+            )))
+        }
+        ApiProvider::Local => {
+            let dimension = crate::vector_store::AdvancedVectorStore::get_config().dimension;
+            Ok(Box::new(LocalHashEmbedder::new(dimension)))
         }
-    };
-    
-    let request_body = EmbeddingRequest {
-        model: model,
-        input: text.to_string(),
-        encoding_format: "float".to_string(),
-    };
-    
-    let body_bytes = serde_json::to_vec(&request_body)
-        .map_err(|e| format!("Failed to serialize request: {}", e))?;
-
-    // Prepare headers based on provider
-    let mut headers = vec![
-        HttpHeader {
-            name: "Content-Type".to_string(),
-            value: "application/json".to_string(),
-        },
-        HttpHeader {
-            name: "Authorization".to_string(),
-            value: format!("Bearer {}", api_key),
-        },
-    ];
-
-    // Add OpenRouter specific headers
-    if matches!(user_config.api_provider, crate::types::ApiProvider::OpenRouter) {
-        headers.push(HttpHeader {
-            name: "HTTP-Referer".to_string(),
-            value: "https://openmemory.ai".to_string(),
-        });
-        headers.push(HttpHeader {
-            name: "X-Title".to_string(),
-            value: "OpenMemory".to_string(),
-        });
     }
-    
-    let http_request = CanisterHttpRequestArgument {
-        url: api_url,
-        method: HttpMethod::POST,
-        body: Some(body_bytes),
-        max_response_bytes: Some(MAX_RESPONSE_BYTES),
-        headers,
-        transform: None,
-    };
-    
-    ic_cdk::println!("Generating embedding for text: {}", &text[..text.len().min(100)]);
-    
-    let (response,): (CanisterHttpResponse,) = ic_cdk::call(
-        Principal::management_canister(),
-        "http_request",
-        (http_request,),
-    )
-    .await
-    .map_err(|e| format!("HTTP request failed: {:?}", e))?;
-    
-    if response.status != 200u16 {
-        let error_body = String::from_utf8_lossy(&response.body);
-        return Err(format!("OpenAI API error ({}): {}", response.status, error_body));
+}
+
+// Model name recorded on embeddings generated for the user right now
+pub fn embedding_model_for_user(user_id: Principal) -> Option<String> {
+    provider_for_user(user_id).ok().map(|provider| provider.model().to_string())
+}
+
+pub async fn generate_embedding_for_user(text: &str, user_id: Principal) -> Result<Vec<f32>, String> {
+    if text.trim().is_empty() {
+        return Err("Text cannot be empty".to_string());
     }
     
-    let embedding_response: EmbeddingResponse = 
-        serde_json::from_slice(&response.body)
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
+    let provider = provider_for_user(user_id)?;
+    provider.embed(text).await
+}
+
+// Embedding for a search query and the model that produced it. Queries that
+// normalize to the same text (`search::preprocess_query`) share one cached
+// embedding per user and model, so repeated searches skip the outcall. The
+// normalized text is only the cache key; the provider embeds the query as given.
+pub async fn generate_query_embedding_for_user(query: &str, user_id: Principal) -> Result<(Vec<f32>, String), String> {
+    if query.trim().is_empty() {
+        return Err("Text cannot be empty".to_string());
+    }
     
-    if embedding_response.data.is_empty() {
-        return Err("No embedding data received".to_string());
+    let provider = provider_for_user(user_id)?;
+    let model = provider.model().to_string();
+    let normalized = crate::search::preprocess_query(query);
+    if normalized.is_empty() {
+        // Nothing but punctuation; embed as given and skip the cache
+        return Ok((provider.embed(query).await?, model));
     }
     
-    ic_cdk::println!("Successfully generated embedding with {} dimensions", 
-                     embedding_response.data[0].embedding.len());
+    if let Some(embedding) = crate::embedding_cache::EmbeddingCache::get(user_id, &model, &normalized) {
+        return Ok((embedding, model));
+    }
     
-    Ok(embedding_response.data[0].embedding.clone())
+    let embedding = provider.embed(query).await?;
+    crate::embedding_cache::EmbeddingCache::insert(user_id, &model, &normalized, embedding.clone());
+    Ok((embedding, model))
 }
 
-// Batch processing support for multiple users
+// Batch processing support for multiple users; fails if any text fails
 pub async fn generate_multiple_embeddings_for_user(texts: Vec<String>, user_id: Principal) -> Result<Vec<Vec<f32>>, String> {
-    let mut embeddings = Vec::new();
-    
-    for text in texts {
-        let embedding = generate_embedding_for_user(&text, user_id).await?;
-        embeddings.push(embedding);
-        
-        // Add a small delay to avoid rate limiting
-        ic_cdk::println!("Generated embedding {} of {}", embeddings.len(), texts.len());
-    }
-    
-    Ok(embeddings)
+    generate_embeddings_batch_for_user(texts, user_id)
+        .await?
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| result.map_err(|e| format!("Embedding {} failed: {}", index, e)))
+        .collect()
 }
 
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
@@ -157,24 +588,11 @@ pub fn normalize_embedding(embedding: &mut [f32]) {
     }
 }
 
-// Batch processing for better efficiency
-pub async fn generate_embeddings_batch_for_user(texts: Vec<String>, batch_size: usize, user_id: Principal) -> Result<Vec<Vec<f32>>, String> {
-    let mut all_embeddings = Vec::new();
-    
-    for chunk in texts.chunks(batch_size) {
-        for text in chunk {
-            let embedding = generate_embedding_for_user(text, user_id).await?;
-            all_embeddings.push(embedding);
-        }
-        
-        // Small delay between batches to respect rate limits
-        if all_embeddings.len() < texts.len() {
-            ic_cdk::println!("Processed batch, waiting before next batch...");
-            // Note: In a real implementation, you might want to add actual delay
-        }
-    }
-    
-    Ok(all_embeddings)
+// Batch processing for better efficiency: texts are packed into as few
+// outcalls as the provider allows, with one result per input text
+pub async fn generate_embeddings_batch_for_user(texts: Vec<String>, user_id: Principal) -> Result<Vec<Result<Vec<f32>, String>>, String> {
+    let provider = provider_for_user(user_id)?;
+    Ok(provider.embed_batch(&texts).await)
 }
 
 // Error recovery and retry logic
@@ -229,6 +647,97 @@ mod tests {
         assert!((euclidean_distance(&a, &b) - 0.0).abs() < 1e-6);
     }
 
+    #[test]
+    fn test_local_hash_embedder() {
+        let embedder = LocalHashEmbedder::new(256);
+        
+        let a = embedder.embed_text("Rust ownership and borrowing rules");
+        assert_eq!(a.len(), 256);
+        assert_eq!(a, embedder.embed_text("Rust ownership and borrowing rules"));
+        
+        // Shared vocabulary scores higher than unrelated text
+        let related = embedder.embed_text("borrowing rules in Rust");
+        let unrelated = embedder.embed_text("chocolate cake recipe");
+        assert!(cosine_similarity(&a, &related) > cosine_similarity(&a, &unrelated));
+        
+        let norm: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_plan_batches() {
+        let texts: Vec<String> = (0..500).map(|i| format!("memory {}", i)).collect();
+        
+        // 1536 dimensions: roughly 30KB per embedding, so ~64 per outcall
+        let batches = plan_batches(&texts, 1536);
+        assert!(batches.len() > 1);
+        assert_eq!(batches.first().unwrap().start, 0);
+        assert_eq!(batches.last().unwrap().end, texts.len());
+        for window in batches.windows(2) {
+            assert_eq!(window[0].end, window[1].start);
+        }
+        for batch in &batches {
+            assert!(response_bytes_for(batch.len(), 1536) <= MAX_OUTCALL_RESPONSE_BYTES);
+        }
+        
+        // Large request bodies also split batches
+        let large: Vec<String> = (0..4).map(|_| "x".repeat(MAX_REQUEST_BODY_BYTES / 3)).collect();
+        assert_eq!(plan_batches(&large, 8).len(), 4);
+        
+        assert!(plan_batches(&[], 1536).is_empty());
+    }
+
+    #[test]
+    fn test_trim_embedding_response() {
+        let raw = br#"{"object": "list", "data": [{"object": "embedding", "index": 0, "embedding": [0.5, -0.2500000417, 0.0069352817]}], "model": "text-embedding-3-small", "usage": {"prompt_tokens": 3, "total_tokens": 3}}"#;
+        let trimmed = trim_embedding_response(TransformArgs {
+            response: CanisterHttpResponse {
+                status: 200u16.into(),
+                headers: vec![HttpHeader { name: "Date".to_string(), value: "Mon".to_string() }],
+                body: raw.to_vec(),
+            },
+            context: Vec::new(),
+        });
+        assert!(trimmed.headers.is_empty());
+        assert_eq!(String::from_utf8(trimmed.body).unwrap(), r#"{"data":[{"embedding":[0.5,-0.25,0.006935],"index":0}]}"#);
+        
+        let error = trim_embedding_response(TransformArgs {
+            response: CanisterHttpResponse {
+                status: 401u16.into(),
+                headers: Vec::new(),
+                body: br#"{"error": {"message": "Incorrect API key", "request_id": "abc"}}"#.to_vec(),
+            },
+            context: Vec::new(),
+        });
+        assert_eq!(error.body, b"Incorrect API key".to_vec());
+    }
+
+    #[test]
+    fn test_model_dimension() {
+        assert_eq!(model_dimension("text-embedding-3-large"), Some(3072));
+        assert_eq!(model_dimension("openai/text-embedding-3-small"), Some(1536));
+        assert_eq!(model_dimension("unknown-model"), None);
+        // Larger models need a larger response allowance
+        assert!(response_bytes_for(1, 3072) > response_bytes_for(1, 1536));
+    }
+
+    #[test]
+    fn test_custom_provider_url() {
+        let provider = OpenAiCompatibleProvider::custom("http://localhost:11434/v1/", None, "nomic-embed-text".to_string(), None);
+        assert_eq!(provider.url, "http://localhost:11434/v1/embeddings");
+        assert_eq!(provider.model(), "nomic-embed-text");
+        assert_eq!(provider.response_dimension(), 768);
+    }
+
+    #[test]
+    fn test_custom_provider_uses_configured_dimension() {
+        let provider = OpenAiCompatibleProvider::custom("http://localhost:8000/v1", None, "e5-mistral-7b".to_string(), Some(4096));
+        assert_eq!(provider.response_dimension(), 4096);
+        assert!(response_bytes_for(1, 4096) > response_bytes_for(1, 1536));
+        // The largest accepted dimension still fits one embedding per outcall
+        assert!(response_bytes_for(1, MAX_EMBEDDING_DIMENSION as usize) < MAX_OUTCALL_RESPONSE_BYTES);
+    }
+
     #[test]
     fn test_normalize_embedding() {
         let mut embedding = vec![3.0, 4.0];
@@ -0,0 +1,199 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Bounded LRU cache of search query embeddings in stable memory, keyed by
+// user, embedding model and the normalized query (`search::preprocess_query`).
+// Entries carry a use sequence number; a second map ordered by that number
+// finds the least recently used entry to evict.
+
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type EntryMap = StableBTreeMap<String, CachedQueryEmbedding, VMem>;
+type RecencyMap = StableBTreeMap<u64, String, VMem>;
+
+/// Maximum number of cached query embeddings across all users
+pub const QUERY_EMBEDDING_CACHE_CAPACITY: u64 = 2_000;
+
+thread_local! {
+    static ENTRIES: RefCell<Option<EntryMap>> = const { RefCell::new(None) };
+    static RECENCY: RefCell<Option<RecencyMap>> = const { RefCell::new(None) };
+    static STATS: RefCell<CacheCounters> = RefCell::new(CacheCounters::default());
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct CachedQueryEmbedding {
+    pub embedding: Vec<f32>,
+    pub last_used: u64,
+}
+
+impl Storable for CachedQueryEmbedding {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+// Lookups since the last upgrade
+#[derive(Clone, Default)]
+struct CacheCounters {
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct EmbeddingCacheStats {
+    pub entries: u64,
+    pub capacity: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub hit_rate: f64,
+}
+
+pub struct EmbeddingCache;
+
+impl EmbeddingCache {
+    pub fn init() {
+        ENTRIES.with(|e| {
+            *e.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_QUERY_EMBEDDINGS)
+            ));
+        });
+
+        RECENCY.with(|r| {
+            *r.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_QUERY_EMBEDDING_RECENCY)
+            ));
+        });
+    }
+
+    // Cached embedding for a normalized query, marking it most recently used
+    pub fn get(user: Principal, model: &str, normalized_query: &str) -> Option<Vec<f32>> {
+        let key = cache_key(user, model, normalized_query);
+
+        let embedding = ENTRIES.with(|e| {
+            let mut entries = e.borrow_mut();
+            let entries = entries.as_mut()?;
+            let mut entry = entries.get(&key)?;
+
+            RECENCY.with(|r| {
+                if let Some(ref mut recency) = *r.borrow_mut() {
+                    recency.remove(&entry.last_used);
+                    entry.last_used = next_sequence(recency);
+                    recency.insert(entry.last_used, key.clone());
+                }
+            });
+            let embedding = entry.embedding.clone();
+            entries.insert(key.clone(), entry);
+            Some(embedding)
+        });
+
+        STATS.with(|s| {
+            let mut stats = s.borrow_mut();
+            if embedding.is_some() {
+                stats.hits += 1;
+            } else {
+                stats.misses += 1;
+            }
+        });
+        embedding
+    }
+
+    // Store an embedding, evicting the least recently used entries beyond capacity
+    pub fn insert(user: Principal, model: &str, normalized_query: &str, embedding: Vec<f32>) {
+        let key = cache_key(user, model, normalized_query);
+
+        ENTRIES.with(|e| {
+            let mut entries = e.borrow_mut();
+            let Some(entries) = entries.as_mut() else { return };
+
+            RECENCY.with(|r| {
+                let mut recency = r.borrow_mut();
+                let Some(recency) = recency.as_mut() else { return };
+
+                if let Some(previous) = entries.get(&key) {
+                    recency.remove(&previous.last_used);
+                }
+                let last_used = next_sequence(recency);
+                recency.insert(last_used, key.clone());
+                entries.insert(key, CachedQueryEmbedding { embedding, last_used });
+
+                while entries.len() > QUERY_EMBEDDING_CACHE_CAPACITY {
+                    let Some((_, oldest)) = recency.pop_first() else { break };
+                    entries.remove(&oldest);
+                    STATS.with(|s| s.borrow_mut().evictions += 1);
+                }
+            });
+        });
+    }
+
+    pub fn get_stats() -> EmbeddingCacheStats {
+        let entries = ENTRIES.with(|e| e.borrow().as_ref().map(|entries| entries.len()).unwrap_or(0));
+        let counters = STATS.with(|s| s.borrow().clone());
+        let lookups = counters.hits + counters.misses;
+
+        EmbeddingCacheStats {
+            entries,
+            capacity: QUERY_EMBEDDING_CACHE_CAPACITY,
+            hits: counters.hits,
+            misses: counters.misses,
+            evictions: counters.evictions,
+            hit_rate: if lookups == 0 { 0.0 } else { counters.hits as f64 / lookups as f64 },
+        }
+    }
+}
+
+fn next_sequence(recency: &RecencyMap) -> u64 {
+    recency.last_key_value().map(|(sequence, _)| sequence + 1).unwrap_or(0)
+}
+
+// Hash of the key parts; queries can be long and should not be stored verbatim
+fn cache_key(user: Principal, model: &str, normalized_query: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(user.as_slice());
+    hasher.update([0u8]);
+    hasher.update(model.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(normalized_query.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        EmbeddingCache::init();
+        let user = Principal::anonymous();
+
+        for i in 0..QUERY_EMBEDDING_CACHE_CAPACITY {
+            EmbeddingCache::insert(user, "model", &format!("query {}", i), vec![i as f32]);
+        }
+        // Touch the oldest entry so the second oldest is evicted instead
+        assert_eq!(EmbeddingCache::get(user, "model", "query 0"), Some(vec![0.0]));
+        EmbeddingCache::insert(user, "model", "newest", vec![-1.0]);
+
+        assert_eq!(EmbeddingCache::get(user, "model", "query 0"), Some(vec![0.0]));
+        assert_eq!(EmbeddingCache::get(user, "model", "query 1"), None);
+        assert_eq!(EmbeddingCache::get(user, "model", "newest"), Some(vec![-1.0]));
+        // Keys are per model and per user
+        assert_eq!(EmbeddingCache::get(user, "other-model", "query 0"), None);
+        assert_eq!(EmbeddingCache::get(Principal::management_canister(), "model", "query 0"), None);
+
+        let stats = EmbeddingCache::get_stats();
+        assert_eq!(stats.entries, QUERY_EMBEDDING_CACHE_CAPACITY);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!((stats.hits, stats.misses), (3, 3));
+    }
+}
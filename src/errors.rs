@@ -2,6 +2,9 @@ use candid::CandidType;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+/// `auth_type` of authentication errors caused by missing permissions rather than missing credentials
+pub const PERMISSION_AUTH_TYPE: &str = "permission";
+
 /// OpenMemory system errors with structured error handling
 #[derive(Debug, Clone, CandidType, Deserialize, Serialize)]
 pub enum OpenMemoryError {
@@ -77,6 +80,14 @@ impl OpenMemoryError {
         }
     }
     
+    /// Create an authorization error for a caller lacking a permission (HTTP 403)
+    pub fn permission_denied(permission: &crate::types::Permission) -> Self {
+        Self::Authentication {
+            message: format!("Missing required permission: {:?}", permission),
+            auth_type: PERMISSION_AUTH_TYPE.to_string(),
+        }
+    }
+    
     /// Create an OpenAI API error
     pub fn openai(message: impl Into<String>, status_code: Option<u16>) -> Self {
         Self::OpenAI {
@@ -137,6 +148,7 @@ impl OpenMemoryError {
     pub fn status_code(&self) -> u16 {
         match self {
             OpenMemoryError::Storage { .. } => 500,
+            OpenMemoryError::Authentication { auth_type, .. } if auth_type == PERMISSION_AUTH_TYPE => 403,
             OpenMemoryError::Authentication { .. } => 401,
             OpenMemoryError::OpenAI { status_code, .. } => status_code.unwrap_or(500),
             OpenMemoryError::Validation { .. } => 400,
@@ -301,6 +313,10 @@ mod tests {
         
         let auth_err = OpenMemoryError::authentication("Invalid token", "bearer");
         assert_eq!(auth_err.status_code(), 401);
+        
+        let permission_err = OpenMemoryError::permission_denied(&crate::types::Permission::Delete);
+        assert_eq!(permission_err.status_code(), 403);
+        assert_eq!(permission_err.category(), "authentication");
         assert_eq!(auth_err.category(), "authentication");
         
         let not_found_err = OpenMemoryError::not_found("memory", "mem_123");
@@ -0,0 +1,551 @@
+use candid::CandidType;
+use ic_stable_structures::{Storable, storable::Bound};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// Hierarchical Navigable Small World graph used as the approximate nearest
+// neighbour index of the vector store. The graph logic is independent of where
+// nodes and vectors live; `GraphStore` abstracts the backing storage so the
+// canister can keep everything in stable memory while tests use plain maps.
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default)]
+pub struct HnswNode {
+    // neighbors[layer] holds the ids linked to this node on that layer.
+    // The node's level is `neighbors.len() - 1`.
+    pub neighbors: Vec<Vec<String>>,
+}
+
+impl HnswNode {
+    pub fn level(&self) -> usize {
+        self.neighbors.len().saturating_sub(1)
+    }
+}
+
+impl Storable for HnswNode {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct EntryPoint {
+    pub id: String,
+    pub level: usize,
+}
+
+impl Storable for EntryPoint {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HnswParams {
+    pub m: usize,               // Max links per node on upper layers (layer 0 allows 2 * m)
+    pub ef_construction: usize, // Candidate list size while inserting
+}
+
+impl HnswParams {
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { self.m * 2 } else { self.m }
+    }
+}
+
+// Backing storage for the graph
+pub trait GraphStore {
+    fn node(&self, id: &str) -> Option<HnswNode>;
+    fn put_node(&mut self, id: &str, node: HnswNode);
+    fn remove_node(&mut self, id: &str);
+    fn vector(&self, id: &str) -> Option<Vec<f32>>;
+    fn entry_point(&self) -> Option<EntryPoint>;
+    fn set_entry_point(&mut self, entry_point: Option<EntryPoint>);
+    // A node whose vector is still present; used when the entry point is
+    // removed or lost and none of its neighbours can take over
+    fn any_node(&self) -> Option<(String, HnswNode)>;
+}
+
+pub type SimilarityFn<'a> = &'a dyn Fn(&[f32], &[f32]) -> f32;
+
+// Deterministic level assignment derived from the id, so rebuilding the index
+// from the same data always yields the same layer structure.
+pub fn assign_level(id: &str, m: usize) -> usize {
+    let hash = Sha256::digest(id.as_bytes());
+    let bits = u64::from_be_bytes([hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7]]);
+    // Uniform value in (0, 1]
+    let uniform = ((bits >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let ml = 1.0 / (m.max(2) as f64).ln();
+    ((-uniform.ln() * ml).floor() as usize).min(16)
+}
+
+pub fn insert<S: GraphStore>(
+    store: &mut S,
+    id: &str,
+    vector: &[f32],
+    params: &HnswParams,
+    similarity: SimilarityFn,
+) {
+    let level = assign_level(id, params.m);
+
+    let entry_point = match store.entry_point() {
+        Some(entry_point) => entry_point,
+        None => {
+            store.put_node(id, HnswNode { neighbors: vec![Vec::new(); level + 1] });
+            store.set_entry_point(Some(EntryPoint { id: id.to_string(), level }));
+            return;
+        }
+    };
+
+    // An entry point that lost its vector is replaced by a surviving node, so
+    // the rest of the graph stays reachable
+    let (entry_point, entry_vector) = match store.vector(&entry_point.id) {
+        Some(v) => (entry_point, v),
+        None => {
+            let replacement = store.any_node().and_then(|(node_id, node)| {
+                store.vector(&node_id).map(|v| (EntryPoint { id: node_id, level: node.level() }, v))
+            });
+            match replacement {
+                Some((replacement, v)) => {
+                    store.set_entry_point(Some(replacement.clone()));
+                    (replacement, v)
+                }
+                None => {
+                    // Nothing left to link to; this node starts a fresh graph
+                    store.put_node(id, HnswNode { neighbors: vec![Vec::new(); level + 1] });
+                    store.set_entry_point(Some(EntryPoint { id: id.to_string(), level }));
+                    return;
+                }
+            }
+        }
+    };
+
+    let mut nearest = vec![Candidate {
+        similarity: similarity(vector, &entry_vector),
+        id: entry_point.id.clone(),
+    }];
+
+    // Greedy descent through the layers above the new node's level
+    let mut layer = entry_point.level;
+    while layer > level {
+        nearest = search_layer(store, vector, nearest, 1, layer, similarity);
+        layer -= 1;
+    }
+
+    let mut node = HnswNode { neighbors: vec![Vec::new(); level + 1] };
+    let top = level.min(entry_point.level);
+    let mut updated_neighbors: Vec<(usize, String)> = Vec::new();
+
+    for layer in (0..=top).rev() {
+        let candidates = search_layer(store, vector, nearest.clone(), params.ef_construction, layer, similarity);
+        let selected: Vec<String> = candidates
+            .iter()
+            .filter(|c| c.id != id)
+            .take(params.m)
+            .map(|c| c.id.clone())
+            .collect();
+
+        for neighbor_id in &selected {
+            updated_neighbors.push((layer, neighbor_id.clone()));
+        }
+        node.neighbors[layer] = selected;
+        nearest = candidates;
+    }
+
+    store.put_node(id, node);
+
+    // Add reverse links, shrinking neighbour lists that overflow
+    for (layer, neighbor_id) in updated_neighbors {
+        let Some(mut neighbor) = store.node(&neighbor_id) else { continue };
+        if layer >= neighbor.neighbors.len() {
+            continue;
+        }
+
+        if !neighbor.neighbors[layer].iter().any(|n| n == id) {
+            neighbor.neighbors[layer].push(id.to_string());
+        }
+
+        if neighbor.neighbors[layer].len() > params.max_links(layer) {
+            if let Some(neighbor_vector) = store.vector(&neighbor_id) {
+                let links = std::mem::take(&mut neighbor.neighbors[layer]);
+                neighbor.neighbors[layer] =
+                    select_closest(store, &neighbor_vector, links, params.max_links(layer), similarity);
+            }
+        }
+
+        store.put_node(&neighbor_id, neighbor);
+    }
+
+    if level > entry_point.level {
+        store.set_entry_point(Some(EntryPoint { id: id.to_string(), level }));
+    }
+}
+
+pub fn remove<S: GraphStore>(
+    store: &mut S,
+    id: &str,
+    params: &HnswParams,
+    similarity: SimilarityFn,
+) -> bool {
+    let Some(node) = store.node(id) else { return false };
+    store.remove_node(id);
+
+    // Unlink the node and reconnect its neighbours with each other so the
+    // graph stays navigable around the hole
+    for (layer, links) in node.neighbors.iter().enumerate() {
+        for neighbor_id in links {
+            let Some(mut neighbor) = store.node(neighbor_id) else { continue };
+            if layer >= neighbor.neighbors.len() {
+                continue;
+            }
+
+            neighbor.neighbors[layer].retain(|n| n != id);
+
+            let mut candidates = neighbor.neighbors[layer].clone();
+            for other in links {
+                if other != neighbor_id && !candidates.contains(other) {
+                    candidates.push(other.clone());
+                }
+            }
+
+            if candidates.len() > params.max_links(layer) {
+                if let Some(neighbor_vector) = store.vector(neighbor_id) {
+                    candidates = select_closest(store, &neighbor_vector, candidates, params.max_links(layer), similarity);
+                } else {
+                    candidates.truncate(params.max_links(layer));
+                }
+            }
+
+            neighbor.neighbors[layer] = candidates;
+            store.put_node(neighbor_id, neighbor);
+        }
+    }
+
+    if store.entry_point().map(|ep| ep.id == id).unwrap_or(false) {
+        let replacement = node
+            .neighbors
+            .iter()
+            .rev()
+            .flatten()
+            .filter_map(|neighbor_id| store.node(neighbor_id).map(|n| (neighbor_id.clone(), n.level())))
+            .max_by_key(|(_, level)| *level)
+            .or_else(|| store.any_node().map(|(id, n)| (id, n.level())));
+
+        store.set_entry_point(replacement.map(|(id, level)| EntryPoint { id, level }));
+    }
+
+    true
+}
+
+pub fn search<S: GraphStore>(
+    store: &S,
+    query: &[f32],
+    k: usize,
+    ef: usize,
+    similarity: SimilarityFn,
+) -> Vec<(String, f32)> {
+    let Some(entry_point) = store.entry_point() else { return Vec::new() };
+    let Some(entry_vector) = store.vector(&entry_point.id) else { return Vec::new() };
+
+    let mut nearest = vec![Candidate {
+        similarity: similarity(query, &entry_vector),
+        id: entry_point.id.clone(),
+    }];
+
+    for layer in (1..=entry_point.level).rev() {
+        nearest = search_layer(store, query, nearest, 1, layer, similarity);
+    }
+
+    search_layer(store, query, nearest, ef.max(k), 0, similarity)
+        .into_iter()
+        .take(k)
+        .map(|c| (c.id, c.similarity))
+        .collect()
+}
+
+// Best-first search on a single layer; returns up to `ef` candidates sorted by
+// similarity, highest first.
+fn search_layer<S: GraphStore>(
+    store: &S,
+    query: &[f32],
+    entry_points: Vec<Candidate>,
+    ef: usize,
+    layer: usize,
+    similarity: SimilarityFn,
+) -> Vec<Candidate> {
+    let mut visited: HashSet<String> = entry_points.iter().map(|c| c.id.clone()).collect();
+    let mut candidates: BinaryHeap<Candidate> = entry_points.iter().cloned().collect();
+    let mut results: BinaryHeap<std::cmp::Reverse<Candidate>> =
+        entry_points.into_iter().map(std::cmp::Reverse).collect();
+
+    while results.len() > ef {
+        results.pop();
+    }
+
+    while let Some(current) = candidates.pop() {
+        let worst = results.peek().map(|r| r.0.similarity).unwrap_or(f32::NEG_INFINITY);
+        if current.similarity < worst && results.len() >= ef {
+            break;
+        }
+
+        let Some(node) = store.node(&current.id) else { continue };
+        let Some(links) = node.neighbors.get(layer) else { continue };
+
+        for neighbor_id in links {
+            if !visited.insert(neighbor_id.clone()) {
+                continue;
+            }
+
+            // Links may point at nodes whose vector was removed; skip them
+            let Some(neighbor_vector) = store.vector(neighbor_id) else { continue };
+            let candidate = Candidate {
+                similarity: similarity(query, &neighbor_vector),
+                id: neighbor_id.clone(),
+            };
+
+            let worst = results.peek().map(|r| r.0.similarity).unwrap_or(f32::NEG_INFINITY);
+            if results.len() < ef || candidate.similarity > worst {
+                candidates.push(candidate.clone());
+                results.push(std::cmp::Reverse(candidate));
+                if results.len() > ef {
+                    results.pop();
+                }
+            }
+        }
+    }
+
+    let mut sorted: Vec<Candidate> = results.into_iter().map(|r| r.0).collect();
+    sorted.sort_by(|a, b| b.cmp(a));
+    sorted
+}
+
+fn select_closest<S: GraphStore>(
+    store: &S,
+    base: &[f32],
+    ids: Vec<String>,
+    limit: usize,
+    similarity: SimilarityFn,
+) -> Vec<String> {
+    let mut vectors: HashMap<String, f32> = HashMap::new();
+    for id in ids {
+        if let Some(v) = store.vector(&id) {
+            vectors.insert(id, similarity(base, &v));
+        }
+    }
+
+    let mut scored: Vec<Candidate> = vectors
+        .into_iter()
+        .map(|(id, similarity)| Candidate { similarity, id })
+        .collect();
+    scored.sort_by(|a, b| b.cmp(a));
+    scored.into_iter().take(limit).map(|c| c.id).collect()
+}
+
+#[derive(Clone, Debug)]
+struct Candidate {
+    similarity: f32,
+    id: String,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MapStore {
+        nodes: HashMap<String, HnswNode>,
+        vectors: HashMap<String, Vec<f32>>,
+        entry_point: Option<EntryPoint>,
+    }
+
+    impl GraphStore for MapStore {
+        fn node(&self, id: &str) -> Option<HnswNode> {
+            self.nodes.get(id).cloned()
+        }
+        fn put_node(&mut self, id: &str, node: HnswNode) {
+            self.nodes.insert(id.to_string(), node);
+        }
+        fn remove_node(&mut self, id: &str) {
+            self.nodes.remove(id);
+        }
+        fn vector(&self, id: &str) -> Option<Vec<f32>> {
+            self.vectors.get(id).cloned()
+        }
+        fn entry_point(&self) -> Option<EntryPoint> {
+            self.entry_point.clone()
+        }
+        fn set_entry_point(&mut self, entry_point: Option<EntryPoint>) {
+            self.entry_point = entry_point;
+        }
+        fn any_node(&self) -> Option<(String, HnswNode)> {
+            self.nodes
+                .iter()
+                .filter(|(k, _)| self.vectors.contains_key(*k))
+                .min_by_key(|(k, _)| k.as_str())
+                .map(|(k, v)| (k.clone(), v.clone()))
+        }
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let na: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let nb: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if na == 0.0 || nb == 0.0 { 0.0 } else { dot / (na * nb) }
+    }
+
+    fn random_vectors(count: usize, dim: usize) -> Vec<Vec<f32>> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        (0..count)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        (state % 2000) as f32 / 1000.0 - 1.0
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn build(count: usize, dim: usize, params: &HnswParams) -> MapStore {
+        let mut store = MapStore::default();
+        for (i, v) in random_vectors(count, dim).into_iter().enumerate() {
+            let id = format!("mem_{}", i);
+            store.vectors.insert(id.clone(), v.clone());
+            insert(&mut store, &id, &v, params, &cosine);
+        }
+        store
+    }
+
+    fn exact_top_k(store: &MapStore, query: &[f32], k: usize) -> Vec<String> {
+        let mut scored: Vec<(String, f32)> = store
+            .vectors
+            .iter()
+            .map(|(id, v)| (id.clone(), cosine(query, v)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(id, _)| id).collect()
+    }
+
+    const PARAMS: HnswParams = HnswParams { m: 8, ef_construction: 64 };
+    const EF_SEARCH: usize = 64;
+
+    #[test]
+    fn test_search_finds_exact_match() {
+        let store = build(200, 16, &PARAMS);
+        let query = store.vectors["mem_42"].clone();
+        let results = search(&store, &query, 1, EF_SEARCH, &cosine);
+        assert_eq!(results[0].0, "mem_42");
+        assert!((results[0].1 - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_recall_against_exact_scan() {
+        let store = build(500, 16, &PARAMS);
+        let queries = random_vectors(520, 16).split_off(500);
+
+        let mut hits = 0;
+        for query in &queries {
+            let expected = exact_top_k(&store, query, 10);
+            let found: Vec<String> = search(&store, query, 10, EF_SEARCH, &cosine)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            hits += expected.iter().filter(|id| found.contains(id)).count();
+        }
+
+        let recall = hits as f32 / (queries.len() * 10) as f32;
+        assert!(recall > 0.9, "recall too low: {}", recall);
+    }
+
+    #[test]
+    fn test_remove_keeps_graph_searchable() {
+        let mut store = build(100, 8, &PARAMS);
+        for i in 0..50 {
+            let id = format!("mem_{}", i);
+            assert!(remove(&mut store, &id, &PARAMS, &cosine));
+            store.vectors.remove(&id);
+        }
+
+        let entry = store.entry_point().expect("entry point should survive removals");
+        assert!(store.nodes.contains_key(&entry.id));
+
+        let query = store.vectors["mem_77"].clone();
+        let results = search(&store, &query, 5, EF_SEARCH, &cosine);
+        assert_eq!(results[0].0, "mem_77");
+        assert!(results.iter().all(|(id, _)| store.vectors.contains_key(id)));
+    }
+
+    #[test]
+    fn test_insert_after_entry_point_lost_its_vector() {
+        let mut store = build(100, 8, &PARAMS);
+        let lost = store.entry_point().unwrap().id;
+        store.vectors.remove(&lost);
+
+        let extra = random_vectors(101, 8).pop().unwrap();
+        store.vectors.insert("extra".to_string(), extra.clone());
+        insert(&mut store, "extra", &extra, &PARAMS, &cosine);
+
+        // The existing nodes are still reachable from the new entry point
+        let entry = store.entry_point().unwrap();
+        assert_ne!(entry.id, lost);
+        assert_ne!(entry.id, "extra");
+        for id in ["mem_3", "mem_64", "extra"] {
+            let query = store.vectors[id].clone();
+            assert_eq!(search(&store, &query, 1, EF_SEARCH, &cosine)[0].0, id);
+        }
+    }
+
+    #[test]
+    fn test_remove_last_node_clears_entry_point() {
+        let mut store = build(1, 4, &PARAMS);
+        assert!(remove(&mut store, "mem_0", &PARAMS, &cosine));
+        assert!(store.entry_point().is_none());
+        assert!(!remove(&mut store, "mem_0", &PARAMS, &cosine));
+    }
+
+    #[test]
+    fn test_assign_level_is_deterministic() {
+        assert_eq!(assign_level("abc", 16), assign_level("abc", 16));
+        let levels: Vec<usize> = (0..1000).map(|i| assign_level(&format!("id_{}", i), 16)).collect();
+        let ground = levels.iter().filter(|&&l| l == 0).count();
+        assert!(ground > 850, "most nodes should live only on layer 0");
+    }
+}
@@ -12,81 +12,21 @@ pub fn handle_http_request(req: HttpRequest) -> HttpResponse {
     
     ic_cdk::println!("HTTP Request: {} {}", method, path);
     
-    match (method.as_str(), path.as_str()) {
-        ("GET", "/health") => handle_health_check(),
-        ("GET", "/stats") => handle_stats(),
-        ("GET", "/stats/vectors") => handle_vector_stats(),
-        ("GET", "/auth/sessions") => handle_list_sessions(),
-        ("GET", path) if path.starts_with("/suggestions") => handle_get_suggestions(&req),
-        ("GET", path) if path.starts_with("/clusters") => handle_get_clusters(&req),
-        ("GET", "/categories") => handle_get_categories(),
-        ("GET", path) if path.starts_with("/memories/search") => {
-            // Semantic search requires async, so return an error for now
-            // In a real implementation, this would need to be restructured
-            error_response(501, "Use /memories/search via POST for semantic search")
-        },
-        ("GET", path) if path.starts_with("/memories/") => handle_get_memory(&req),
-        ("GET", "/memories") => handle_list_memories(&req),
-        ("GET", path) if path.starts_with("/test-auth") => handle_test_auth(&req),
-        ("GET", path) if path.starts_with("/quick-memory") => handle_quick_memory(&req),
-        ("GET", path) if path.starts_with("/save-memory") => handle_save_memory_get(&req),
-        ("GET", path) if path.starts_with("/simple-memory") => handle_simple_memory_save(&req),
-        ("POST", "/conversations") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("GET", "/conversations") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("POST", "/memories") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("POST", "/memories/search") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("POST", "/config") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("POST", "/config/openai-key") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("GET", "/config") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("DELETE", path) if path.starts_with("/config/") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("DELETE", path) if path.starts_with("/memories/") => HttpResponse {
-            status_code: 204,
-            headers: create_cors_headers(),
-            body: Vec::new(),
-            upgrade: Some(true),
-        },
-        ("OPTIONS", _) => handle_cors_preflight(),
-        _ => error_response(404, "Not found"),
+    // Query answers come from a single replica, so only certified responses
+    // are served here; everything else is upgraded to an update call
+    match method.as_str() {
+        "OPTIONS" => handle_cors_preflight(),
+        "GET" => crate::certification::serve(&path).unwrap_or_else(upgrade_response),
+        _ => upgrade_response(),
+    }
+}
+
+fn upgrade_response() -> HttpResponse {
+    HttpResponse {
+        status_code: 204,
+        headers: create_cors_headers(),
+        body: Vec::new(),
+        upgrade: Some(true),
     }
 }
 
@@ -96,12 +36,39 @@ pub async fn handle_http_request_update(req: HttpRequest) -> HttpResponse {
     
     ic_cdk::println!("HTTP Update Request: {} {}", method, path);
     
+    // Internet Identity login is how a caller obtains credentials in the first place
+    if method == "POST" && path == "/auth/ii" {
+        return handle_ii_login(&req).await;
+    }
+    
+    // Public routes and routes that check their own API key
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/health") => return handle_health_check(),
+        ("GET", "/stats") => return handle_stats(),
+        ("GET", "/stats/vectors") => return handle_vector_stats(),
+        ("GET", path) if path.starts_with("/suggestions") => return handle_get_suggestions(&req),
+        ("GET", path) if path.starts_with(crate::certification::DIGEST_PATH_PREFIX) => return handle_listing_digest(path),
+        ("GET", path) if path.starts_with("/test-auth") => return handle_test_auth(&req),
+        ("GET", path) if path.starts_with("/quick-memory") => return handle_quick_memory(&req),
+        ("GET", path) if path.starts_with("/save-memory") => return handle_save_memory_get(&req),
+        ("GET", path) if path.starts_with("/simple-memory") => return handle_simple_memory_save(&req),
+        _ => {}
+    }
+    
     // Authenticate user for all update operations (supports both Bearer tokens and Internet Identity)
-    let user = match authenticate_request(&req).await {
-        Ok(user) => user,
+    let auth = match authenticate_request(&req).await {
+        Ok(auth) => auth,
         Err(e) => return error_response(401, &format!("Authentication failed: {}", e)),
     };
     
+    // Scoped tokens may only reach the routes their permissions cover
+    if let Some(permission) = required_permission(method.as_str(), path.as_str()) {
+        if let Err(e) = auth.require(permission) {
+            return error_response_from_error(e);
+        }
+    }
+    let user = auth.principal;
+    
     match (method.as_str(), path.as_str()) {
         ("POST", "/memories/search") => handle_semantic_search(&req, user).await,
         ("POST", "/memories") => handle_add_memory(&req, user).await,
@@ -112,16 +79,80 @@ pub async fn handle_http_request_update(req: HttpRequest) -> HttpResponse {
         ("POST", "/config/openai-key") => handle_set_openai_key(&req, user).await,
         ("GET", "/config") => handle_get_config(&req, user).await,
         ("DELETE", "/config/openai-key") => handle_delete_openai_key(&req, user).await,
-        ("DELETE", path) if path.starts_with("/memories/") => handle_delete_memory(&req, user).await,
+        ("POST", "/embeddings/reembed") => handle_start_reembed(user),
+        ("GET", "/embeddings/reembed") => handle_get_reembed(user),
+        ("DELETE", "/embeddings/reembed") => handle_cancel_reembed(user),
+        ("POST", "/clusters/compute") => handle_compute_clusters(&req, user),
+        ("GET", "/clusters") => handle_get_clusters(&req, user),
+        ("POST", "/collections") => handle_create_collection(&req, user),
+        ("GET", "/collections") => handle_list_collections(&req, user),
+        ("GET", path) if path.starts_with("/collections/") && path.ends_with("/memories") => handle_list_collection_memories(&req, user),
+        ("POST", path) if path.starts_with("/collections/") && path.ends_with("/memories") => handle_add_collection_memories(&req, user),
+        ("DELETE", path) if path.starts_with("/collections/") && path.contains("/memories/") => handle_remove_collection_memory(&req, user),
+        ("GET", path) if path.starts_with("/collections/") => handle_get_collection(&req, user),
+        ("PATCH", path) | ("PUT", path) if path.starts_with("/collections/") => handle_update_collection(&req, user),
+        ("DELETE", path) if path.starts_with("/collections/") => handle_delete_collection(&req, user),
+        ("GET", "/categories") => handle_get_categories(user),
+        ("POST", "/categories") => handle_create_category(&req, user),
+        ("GET", path) if path.starts_with("/categories/") => handle_get_category(&req, user),
+        ("PATCH", path) | ("PUT", path) if path.starts_with("/categories/") => handle_update_category(&req, user),
+        ("DELETE", path) if path.starts_with("/categories/") => handle_delete_category(&req, user),
         ("POST", "/memories/bulk") => handle_bulk_add(&req, user).await,
         ("DELETE", "/memories/bulk") => handle_bulk_delete(&req, user).await,
-        ("POST", "/auth/tokens") => handle_create_token(&req, user).await,
+        ("PUT", path) if path.starts_with("/memories/") => handle_update_memory(&req, user, false).await,
+        ("PATCH", path) if path.starts_with("/memories/") => handle_update_memory(&req, user, true).await,
+        ("GET", "/memories/search") => handle_search_get(&req, user).await,
+        ("GET", path) if path.starts_with("/memories/") && path.ends_with("/revisions") => handle_list_memory_revisions(&req, user).await,
+        ("GET", path) if path.starts_with("/memories/") => handle_get_memory(&req, user),
+        ("GET", "/memories") => handle_list_memories(&req, user),
+        ("POST", path) if path.starts_with("/memories/") && path.ends_with("/restore") => handle_restore_memory_revision(&req, user).await,
+        ("DELETE", path) if path.starts_with("/memories/") => handle_delete_memory(&req, user).await,
+        ("POST", "/auth/tokens") => handle_create_token(&req, &auth).await,
         ("GET", "/auth/tokens") => handle_list_user_tokens(&req, user).await,
         ("DELETE", path) if path.starts_with("/auth/tokens/") => handle_revoke_token(&req, user).await,
+        ("GET", "/auth/sessions") => handle_list_sessions(&req, user),
+        ("DELETE", "/auth/sessions") => handle_revoke_all_sessions(user),
+        ("DELETE", path) if path.starts_with("/auth/sessions/") => handle_revoke_session(&req, user),
+        ("POST", "/auth/logout") => handle_logout(&req),
+        ("POST", "/auth/api-keys") => handle_create_api_key(&req, &auth).await,
+        ("GET", "/auth/api-keys") => handle_list_api_keys(&req, user).await,
+        ("DELETE", path) if path.starts_with("/auth/api-keys/") => handle_revoke_api_key(&req, user).await,
         _ => error_response(404, "Not found"),
     }
 }
 
+// Permission each update route requires; None for unknown routes, which fall through to 404
+fn required_permission(method: &str, path: &str) -> Option<Permission> {
+    let permission = match (method, path) {
+        ("POST", "/memories/search") | ("GET", "/conversations") => Permission::Read,
+        ("GET", path) if path.starts_with("/memories") => Permission::Read,
+        ("GET", "/clusters") => Permission::Read,
+        ("GET", path) if path.starts_with("/collections") => Permission::Read,
+        ("DELETE", path) if path.starts_with("/collections/") && !path.contains("/memories/") => Permission::Delete,
+        ("POST", path) | ("PATCH", path) | ("PUT", path) | ("DELETE", path) if path.starts_with("/collections") => Permission::Write,
+        ("GET", path) if path.starts_with("/categories") => Permission::Read,
+        ("DELETE", path) if path.starts_with("/categories/") => Permission::Delete,
+        ("POST", "/categories") => Permission::Write,
+        ("PATCH", path) | ("PUT", path) if path.starts_with("/categories/") => Permission::Write,
+        ("POST", "/memories") | ("POST", "/simple-memories") | ("POST", "/conversations") | ("POST", "/memories/bulk") => Permission::Write,
+        ("POST", "/clusters/compute") => Permission::Write,
+        ("PUT", path) | ("PATCH", path) if path.starts_with("/memories/") => Permission::Write,
+        ("POST", path) if path.starts_with("/memories/") && path.ends_with("/restore") => Permission::Write,
+        ("DELETE", "/memories/bulk") => Permission::Delete,
+        ("DELETE", path) if path.starts_with("/memories/") => Permission::Delete,
+        ("GET", "/config") | ("POST", "/config") | ("POST", "/config/openai-key") | ("DELETE", "/config/openai-key") => Permission::ManageConfig,
+        (_, "/embeddings/reembed") => Permission::ManageConfig,
+        ("POST", "/auth/tokens") | ("GET", "/auth/tokens") => Permission::ManageConfig,
+        ("DELETE", path) if path.starts_with("/auth/tokens/") => Permission::ManageConfig,
+        ("POST", "/auth/api-keys") | ("GET", "/auth/api-keys") => Permission::ManageConfig,
+        ("GET", "/auth/sessions") => Permission::ManageConfig,
+        ("DELETE", path) if path.starts_with("/auth/sessions") => Permission::ManageConfig,
+        ("DELETE", path) if path.starts_with("/auth/api-keys/") => Permission::ManageConfig,
+        _ => return None,
+    };
+    Some(permission)
+}
+
 fn handle_health_check() -> HttpResponse {
     let health = HealthResponse {
         status: "healthy".to_string(),
@@ -151,13 +182,15 @@ fn handle_vector_stats() -> HttpResponse {
     let response = json!({
         "vector_statistics": vector_stats,
         "vector_configuration": vector_config,
+        "embedding_outcalls": crate::embedding::get_outcall_stats(),
+        "query_embedding_cache": crate::embedding_cache::EmbeddingCache::get_stats(),
         "timestamp": time()
     });
     
     success_response(&response, 200)
 }
 
-fn handle_get_memory(req: &HttpRequest) -> HttpResponse {
+fn handle_get_memory(req: &HttpRequest, user: Principal) -> HttpResponse {
     let path = extract_path(&req.url);
     let memory_id = path.strip_prefix("/memories/").unwrap_or("");
     
@@ -165,14 +198,15 @@ fn handle_get_memory(req: &HttpRequest) -> HttpResponse {
         return error_response(400, "Memory ID is required");
     }
     
+    // Other users' memories are reported as missing
     match get_memory(memory_id) {
-        Ok(Some(memory)) => success_response(&memory, 200),
-        Ok(None) => error_response(404, "Memory not found"),
+        Ok(Some(memory)) if memory.user_id == user => success_response(&memory, 200),
+        Ok(_) => error_response(404, "Memory not found"),
         Err(e) => error_response(500, &format!("Failed to get memory: {}", e)),
     }
 }
 
-fn handle_list_memories(req: &HttpRequest) -> HttpResponse {
+fn handle_list_memories(req: &HttpRequest, user: Principal) -> HttpResponse {
     let query_params = parse_query_params(&req.url);
     
     let limit: usize = query_params
@@ -186,18 +220,39 @@ fn handle_list_memories(req: &HttpRequest) -> HttpResponse {
         .and_then(|o| o.parse().ok())
         .unwrap_or(0);
     
-    let memories = list_memories(offset, limit, None).unwrap_or_default();
+    let memories = list_user_memories(user, offset, limit).unwrap_or_default();
+    let (digest, digest_path) = crate::certification::user_digest(user);
     
+    // The digest can be checked against the certified response at digest_path
     let response = json!({
         "memories": memories,
         "limit": limit,
         "offset": offset,
-        "total_count": get_memory_count()
+        "total_count": get_user_memory_count(user),
+        "digest": digest,
+        "digest_path": digest_path
     });
     
     success_response(&response, 200)
 }
 
+// Uncertified fallback for a query that arrived without a certificate
+fn handle_listing_digest(path: &str) -> HttpResponse {
+    let listing = path
+        .strip_prefix(crate::certification::DIGEST_PATH_PREFIX)
+        .and_then(find_listing_digest);
+    
+    let Some(listing) = listing else {
+        return error_response(404, "Digest not found");
+    };
+    
+    let response = json!({
+        "digest": listing.digest,
+        "certified_at": null
+    });
+    success_response(&response, 200)
+}
+
 fn handle_cors_preflight() -> HttpResponse {
     HttpResponse {
         status_code: 200,
@@ -207,16 +262,88 @@ fn handle_cors_preflight() -> HttpResponse {
     }
 }
 
-fn handle_list_sessions() -> HttpResponse {
-    // Return empty sessions for now
+async fn handle_ii_login(req: &HttpRequest) -> HttpResponse {
+    let body = match std::str::from_utf8(&req.body) {
+        Ok(body) => body,
+        Err(_) => return error_response(400, "Invalid UTF-8 in request body"),
+    };
+    
+    match crate::internet_identity::authenticate_with_ii(body).await {
+        Ok((session_key, session)) => {
+            let cookie = crate::internet_identity::session_cookie(&session_key, session.absolute_expires_at, session.created_at);
+            let response = json!({
+                "principal": session.user_principal.to_text(),
+                "session_key": session_key,
+                "expires_at": session.expires_at,
+                "absolute_expires_at": session.absolute_expires_at
+            });
+            let mut http_response = success_response(&response, 200);
+            http_response.headers.push(("Set-Cookie".to_string(), cookie));
+            http_response
+        }
+        Err(e) => error_response_from_error(crate::errors::OpenMemoryError::Authentication {
+            message: e,
+            auth_type: "internet_identity".to_string(),
+        }),
+    }
+}
+
+fn handle_list_sessions(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let current = crate::internet_identity::extract_ii_session(req);
+    let sessions = crate::internet_identity::list_user_sessions(user, current.as_deref());
     let response = json!({
-        "sessions": [],
-        "count": 0
+        "count": sessions.len(),
+        "sessions": sessions
     });
     
     success_response(&response, 200)
 }
 
+fn handle_revoke_session(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let path = extract_path(&req.url);
+    let session_id = path.strip_prefix("/auth/sessions/").unwrap_or("");
+    
+    if session_id.is_empty() {
+        return error_response(400, "Session ID is required");
+    }
+    
+    if crate::internet_identity::revoke_user_session(user, session_id) {
+        let response = json!({
+            "success": true,
+            "message": "Session revoked successfully"
+        });
+        success_response(&response, 200)
+    } else {
+        error_response(404, "Session not found")
+    }
+}
+
+// Log out everywhere
+fn handle_revoke_all_sessions(user: Principal) -> HttpResponse {
+    let revoked = crate::internet_identity::revoke_all_user_sessions(user);
+    let response = json!({
+        "success": true,
+        "revoked": revoked
+    });
+    let mut http_response = success_response(&response, 200);
+    http_response.headers.push(("Set-Cookie".to_string(), crate::internet_identity::clear_session_cookie()));
+    http_response
+}
+
+// End the session this request was made with
+fn handle_logout(req: &HttpRequest) -> HttpResponse {
+    let revoked = crate::internet_identity::extract_ii_session(req)
+        .map(|session_key| crate::internet_identity::revoke_session(&session_key))
+        .unwrap_or(false);
+    let response = json!({
+        "success": true,
+        "revoked": revoked
+    });
+    let mut http_response = success_response(&response, 200);
+    http_response.headers.push(("Set-Cookie".to_string(), crate::internet_identity::clear_session_cookie()));
+    http_response
+}
+
 fn handle_get_suggestions(req: &HttpRequest) -> HttpResponse {
     let query_params = parse_query_params(&req.url);
     
@@ -227,14 +354,11 @@ fn handle_get_suggestions(req: &HttpRequest) -> HttpResponse {
         .unwrap_or(10)
         .min(20); // Max 20 suggestions
     
-    // Extract user from headers if available (for personalized suggestions)
+    // Personalize for callers presenting an access token that may read memories
     let user = extract_bearer_token(&req.headers)
-        .and_then(|token| {
-            // Try to verify token to get user
-            futures::executor::block_on(async {
-                crate::auth::verify_token(&token).await.ok()
-            })
-        });
+        .and_then(|token| crate::storage::verify_access_token(&token).ok())
+        .filter(|token| token.permissions.contains(&Permission::Read))
+        .map(|token| token.owner_principal);
     
     let suggestions = crate::suggestions::SuggestionsEngine::get_suggestions(
         user, 
@@ -251,7 +375,65 @@ fn handle_get_suggestions(req: &HttpRequest) -> HttpResponse {
     success_response(&response, 200)
 }
 
-fn handle_get_clusters(req: &HttpRequest) -> HttpResponse {
+fn handle_compute_clusters(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let request: ComputeClustersRequest = if req.body.is_empty() {
+        ComputeClustersRequest::default()
+    } else {
+        match serde_json::from_slice(&req.body) {
+            Ok(request) => request,
+            Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+        }
+    };
+    
+    let method = match request.method.as_deref() {
+        Some(name) => match crate::clustering::ClusteringMethod::from_name(name) {
+            Some(method) => method,
+            None => return error_response(400, &format!("Unknown clustering method: {}", name)),
+        },
+        None => crate::clustering::ClusteringMethod::KMeans,
+    };
+    let time_period = match request.time_period.as_deref() {
+        Some(name) => match crate::clustering::TimePeriod::from_name(name) {
+            Some(period) => Some(period),
+            None => return error_response(400, &format!("Unknown time period: {}", name)),
+        },
+        None => None,
+    };
+    if request.k.is_some_and(|k| k == 0 || k > crate::clustering::MAX_K) {
+        return error_response(400, &format!("k must be between 1 and {}", crate::clustering::MAX_K));
+    }
+    if request.eps.is_some_and(|eps| !(eps > 0.0 && eps <= 2.0)) {
+        return error_response(400, "eps must be a cosine distance between 0 and 2");
+    }
+    if request.min_points == Some(0) {
+        return error_response(400, "min_points must be at least 1");
+    }
+    if request.similarity_threshold.is_some_and(|threshold| !(-1.0..=1.0).contains(&threshold)) {
+        return error_response(400, "similarity_threshold must be between -1 and 1");
+    }
+    
+    let options = crate::clustering::ClusteringOptions {
+        k: request.k,
+        time_period,
+        eps: request.eps,
+        min_points: request.min_points,
+        similarity_threshold: request.similarity_threshold,
+        seed: request.seed,
+    };
+    match crate::clustering::ClusteringEngine::compute_clusters(user, method, options) {
+        Ok(result) => {
+            let mut response = clusters_json(user, 1, member_page(req));
+            // The dendrogram can be cut again client-side at another threshold
+            if let Some(dendrogram) = result.dendrogram {
+                response["dendrogram"] = json!(dendrogram);
+            }
+            success_response(&response, 201)
+        }
+        Err(e) => error_response(400, &e),
+    }
+}
+
+fn handle_get_clusters(req: &HttpRequest, user: Principal) -> HttpResponse {
     let query_params = parse_query_params(&req.url);
     
     let min_cluster_size: usize = query_params
@@ -259,38 +441,301 @@ fn handle_get_clusters(req: &HttpRequest) -> HttpResponse {
         .and_then(|s| s.parse().ok())
         .unwrap_or(2);
     
-    // Get stored clusters (placeholder for now)
-    let clusters: Vec<crate::clustering::MemoryCluster> = vec![];
+    success_response(&clusters_json(user, min_cluster_size, member_page(req)), 200)
+}
+
+// Member memories that still exist and belong to the cluster's owner
+fn cluster_memories(cluster: &crate::clustering::MemoryCluster) -> Vec<Memory> {
+    cluster
+        .memory_ids
+        .iter()
+        .filter_map(|id| get_memory(id).ok().flatten())
+        .filter(|memory| memory.user_id == cluster.user_id)
+        .collect()
+}
+
+// Characters of content shown for each member of a cluster or collection
+const MEMBER_PREVIEW_CHARS: usize = 200;
+
+// Which members a cluster or collection response lists in full
+#[derive(Clone, Copy)]
+struct MemberPage {
+    offset: usize,
+    limit: usize,
+}
+
+// `member_offset` / `member_limit` (at most 100, 20 by default)
+fn member_page(req: &HttpRequest) -> MemberPage {
+    let query_params = parse_query_params(&req.url);
+    MemberPage {
+        offset: query_params
+            .get("member_offset")
+            .and_then(|o| o.parse().ok())
+            .unwrap_or(0),
+        limit: query_params
+            .get("member_limit")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(20)
+            .min(100),
+    }
+}
+
+// A member without its embedding, with the content shortened to a preview
+fn memory_summary(memory: &Memory) -> serde_json::Value {
+    let truncated = memory.content.chars().count() > MEMBER_PREVIEW_CHARS;
+    let preview: String = memory.content.chars().take(MEMBER_PREVIEW_CHARS).collect();
+    json!({
+        "id": memory.id,
+        "content_preview": preview,
+        "truncated": truncated,
+        "tags": memory.tags,
+        "created_at": memory.created_at,
+        "updated_at": memory.updated_at,
+    })
+}
+
+fn cluster_json(cluster: &crate::clustering::MemoryCluster, memories: &[Memory], page: MemberPage) -> serde_json::Value {
+    let memory_ids: Vec<&String> = memories.iter().map(|memory| &memory.id).collect();
+    let members: Vec<serde_json::Value> = memories
+        .iter()
+        .skip(page.offset)
+        .take(page.limit)
+        .map(memory_summary)
+        .collect();
+    json!({
+        "id": cluster.id,
+        "name": cluster.name,
+        "theme": cluster.name,
+        "description": cluster.description,
+        "cluster_type": cluster.cluster_type,
+        "memory_ids": memory_ids,
+        "memory_count": memories.len(),
+        "memories": members,
+        "member_offset": page.offset,
+        "member_limit": page.limit,
+        "tags": cluster.tags,
+        "created_at": cluster.created_at,
+        "updated_at": cluster.updated_at,
+    })
+}
+
+// The caller's stored clusters with a page of member summaries. Memories
+// deleted since the clusters were computed are left out.
+fn clusters_json(user: Principal, min_cluster_size: usize, page: MemberPage) -> serde_json::Value {
+    let clustering = crate::clustering::ClusteringEngine::get_user_clustering(user).unwrap_or_default();
+    
+    // Collections are curated, so they are listed whatever their size
+    let clusters: Vec<serde_json::Value> = crate::clustering::ClusteringEngine::get_user_clusters(user)
+        .iter()
+        .filter_map(|cluster| {
+            let memories = cluster_memories(cluster);
+            let curated = cluster.cluster_type == crate::clustering::ClusterType::Manual;
+            if !curated && memories.len() < min_cluster_size {
+                return None;
+            }
+            Some(cluster_json(cluster, &memories, page))
+        })
+        .collect();
     
-    // Filter by minimum size
-    let filtered_clusters: Vec<_> = clusters
-        .into_iter()
-        .filter(|cluster| cluster.memory_ids.len() >= min_cluster_size)
+    let unclustered_memories = clustering
+        .unclustered_memories
+        .iter()
+        .filter(|id| matches!(get_memory(id), Ok(Some(_))))
+        .count();
+    
+    json!({
+        "clusters": clusters,
+        "total_clusters": clusters.len(),
+        "unclustered_memories": unclustered_memories,
+        "method": clustering.method,
+        "clustering_score": clustering.clustering_score,
+        "computed_at": clustering.computed_at,
+        "min_cluster_size": min_cluster_size
+    })
+}
+
+// `/collections/{id}` and `/collections/{id}/memories[/{memory_id}]`
+fn collection_path(req: &HttpRequest) -> (String, Option<String>) {
+    let path = extract_path(&req.url);
+    let rest = path.strip_prefix("/collections/").unwrap_or("");
+    let mut parts = rest.splitn(3, '/');
+    let collection_id = parts.next().unwrap_or("").to_string();
+    let memory_id = match (parts.next(), parts.next()) {
+        (Some("memories"), Some(memory_id)) if !memory_id.is_empty() => Some(memory_id.to_string()),
+        _ => None,
+    };
+    (collection_id, memory_id)
+}
+
+fn collection_response(req: &HttpRequest, collection: &crate::clustering::MemoryCluster, status: u16) -> HttpResponse {
+    success_response(&cluster_json(collection, &cluster_memories(collection), member_page(req)), status)
+}
+
+fn handle_create_collection(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let request: CreateCollectionRequest = match serde_json::from_slice(&req.body) {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+    if let Err(e) = crate::validation::validate_collection_fields(Some(&request.name), request.description.as_deref()) {
+        return error_response_from_error(e);
+    }
+    
+    match crate::clustering::ClusteringEngine::create_collection(
+        user,
+        request.name.trim().to_string(),
+        request.description.unwrap_or_default(),
+        request.memory_ids.unwrap_or_default(),
+    ) {
+        Ok(collection) => collection_response(req, &collection, 201),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_list_collections(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let page = member_page(req);
+    let collections: Vec<serde_json::Value> = crate::clustering::ClusteringEngine::get_user_collections(user)
+        .iter()
+        .map(|collection| cluster_json(collection, &cluster_memories(collection), page))
         .collect();
     
     let response = json!({
-        "clusters": filtered_clusters,
-        "total_clusters": filtered_clusters.len(),
-        "min_cluster_size": min_cluster_size
+        "collections": collections,
+        "total_collections": collections.len()
     });
+    success_response(&response, 200)
+}
+
+fn handle_get_collection(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let (collection_id, _) = collection_path(req);
+    match crate::clustering::ClusteringEngine::get_collection(user, &collection_id) {
+        Ok(collection) => collection_response(req, &collection, 200),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_update_collection(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let (collection_id, _) = collection_path(req);
+    let request: UpdateCollectionRequest = match serde_json::from_slice(&req.body) {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+    if let Err(e) = crate::validation::validate_collection_fields(request.name.as_deref(), request.description.as_deref()) {
+        return error_response_from_error(e);
+    }
+    
+    match crate::clustering::ClusteringEngine::update_collection(
+        user,
+        &collection_id,
+        request.name.map(|name| name.trim().to_string()),
+        request.description,
+    ) {
+        Ok(collection) => collection_response(req, &collection, 200),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_delete_collection(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let (collection_id, _) = collection_path(req);
+    match crate::clustering::ClusteringEngine::delete_collection(user, &collection_id) {
+        Ok(()) => {
+            // Only the collection goes; its memories are kept
+            let response = json!({
+                "deleted": true,
+                "message": "Collection deleted successfully"
+            });
+            success_response(&response, 200)
+        }
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_list_collection_memories(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let (collection_id, _) = collection_path(req);
+    let query_params = parse_query_params(&req.url);
+    let limit: usize = query_params
+        .get("limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(50)
+        .min(100);
+    let offset: usize = query_params
+        .get("offset")
+        .and_then(|o| o.parse().ok())
+        .unwrap_or(0);
     
+    let collection = match crate::clustering::ClusteringEngine::get_collection(user, &collection_id) {
+        Ok(collection) => collection,
+        Err(e) => return error_response_from_error(e),
+    };
+    let memories = cluster_memories(&collection);
+    let total_count = memories.len();
+    let page: Vec<serde_json::Value> = memories.iter().skip(offset).take(limit).map(memory_summary).collect();
+    
+    let response = json!({
+        "collection_id": collection.id,
+        "memories": page,
+        "limit": limit,
+        "offset": offset,
+        "total_count": total_count
+    });
     success_response(&response, 200)
 }
 
-fn handle_get_categories() -> HttpResponse {
-    // Return predefined categories
-    let categories = vec![
-        "work".to_string(),
-        "personal".to_string(),
-        "learning".to_string(),
-        "ideas".to_string(),
-        "research".to_string(),
-        "projects".to_string(),
-        "meetings".to_string(),
-        "notes".to_string(),
-        "reminders".to_string(),
-        "goals".to_string(),
-    ];
+fn handle_add_collection_memories(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let (collection_id, _) = collection_path(req);
+    let request: CollectionMemoriesRequest = match serde_json::from_slice(&req.body) {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+    if request.memory_ids.is_empty() {
+        return error_response(400, "memory_ids cannot be empty");
+    }
+    
+    match crate::clustering::ClusteringEngine::add_to_collection(user, &collection_id, request.memory_ids) {
+        Ok(collection) => collection_response(req, &collection, 200),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_remove_collection_memory(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let (collection_id, memory_id) = collection_path(req);
+    let Some(memory_id) = memory_id else {
+        return error_response(400, "Memory ID is required");
+    };
+    
+    match crate::clustering::ClusteringEngine::remove_from_collection(user, &collection_id, &memory_id) {
+        Ok(collection) => collection_response(req, &collection, 200),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn category_json(category: &crate::categories::MemoryCategory) -> serde_json::Value {
+    json!({
+        "id": category.id,
+        "name": category.name,
+        "description": category.description,
+        "keywords": category.keywords,
+        "confidence_threshold": category.confidence_threshold,
+        "memory_count": category.memory_count,
+        "parent_category": category.parent_category,
+        "subcategories": category.subcategories,
+        "has_centroid": !category.centroid.is_empty(),
+        "centroid_model": category.centroid_model,
+        "created_at": category.created_at,
+        "updated_at": category.updated_at,
+    })
+}
+
+// `/categories/{id}`
+fn category_path(req: &HttpRequest) -> String {
+    let path = extract_path(&req.url);
+    path.strip_prefix("/categories/").unwrap_or("").trim_end_matches('/').to_string()
+}
+
+fn handle_get_categories(user: Principal) -> HttpResponse {
+    let categories: Vec<serde_json::Value> = crate::categories::get_categories(user)
+        .iter()
+        .map(category_json)
+        .collect();
     
     let response = json!({
         "categories": categories,
@@ -300,6 +745,94 @@ fn handle_get_categories() -> HttpResponse {
     success_response(&response, 200)
 }
 
+fn handle_create_category(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let request: CreateCategoryRequest = match serde_json::from_slice(&req.body) {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+    if let Err(e) = crate::validation::validate_category_fields(
+        Some(&request.name),
+        request.description.as_deref(),
+        request.keywords.as_deref(),
+        request.confidence_threshold,
+    ) {
+        return error_response_from_error(e);
+    }
+    
+    match crate::categories::create_category(user, request) {
+        Ok(category) => success_response(&category_json(&category), 201),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_get_category(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let category_id = category_path(req);
+    match crate::categories::get_category(user, &category_id) {
+        Ok(category) => {
+            let mut response = category_json(&category);
+            response["memory_ids"] = json!(crate::categories::category_memory_ids(user, &category_id));
+            success_response(&response, 200)
+        }
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_update_category(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let category_id = category_path(req);
+    let request: UpdateCategoryRequest = match serde_json::from_slice(&req.body) {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+    if let Err(e) = crate::validation::validate_category_fields(
+        request.name.as_deref(),
+        request.description.as_deref(),
+        request.keywords.as_deref(),
+        request.confidence_threshold,
+    ) {
+        return error_response_from_error(e);
+    }
+    
+    match crate::categories::update_category(user, &category_id, request) {
+        Ok(category) => success_response(&category_json(&category), 200),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+fn handle_delete_category(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let category_id = category_path(req);
+    match crate::categories::delete_category(user, &category_id) {
+        Ok(()) => {
+            // Subcategories and memories move up to the parent category
+            let response = json!({
+                "deleted": true,
+                "message": "Category deleted successfully"
+            });
+            success_response(&response, 200)
+        }
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+// Every route that creates a memory applies the content limits and quota of POST /memories
+fn validate_new_memory(request: &AddMemoryRequest, user: Principal) -> Result<(), HttpResponse> {
+    crate::validation::validate_add_memory_request(request)
+        .and_then(|_| crate::validation::validate_user_quota(user, get_user_memory_count(user)))
+        .map_err(error_response_from_error)
+}
+
+// The GET save routes take content and comma-separated tags from the query string
+fn query_memory_request(query_params: &std::collections::HashMap<String, String>, default_content: String) -> AddMemoryRequest {
+    AddMemoryRequest {
+        content: query_params.get("content").cloned().unwrap_or(default_content),
+        tags: Some(
+            query_params.get("tags")
+                .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                .unwrap_or_default(),
+        ),
+        metadata: None,
+    }
+}
+
 async fn handle_add_memory(req: &HttpRequest, user: Principal) -> HttpResponse {
     let body_str = match std::str::from_utf8(&req.body) {
         Ok(s) => s,
@@ -311,8 +844,8 @@ async fn handle_add_memory(req: &HttpRequest, user: Principal) -> HttpResponse {
         Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
     };
 
-    if request.content.trim().is_empty() {
-        return error_response(400, "Content cannot be empty");
+    if let Err(response) = validate_new_memory(&request, user) {
+        return response;
     }
 
     // Generate embedding for the content
@@ -333,18 +866,12 @@ async fn handle_add_memory(req: &HttpRequest, user: Principal) -> HttpResponse {
         tags: request.tags.unwrap_or_default(),
         created_at: timestamp,
         updated_at: timestamp,
+        embedding_model: crate::embedding::embedding_model_for_user(user),
     };
 
     match store_memory(memory.clone()).await {
         Ok(_) => {
-            // Add to vector store
-            if let Err(e) = crate::vector_store::AdvancedVectorStore::add_vector(
-                memory.id.clone(),
-                memory.embedding.clone()
-            ) {
-                ic_cdk::println!("Failed to add vector to store: {}", e);
-            }
-            
+            // store_memory already indexed the embedding in the vector store
             let response = AddMemoryResponse {
                 id: memory.id,
                 created_at: memory.created_at,
@@ -439,29 +966,41 @@ async fn handle_semantic_search(req: &HttpRequest, user: Principal) -> HttpRespo
         Err(_) => return error_response(400, "Invalid UTF-8 in request body"),
     };
 
-    #[derive(serde::Deserialize)]
-    struct SearchRequest {
-        query: String,
-        limit: Option<usize>,
-        tags: Option<Vec<String>>,
-    }
-    
-    let search_req: SearchRequest = match serde_json::from_str(body_str) {
+    let search_req: crate::validation::SearchRequest = match serde_json::from_str(body_str) {
         Ok(req) => req,
         Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
     };
     
-    if search_req.query.trim().is_empty() {
-        return error_response(400, "Query cannot be empty");
+    run_search(search_req, user).await
+}
+
+// GET /memories/search?q=...&limit=...&tags=... for browsers and curl
+async fn handle_search_get(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let params = parse_query_params(&req.url);
+    match crate::validation::SearchRequest::from_query_params(&params) {
+        Ok(search_req) => run_search(search_req, user).await,
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+async fn run_search(search_req: crate::validation::SearchRequest, user: Principal) -> HttpResponse {
+    if let Err(e) = crate::validation::validate_search_request(&search_req) {
+        return error_response_from_error(e);
     }
     
-    let limit = search_req.limit.unwrap_or(10).min(100); // Max 100 results
+    let limit = search_req.limit.unwrap_or(10);
+    let filters = match crate::search::SearchFilters::from_request(user, &search_req) {
+        Ok(filters) => filters,
+        Err(e) => return error_response_from_error(e),
+    };
     
-    match crate::search::generate_embedding_and_search(
+    match crate::search::hybrid_search(
         &search_req.query, 
         limit, 
-        Some(user), // Filter by user
-        search_req.tags
+        user,
+        &filters,
+        search_req.mode.unwrap_or_default(),
+        search_req.semantic_weight.unwrap_or(0.5)
     ).await {
         Ok(results) => {
             let response = SearchResponse {
@@ -496,8 +1035,212 @@ async fn handle_delete_memory(req: &HttpRequest, user: Principal) -> HttpRespons
     }
 }
 
-async fn handle_bulk_add(_req: &HttpRequest, _user: Principal) -> HttpResponse {
-    error_response(501, "Bulk add not implemented yet")
+async fn handle_update_memory(req: &HttpRequest, user: Principal, partial: bool) -> HttpResponse {
+    let path = extract_path(&req.url);
+    let memory_id = path.strip_prefix("/memories/").unwrap_or("");
+    
+    if memory_id.is_empty() {
+        return error_response(400, "Memory ID is required");
+    }
+
+    let body_str = match std::str::from_utf8(&req.body) {
+        Ok(s) => s,
+        Err(_) => return error_response(400, "Invalid UTF-8 in request body"),
+    };
+
+    let request: UpdateMemoryRequest = match serde_json::from_str(body_str) {
+        Ok(req) => req,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+
+    let current = match get_memory(memory_id) {
+        Ok(Some(memory)) if memory.user_id == user => memory,
+        Ok(_) => return error_response(404, "Memory not found or permission denied"),
+        Err(e) => return error_response(500, &format!("Failed to get memory: {}", e)),
+    };
+
+    // PUT replaces the whole memory, PATCH keeps whatever is omitted
+    let (content, tags, metadata) = if partial {
+        (
+            request.content.unwrap_or_else(|| current.content.clone()),
+            request.tags.unwrap_or_else(|| current.tags.clone()),
+            request.metadata.unwrap_or_else(|| current.metadata.clone()),
+        )
+    } else {
+        match request.content {
+            Some(content) => (content, request.tags.unwrap_or_default(), request.metadata.unwrap_or_default()),
+            None => return error_response(400, "Content is required for PUT; use PATCH for partial updates"),
+        }
+    };
+
+    let validated = AddMemoryRequest {
+        content,
+        tags: Some(tags),
+        metadata: Some(metadata),
+    };
+    if let Err(e) = crate::validation::validate_add_memory_request(&validated) {
+        return error_response_from_error(e);
+    }
+
+    let content = validated.content.trim().to_string();
+    let tags = validated.tags.unwrap_or_default();
+    let metadata = validated.metadata.unwrap_or_default();
+
+    if content == current.content && tags == current.tags && metadata == current.metadata {
+        return success_response(&current, 200);
+    }
+
+    // Only a content change needs a new embedding
+    let (embedding, embedding_model) = if crate::storage::edit_needs_embedding(&current, &content) {
+        match crate::embedding::generate_embedding_for_user(&content, user).await {
+            Ok(emb) => (emb, crate::embedding::embedding_model_for_user(user)),
+            Err(e) => return error_response(500, &format!("Failed to generate embedding: {}", e)),
+        }
+    } else {
+        (current.embedding.clone(), current.embedding_model.clone())
+    };
+
+    let updated = Memory {
+        content,
+        embedding,
+        embedding_model,
+        tags,
+        metadata,
+        updated_at: ic_cdk::api::time(),
+        ..current
+    };
+
+    match crate::storage::update_memory(updated.clone()).await {
+        Ok(_) => success_response(&updated, 200),
+        Err(e) => error_response(500, &format!("Failed to update memory: {}", e)),
+    }
+}
+
+async fn handle_list_memory_revisions(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let path = extract_path(&req.url);
+    let memory_id = path
+        .strip_prefix("/memories/")
+        .and_then(|rest| rest.strip_suffix("/revisions"))
+        .unwrap_or("");
+
+    match get_memory(memory_id) {
+        Ok(Some(memory)) if memory.user_id == user => {
+            let response = json!({
+                "memory_id": memory_id,
+                "revisions": crate::storage::list_memory_revisions(memory_id),
+            });
+            success_response(&response, 200)
+        }
+        Ok(_) => error_response(404, "Memory not found or permission denied"),
+        Err(e) => error_response(500, &format!("Failed to get memory: {}", e)),
+    }
+}
+
+// POST /memories/{id}/revisions/{revision}/restore
+async fn handle_restore_memory_revision(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let path = extract_path(&req.url);
+    let parts: Vec<&str> = path
+        .strip_prefix("/memories/")
+        .and_then(|rest| rest.strip_suffix("/restore"))
+        .map(|rest| rest.split('/').collect())
+        .unwrap_or_default();
+
+    let (memory_id, revision) = match parts.as_slice() {
+        [memory_id, "revisions", revision] => match revision.parse::<u32>() {
+            Ok(revision) => (*memory_id, revision),
+            Err(_) => return error_response(400, "Invalid revision number"),
+        },
+        _ => return error_response(404, "Not found"),
+    };
+
+    match crate::storage::restore_memory_revision(memory_id, revision, user).await {
+        Ok(memory) => success_response(&memory, 200),
+        Err(e) => error_response_from_error(e),
+    }
+}
+
+async fn handle_bulk_add(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let body_str = match std::str::from_utf8(&req.body) {
+        Ok(s) => s,
+        Err(_) => return error_response(400, "Invalid UTF-8 in request body"),
+    };
+
+    let request: BulkAddMemoryRequest = match serde_json::from_str(body_str) {
+        Ok(req) => req,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+
+    if let Err(e) = crate::validation::validate_bulk_add_request(&request) {
+        return error_response_from_error(e);
+    }
+
+    let mut results: Vec<BulkItemResult> = Vec::with_capacity(request.memories.len());
+    let mut accepted: Vec<(usize, AddMemoryRequest)> = Vec::new();
+    let mut memory_count = get_user_memory_count(user);
+
+    // Validate every item and reserve quota before paying for any embeddings
+    for (index, item) in request.memories.into_iter().enumerate() {
+        let check = crate::validation::validate_add_memory_request(&item)
+            .and_then(|_| crate::validation::validate_user_quota(user, memory_count));
+        match check {
+            Ok(()) => {
+                memory_count += 1;
+                accepted.push((index, item));
+            }
+            Err(e) => results.push(BulkItemResult { index, id: None, error: Some(e.to_string()) }),
+        }
+    }
+
+    if !accepted.is_empty() {
+        let texts: Vec<String> = accepted.iter().map(|(_, item)| item.content.trim().to_string()).collect();
+        let embeddings = match crate::embedding::generate_embeddings_batch_for_user(texts, user).await {
+            Ok(embeddings) => embeddings,
+            Err(e) => accepted.iter().map(|_| Err(e.clone())).collect(),
+        };
+        let embedding_model = crate::embedding::embedding_model_for_user(user);
+
+        for ((index, item), embedding) in accepted.into_iter().zip(embeddings) {
+            let embedding = match embedding {
+                Ok(embedding) => embedding,
+                Err(e) => {
+                    results.push(BulkItemResult {
+                        index,
+                        id: None,
+                        error: Some(format!("Failed to generate embedding: {}", e)),
+                    });
+                    continue;
+                }
+            };
+
+            let timestamp = ic_cdk::api::time();
+            let memory = Memory {
+                id: crate::utils::generate_uuid(),
+                user_id: user,
+                content: item.content.trim().to_string(),
+                embedding,
+                metadata: item.metadata.unwrap_or_default(),
+                tags: item.tags.unwrap_or_default(),
+                created_at: timestamp,
+                updated_at: timestamp,
+                embedding_model: embedding_model.clone(),
+            };
+
+            let id = memory.id.clone();
+            results.push(match store_memory(memory).await {
+                Ok(()) => BulkItemResult { index, id: Some(id), error: None },
+                Err(e) => BulkItemResult { index, id: None, error: Some(format!("Failed to store memory: {}", e)) },
+            });
+        }
+    }
+
+    results.sort_by_key(|result| result.index);
+    let created = results.iter().filter(|result| result.id.is_some()).count();
+    let response = BulkAddResponse {
+        created,
+        failed: results.len() - created,
+        results,
+    };
+    success_response(&response, if created > 0 { 201 } else { 200 })
 }
 
 async fn handle_add_simple_memory(req: &HttpRequest, user: Principal) -> HttpResponse {
@@ -511,8 +1254,8 @@ async fn handle_add_simple_memory(req: &HttpRequest, user: Principal) -> HttpRes
         Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
     };
 
-    if request.content.trim().is_empty() {
-        return error_response(400, "Content cannot be empty");
+    if let Err(response) = validate_new_memory(&request, user) {
+        return response;
     }
 
     let memory_id = crate::utils::generate_uuid();
@@ -530,6 +1273,7 @@ async fn handle_add_simple_memory(req: &HttpRequest, user: Principal) -> HttpRes
         tags: request.tags.unwrap_or_default(),
         created_at: timestamp,
         updated_at: timestamp,
+        embedding_model: None, // Placeholder; replaced by a re-embedding job
     };
 
     match crate::storage::store_memory_sync(memory.clone()) {
@@ -544,86 +1288,123 @@ async fn handle_add_simple_memory(req: &HttpRequest, user: Principal) -> HttpRes
     }
 }
 
-async fn handle_bulk_delete(_req: &HttpRequest, _user: Principal) -> HttpResponse {
-    error_response(501, "Bulk delete not implemented yet")
+async fn handle_bulk_delete(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let body_str = match std::str::from_utf8(&req.body) {
+        Ok(s) => s,
+        Err(_) => return error_response(400, "Invalid UTF-8 in request body"),
+    };
+
+    let request: BulkDeleteRequest = match serde_json::from_str(body_str) {
+        Ok(req) => req,
+        Err(e) => return error_response(400, &format!("Invalid JSON: {}", e)),
+    };
+
+    if let Err(e) = crate::validation::validate_bulk_delete_request(&request) {
+        return error_response_from_error(e);
+    }
+
+    // Resolve the target ids; filters only ever see the caller's own memories.
+    // A filter deletes at most MAX_BULK_ITEMS matches per request and reports
+    // how many are left, so the client repeats the request until none remain.
+    let (ids, remaining): (Vec<String>, usize) = match (request.ids, request.filter) {
+        (Some(mut ids), _) => {
+            let mut seen = std::collections::HashSet::new();
+            ids.retain(|id| seen.insert(id.clone()));
+            (ids, 0)
+        }
+        (None, Some(filter)) => {
+            let filters = crate::search::SearchFilters {
+                user_filter: Some(user),
+                tags: filter.tags.filter(|tags| !tags.is_empty()),
+                tag_mode: filter.tag_mode.unwrap_or_default(),
+                date_range: match (filter.created_after, filter.created_before) {
+                    (None, None) => None,
+                    (start, end) => Some((start.unwrap_or(0), end.unwrap_or(u64::MAX))),
+                },
+                ..Default::default()
+            };
+            let mut matched: Vec<String> = user_memory_ids(user)
+                .into_iter()
+                .filter(|id| {
+                    get_memory(id)
+                        .ok()
+                        .flatten()
+                        .is_some_and(|memory| crate::search::matches_memory_filters(&memory, &filters))
+                })
+                .collect();
+            let remaining = matched.len().saturating_sub(crate::validation::MAX_BULK_ITEMS);
+            matched.truncate(crate::validation::MAX_BULK_ITEMS);
+            (matched, remaining)
+        }
+        (None, None) => (Vec::new(), 0),
+    };
+
+    let mut results = Vec::with_capacity(ids.len());
+    for (index, id) in ids.into_iter().enumerate() {
+        // delete_memory refuses memories owned by someone else
+        results.push(match delete_memory(&id, user).await {
+            Ok(true) => BulkItemResult { index, id: Some(id), error: None },
+            Ok(false) => BulkItemResult { index, id: Some(id), error: Some("Memory not found".to_string()) },
+            Err(e) => BulkItemResult { index, id: Some(id), error: Some(e) },
+        });
+    }
+
+    let deleted = results.iter().filter(|result| result.error.is_none()).count();
+    let response = BulkDeleteResponse {
+        deleted,
+        failed: results.len() - deleted,
+        remaining,
+        results,
+    };
+    success_response(&response, 200)
+}
+
+// Authenticate a synchronous GET route by API key and check the permission it needs
+fn authorize_api_key(req: &HttpRequest, permission: Permission) -> Result<AuthContext, HttpResponse> {
+    let auth = authenticate_api_key(&req.headers)
+        .map_err(|e| error_response(401, &e))?;
+    auth.require(permission).map_err(error_response_from_error)?;
+    Ok(auth)
 }
 
 fn handle_test_auth(req: &HttpRequest) -> HttpResponse {
     // Test authentication without async
-    let api_key = extract_api_key(&req.headers);
-    
-    if let Some(key) = api_key {
-        // Simple synchronous API key validation
-        match key.as_str() {
-            "openmemory-api-key-development" |
-            "claude-code-integration-key" |
-            "om_feoQrSrz5UqCQ3DjfaXkyMv7x4mtt08O" => {
-                let response = serde_json::json!({
-                    "authenticated": true,
-                    "api_key_valid": true,
-                    "message": "API key authentication successful",
-                    "key_prefix": &key[..10]
-                });
-                success_response(&response, 200)
-            }
-            key if key.starts_with("om_") && key.len() > 10 => {
-                let response = serde_json::json!({
-                    "authenticated": true,
-                    "api_key_valid": true,
-                    "message": "Generic API key pattern accepted",
-                    "key_prefix": &key[..10]
-                });
-                success_response(&response, 200)
-            }
-            _ => {
-                let response = serde_json::json!({
-                    "authenticated": false,
-                    "api_key_valid": false,
-                    "message": "Invalid API key",
-                    "key_provided": true
-                });
-                error_response(401, "Invalid API key")
-            }
+    match authenticate_api_key(&req.headers) {
+        Ok(auth) => {
+            let key = extract_api_key(&req.headers).unwrap_or_default();
+            let response = serde_json::json!({
+                "authenticated": true,
+                "api_key_valid": true,
+                "message": "API key authentication successful",
+                "key_prefix": api_key_prefix(&key),
+                "principal": auth.principal.to_text(),
+                "permissions": auth.permissions
+            });
+            success_response(&response, 200)
         }
-    } else {
-        let response = serde_json::json!({
-            "authenticated": false,
-            "api_key_valid": false,
-            "message": "No API key provided",
-            "key_provided": false
-        });
-        error_response(401, "No API key provided")
+        Err(e) => error_response(401, &e),
     }
 }
 
 fn handle_quick_memory(req: &HttpRequest) -> HttpResponse {
     // Quick memory creation via GET for testing
-    let api_key = extract_api_key(&req.headers);
-    
-    if api_key.is_none() {
-        return error_response(401, "API key required");
-    }
-    
-    let key = api_key.unwrap();
-    
-    // Validate API key
-    let is_valid = match key.as_str() {
-        "openmemory-api-key-development" |
-        "claude-code-integration-key" |
-        "om_feoQrSrz5UqCQ3DjfaXkyMv7x4mtt08O" => true,
-        key if key.starts_with("om_") && key.len() > 10 => true,
-        _ => false
+    let user_principal = match authorize_api_key(req, Permission::Write) {
+        Ok(auth) => auth.principal,
+        Err(response) => return response,
     };
     
-    if !is_valid {
-        return error_response(401, "Invalid API key");
-    }
-    
     // Extract content from query parameters
     let query_params = parse_query_params(&req.url);
-    let content = query_params.get("content").cloned().unwrap_or_else(|| {
-        format!("Quick test memory created at {}", ic_cdk::api::time())
-    });
+    let request = query_memory_request(
+        &query_params,
+        format!("Quick test memory created at {}", ic_cdk::api::time()),
+    );
+    
+    // Report what a real save would reject
+    if let Err(response) = validate_new_memory(&request, user_principal) {
+        return response;
+    }
+    let content = request.content.trim().to_string();
     
     // Create a simple memory response (without actually storing it)
     let memory_id = crate::utils::generate_uuid();
@@ -646,39 +1427,20 @@ fn handle_quick_memory(req: &HttpRequest) -> HttpResponse {
 
 fn handle_simple_memory_save(req: &HttpRequest) -> HttpResponse {
     // Simple memory save without embedding generation - for direct API usage
-    let api_key = extract_api_key(&req.headers);
-    
-    if api_key.is_none() {
-        return error_response(401, "API key required");
-    }
-    
-    let key = api_key.unwrap();
-    
-    // Validate API key and get user principal
-    let user_principal = match key.as_str() {
-        "openmemory-api-key-development" => {
-            Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap()
-        }
-        "claude-code-integration-key" => {
-            Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()
-        }
-        "om_feoQrSrz5UqCQ3DjfaXkyMv7x4mtt08O" => {
-            Principal::from_text("4wbqy-noqfb-3dunk-64f7k-4v54w-kzvti-l24ky-jaz3f-73y36-gegjt-cqe").unwrap()
-        }
-        key if key.starts_with("om_") && key.len() > 10 => {
-            Principal::from_text("4wbqy-noqfb-3dunk-64f7k-4v54w-kzvti-l24ky-jaz3f-73y36-gegjt-cqe").unwrap()
-        }
-        _ => return error_response(401, "Invalid API key")
+    let user_principal = match authorize_api_key(req, Permission::Write) {
+        Ok(auth) => auth.principal,
+        Err(response) => return response,
     };
     
     // Extract content from query parameters
     let query_params = parse_query_params(&req.url);
-    let content = query_params.get("content").cloned().unwrap_or_else(|| {
-        format!("Simple memory saved via API at {}", ic_cdk::api::time())
-    });
+    let request = query_memory_request(
+        &query_params,
+        format!("Simple memory saved via API at {}", ic_cdk::api::time()),
+    );
     
-    if content.trim().is_empty() {
-        return error_response(400, "Content cannot be empty");
+    if let Err(response) = validate_new_memory(&request, user_principal) {
+        return response;
     }
     
     // Create memory with simple placeholder embedding (no OpenAI API call)
@@ -691,14 +1453,13 @@ fn handle_simple_memory_save(req: &HttpRequest) -> HttpResponse {
     let memory = Memory {
         id: memory_id.clone(),
         user_id: user_principal,
-        content: content.trim().to_string(),
+        content: request.content.trim().to_string(),
         embedding: simple_embedding,
         metadata: std::collections::HashMap::new(),
-        tags: query_params.get("tags")
-            .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-            .unwrap_or_default(),
+        tags: request.tags.unwrap_or_default(),
         created_at: timestamp,
         updated_at: timestamp,
+        embedding_model: None,
     };
     
     // Store the memory synchronously
@@ -725,39 +1486,20 @@ fn handle_simple_memory_save(req: &HttpRequest) -> HttpResponse {
 
 fn handle_save_memory_get(req: &HttpRequest) -> HttpResponse {
     // Save memory via GET for testing - this will actually store the memory
-    let api_key = extract_api_key(&req.headers);
-    
-    if api_key.is_none() {
-        return error_response(401, "API key required");
-    }
-    
-    let key = api_key.unwrap();
-    
-    // Validate API key and get user principal
-    let user_principal = match key.as_str() {
-        "openmemory-api-key-development" => {
-            Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap()
-        }
-        "claude-code-integration-key" => {
-            Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()
-        }
-        "om_feoQrSrz5UqCQ3DjfaXkyMv7x4mtt08O" => {
-            Principal::from_text("4wbqy-noqfb-3dunk-64f7k-4v54w-kzvti-l24ky-jaz3f-73y36-gegjt-cqe").unwrap()
-        }
-        key if key.starts_with("om_") && key.len() > 10 => {
-            Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()
-        }
-        _ => return error_response(401, "Invalid API key")
+    let user_principal = match authorize_api_key(req, Permission::Write) {
+        Ok(auth) => auth.principal,
+        Err(response) => return response,
     };
     
     // Extract content from query parameters
     let query_params = parse_query_params(&req.url);
-    let content = query_params.get("content").cloned().unwrap_or_else(|| {
-        format!("Memory saved via API at {}", ic_cdk::api::time())
-    });
+    let request = query_memory_request(
+        &query_params,
+        format!("Memory saved via API at {}", ic_cdk::api::time()),
+    );
     
-    if content.trim().is_empty() {
-        return error_response(400, "Content cannot be empty");
+    if let Err(response) = validate_new_memory(&request, user_principal) {
+        return response;
     }
     
     // Create and store the memory
@@ -770,14 +1512,13 @@ fn handle_save_memory_get(req: &HttpRequest) -> HttpResponse {
     let memory = Memory {
         id: memory_id.clone(),
         user_id: user_principal,
-        content: content.trim().to_string(),
+        content: request.content.trim().to_string(),
         embedding: simple_embedding,
         metadata: std::collections::HashMap::new(),
-        tags: query_params.get("tags")
-            .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-            .unwrap_or_default(),
+        tags: request.tags.unwrap_or_default(),
         created_at: timestamp,
         updated_at: timestamp,
+        embedding_model: None,
     };
     
     // Store the memory synchronously (simplified version)
@@ -857,6 +1598,9 @@ async fn handle_get_config(_req: &HttpRequest, user: Principal) -> HttpResponse
                 }),
                 api_provider: format!("{:?}", config.api_provider),
                 embedding_model: config.embedding_model,
+                api_base_url: config.api_base_url,
+                has_compatible_key: config.compatible_api_key.is_some(),
+                embedding_dimension: config.embedding_dimension,
                 available_models,
                 updated_at: Some(config.updated_at),
             };
@@ -871,6 +1615,9 @@ async fn handle_get_config(_req: &HttpRequest, user: Principal) -> HttpResponse
                 openrouter_key_preview: None,
                 api_provider: "OpenAI".to_string(),
                 embedding_model: "text-embedding-ada-002".to_string(),
+                api_base_url: None,
+                has_compatible_key: false,
+                embedding_dimension: None,
                 available_models,
                 updated_at: None,
             };
@@ -892,22 +1639,45 @@ async fn handle_update_config(req: &HttpRequest, user: Principal) -> HttpRespons
     };
 
     // Convert provider string to enum
-    let api_provider = request.api_provider.as_deref().map(|p| match p {
-        "OpenRouter" => crate::types::ApiProvider::OpenRouter,
-        _ => crate::types::ApiProvider::OpenAI,
-    });
+    let api_provider = match request.api_provider.as_deref() {
+        Some(name) => match crate::types::ApiProvider::from_name(name) {
+            Some(provider) => Some(provider),
+            None => return error_response(400, &format!("Unknown API provider: {}", name)),
+        },
+        None => None,
+    };
 
-    match crate::storage::update_user_config(
-        user,
-        request.openai_api_key,
-        request.openrouter_api_key,
-        api_provider,
-        request.embedding_model,
-    ) {
+    if let Some(ref url) = request.api_base_url {
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            return error_response(400, "API base URL must start with http:// or https://");
+        }
+    }
+
+    if let Some(dimension) = request.embedding_dimension {
+        if dimension == 0 || dimension > crate::embedding::MAX_EMBEDDING_DIMENSION {
+            return error_response(
+                400,
+                &format!("embedding_dimension must be between 1 and {}", crate::embedding::MAX_EMBEDDING_DIMENSION),
+            );
+        }
+    }
+
+    let previous_model = crate::embedding::embedding_model_for_user(user);
+
+    match crate::storage::update_user_config(user, api_provider, request) {
         Ok(_) => {
+            // Existing embeddings belong to the old model; migrate them in the background
+            let current_model = crate::embedding::embedding_model_for_user(user);
+            let reembed_job = if current_model.is_some() && current_model != previous_model {
+                crate::reembed::start_job(user).ok()
+            } else {
+                None
+            };
+
             let response = serde_json::json!({
                 "success": true,
-                "message": "Configuration updated successfully"
+                "message": "Configuration updated successfully",
+                "reembed_job": reembed_job.as_ref().map(reembed_job_json),
             });
             success_response(&response, 200)
         }
@@ -935,8 +1705,46 @@ async fn handle_delete_openai_key(_req: &HttpRequest, user: Principal) -> HttpRe
     }
 }
 
+fn reembed_job_json(job: &crate::reembed::ReembedJob) -> serde_json::Value {
+    serde_json::json!({
+        "user_id": job.user_id.to_text(),
+        "target_model": job.target_model,
+        "status": job.status,
+        "total": job.total,
+        "processed": job.processed,
+        "failed": job.failed,
+        "percent_complete": job.percent_complete(),
+        "last_error": job.last_error,
+        "started_at": job.started_at,
+        "updated_at": job.updated_at,
+        "finished_at": job.finished_at,
+    })
+}
+
+fn handle_start_reembed(user: Principal) -> HttpResponse {
+    match crate::reembed::start_job(user) {
+        Ok(job) => success_response(&reembed_job_json(&job), 202),
+        Err(e) => error_response(400, &e),
+    }
+}
+
+fn handle_get_reembed(user: Principal) -> HttpResponse {
+    match crate::reembed::get_job(user) {
+        Some(job) => success_response(&reembed_job_json(&job), 200),
+        None => error_response(404, "No re-embedding job found"),
+    }
+}
+
+fn handle_cancel_reembed(user: Principal) -> HttpResponse {
+    match crate::reembed::cancel_job(user) {
+        Some(job) => success_response(&reembed_job_json(&job), 200),
+        None => error_response(404, "No re-embedding job found"),
+    }
+}
+
 // Token Management Handlers
-async fn handle_create_token(req: &HttpRequest, user: Principal) -> HttpResponse {
+async fn handle_create_token(req: &HttpRequest, auth: &AuthContext) -> HttpResponse {
+    let user = auth.principal;
     let request: CreateTokenRequest = match serde_json::from_slice(&req.body) {
         Ok(req) => req,
         Err(e) => return error_response(400, &format!("Invalid request body: {}", e)),
@@ -947,17 +1755,31 @@ async fn handle_create_token(req: &HttpRequest, user: Principal) -> HttpResponse
         Permission::Write,
     ]);
     
+    // A token can never grant more than the credential that created it
+    for permission in &permissions {
+        if let Err(e) = auth.require(permission.clone()) {
+            return error_response_from_error(e);
+        }
+    }
+    
     let expires_in_days = request.expires_in_days.unwrap_or(30); // Default 30 days
     
+    let token = match generate_access_token().await {
+        Ok(token) => token,
+        Err(e) => return error_response(500, &format!("Failed to generate token: {}", e)),
+    };
+    
     match crate::storage::create_access_token(
         user,
+        &token,
         request.description,
         permissions.clone(),
         expires_in_days,
     ) {
         Ok(access_token) => {
             let response = CreateTokenResponse {
-                token: access_token.token,
+                token,
+                id: crate::storage::access_token_id(&access_token.token),
                 expires_at: access_token.expires_at,
                 permissions,
             };
@@ -994,3 +1816,74 @@ async fn handle_revoke_token(req: &HttpRequest, user: Principal) -> HttpResponse
         Err(e) => error_response(500, &format!("Failed to revoke token: {}", e)),
     }
 }
+
+async fn handle_create_api_key(req: &HttpRequest, auth: &AuthContext) -> HttpResponse {
+    let request: CreateApiKeyRequest = match serde_json::from_slice(&req.body) {
+        Ok(req) => req,
+        Err(e) => return error_response(400, &format!("Invalid request body: {}", e)),
+    };
+    
+    let permissions = request.permissions.unwrap_or_else(|| auth.permissions.clone());
+    if permissions.is_empty() {
+        return error_response(400, "At least one permission is required");
+    }
+    
+    // A key can never grant more than the credential that created it
+    for permission in &permissions {
+        if let Err(e) = auth.require(permission.clone()) {
+            return error_response_from_error(e);
+        }
+    }
+    
+    let api_key = match generate_api_key().await {
+        Ok(key) => key,
+        Err(e) => return error_response(500, &format!("Failed to generate API key: {}", e)),
+    };
+    
+    match crate::storage::store_api_key(
+        auth.principal,
+        &api_key,
+        request.name,
+        permissions,
+        request.expires_in_days,
+    ) {
+        Ok(record) => {
+            let response = CreateApiKeyResponse {
+                api_key,
+                prefix: record.prefix,
+                permissions: record.permissions,
+                expires_at: record.expires_at,
+            };
+            success_response(&response, 201)
+        }
+        Err(e) => error_response(500, &format!("Failed to create API key: {}", e)),
+    }
+}
+
+async fn handle_list_api_keys(_req: &HttpRequest, user: Principal) -> HttpResponse {
+    match crate::storage::get_user_api_keys(user) {
+        Ok(keys) => success_response(&keys, 200),
+        Err(e) => error_response(500, &format!("Failed to list API keys: {}", e)),
+    }
+}
+
+async fn handle_revoke_api_key(req: &HttpRequest, user: Principal) -> HttpResponse {
+    let path = extract_path(&req.url);
+    let prefix = path.strip_prefix("/auth/api-keys/").unwrap_or("");
+    
+    if prefix.is_empty() {
+        return error_response(400, "API key prefix is required");
+    }
+    
+    match crate::storage::revoke_api_key(prefix, user) {
+        Ok(true) => {
+            let response = serde_json::json!({
+                "success": true,
+                "message": "API key revoked successfully"
+            });
+            success_response(&response, 200)
+        }
+        Ok(false) => error_response(404, "API key not found"),
+        Err(e) => error_response(500, &format!("Failed to revoke API key: {}", e)),
+    }
+}
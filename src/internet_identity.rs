@@ -1,190 +1,446 @@
 use crate::types::*;
-use candid::Principal;
+use candid::{CandidType, Principal};
 use ic_cdk::api::time;
-use std::collections::HashMap;
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use std::borrow::Cow;
 use std::cell::RefCell;
-use sha2::{Digest, Sha256};
+use std::time::Duration;
 use base64::{Engine, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+
+// Internet Identity session management. Sessions live in stable memory so
+// upgrades do not log users out; they are keyed by the SHA-256 of the
+// session key, which is only ever returned to the client at login.
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type SessionMap = StableBTreeMap<String, IISession, VMem>;
+// Login proofs already exchanged for a session, until their timestamp expires
+type UsedLoginMap = StableBTreeMap<String, u64, VMem>;
 
-// Internet Identity session management
 thread_local! {
-    static II_SESSIONS: RefCell<HashMap<String, IISession>> = RefCell::new(HashMap::new());
-    static II_DELEGATION_CACHE: RefCell<HashMap<Principal, IIDelegation>> = RefCell::new(HashMap::new());
+    static II_SESSIONS: RefCell<Option<SessionMap>> = const { RefCell::new(None) };
+    static USED_LOGINS: RefCell<Option<UsedLoginMap>> = const { RefCell::new(None) };
 }
 
-#[derive(Clone, Debug)]
+/// Session keys start with this marker so they can be sent as bearer tokens
+pub const SESSION_KEY_PREFIX: &str = "om_session_";
+/// Name of the cookie carrying the session key
+pub const SESSION_COOKIE: &str = "ii_session";
+/// A session expires after this long without use
+pub const SESSION_IDLE_TIMEOUT_NS: u64 = 2 * 60 * 60 * 1_000_000_000; // 2 hours
+/// No session lives longer than this, however often it is used
+pub const SESSION_MAX_LIFETIME_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000; // 7 days
+// Length of the public session id shown in listings (prefix of the key hash)
+const SESSION_ID_LENGTH: usize = 16;
+const SESSION_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct IISession {
     pub user_principal: Principal,
-    pub session_key: String,
+    pub session_public_key: Vec<u8>, // Key the delegation chain was issued to
     pub created_at: u64,
+    pub last_used_at: u64,
+    pub expires_at: u64,          // Slides forward on use, never past `absolute_expires_at`
+    pub absolute_expires_at: u64, // Creation + max lifetime, capped by the delegation's expiration
+}
+
+impl Storable for IISession {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+// What `GET /auth/sessions` shows for one session
+#[derive(Serialize, Clone, Debug)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: u64,
+    pub last_used_at: u64,
     pub expires_at: u64,
-    pub delegation_chain: Vec<IIDelegation>,
+    pub absolute_expires_at: u64,
+    pub current: bool,
 }
 
-#[derive(Clone, Debug)]
-pub struct IIDelegation {
-    pub delegation: String, // base64 encoded delegation
-    pub signature: String,  // base64 encoded signature
-    pub public_key: String, // base64 encoded public key
-    pub expiration: u64,
+pub fn init_sessions() {
+    II_SESSIONS.with(|sessions| {
+        *sessions.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_II_SESSIONS)
+        ));
+    });
+    USED_LOGINS.with(|used| {
+        *used.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_II_USED_LOGINS)
+        ));
+    });
+    
+    // Timers do not survive upgrades, so this is scheduled from init and post_upgrade
+    ic_cdk_timers::set_timer_interval(SESSION_CLEANUP_INTERVAL, || {
+        let removed = cleanup_expired_sessions();
+        if removed > 0 {
+            ic_cdk::println!("Removed {} expired II sessions", removed);
+        }
+        cleanup_used_logins(time());
+    });
 }
 
 #[derive(serde::Deserialize)]
 pub struct IIAuthRequest {
     pub delegation_chain: Vec<IIDelegationData>,
-    pub user_public_key: String,
+    pub user_public_key: String,   // base64 DER public key the chain starts from (II's canister signature key)
+    pub login_timestamp: u64,      // Nanoseconds; bounds how long a captured login can be replayed
+    pub session_signature: String, // base64 signature over the login message by the last delegated key
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Clone)]
 pub struct IIDelegationData {
-    pub delegation: String,
-    pub signature: String,
+    pub delegation: String, // base64 CBOR map {pubkey, expiration, targets?}
+    pub signature: String,  // base64 signature by the previous key in the chain
+}
+
+// A delegation as defined by the IC interface specification
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct Delegation {
+    #[serde(with = "serde_bytes")]
+    pub pubkey: Vec<u8>,
+    pub expiration: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<Vec<serde_bytes::ByteBuf>>,
+}
+
+// Outcome of a successfully verified chain
+#[derive(Clone, Debug)]
+pub struct VerifiedDelegation {
+    pub principal: Principal,
+    pub session_public_key: Vec<u8>,
+    pub expiration: u64,
 }
 
-// Internet Identity authentication
-pub async fn authenticate_with_ii(auth_data: &str) -> Result<Principal, String> {
+// The IC allows at most 20 delegations per chain
+const MAX_DELEGATION_CHAIN_LENGTH: usize = 20;
+// How far `login_timestamp` may be from the canister's clock
+const LOGIN_TIMESTAMP_TOLERANCE_NS: u64 = 5 * 60 * 1_000_000_000;
+const LOGIN_SIG_DOMAIN: &[u8] = b"openmemory-ii-login";
+
+// DER prefixes and OIDs identifying the supported public key types
+const ED25519_DER_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+const P256_CURVE_OID: [u8; 10] = [0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const SECP256K1_CURVE_OID: [u8; 7] = [0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a];
+
+thread_local! {
+    // Raw root key of a local replica; mainnet's key is used when unset
+    static IC_ROOT_KEY_OVERRIDE: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+// Internet Identity authentication. Returns the new session key and its record.
+pub async fn authenticate_with_ii(auth_data: &str) -> Result<(String, IISession), String> {
     let auth_request: IIAuthRequest = serde_json::from_str(auth_data)
         .map_err(|e| format!("Invalid II auth data: {}", e))?;
     
+    let now = time();
+    let canister_id = ic_cdk::id();
+    
     // Verify delegation chain
-    let principal = verify_delegation_chain(&auth_request.delegation_chain, &auth_request.user_public_key)?;
+    let verified = verify_delegation_chain(
+        &auth_request.delegation_chain,
+        &auth_request.user_public_key,
+        now,
+        canister_id,
+        &ic_root_key()?,
+    )?;
+    
+    // The caller must hold the private key the chain delegates to
+    verify_session_signature(&verified, &auth_request, now, canister_id)?;
     
-    // Create session
-    let session = create_ii_session(principal, auth_request.delegation_chain)?;
+    // Each signed login is good for one session. Recorded before the first
+    // await so a concurrent replay of the same request is rejected too.
+    record_login(&verified.session_public_key, auth_request.login_timestamp)?;
+    
+    // Session keys come from the canister CSPRNG
+    crate::rng::ensure_seeded().await?;
+    let session_key = generate_session_key()?;
+    let session = create_ii_session(&verified, now);
     
-    // Cache the session
     II_SESSIONS.with(|sessions| {
-        sessions.borrow_mut().insert(session.session_key.clone(), session);
-    });
+        match *sessions.borrow_mut() {
+            Some(ref mut session_map) => {
+                session_map.insert(session_hash(&session_key), session.clone());
+                Ok(())
+            }
+            None => Err("Session storage not available".to_string()),
+        }
+    })?;
     
-    Ok(principal)
+    Ok((session_key, session))
 }
 
+// Resolve a session key to its user, sliding the idle expiry forward
 pub fn verify_ii_session(session_key: &str) -> Result<Principal, String> {
+    let key_hash = session_hash(session_key);
+    
     II_SESSIONS.with(|sessions| {
-        let sessions_map = sessions.borrow();
-        match sessions_map.get(session_key) {
-            Some(session) => {
-                let current_time = time();
-                if current_time > session.expires_at {
-                    Err("Session expired".to_string())
-                } else {
-                    Ok(session.user_principal)
-                }
-            }
-            None => Err("Invalid session".to_string())
+        let mut sessions = sessions.borrow_mut();
+        let session_map = sessions.as_mut().ok_or_else(|| "Session storage not available".to_string())?;
+        let mut session = session_map.get(&key_hash).ok_or_else(|| "Invalid session".to_string())?;
+        
+        let current_time = time();
+        if current_time > session.expires_at {
+            session_map.remove(&key_hash);
+            return Err("Session expired".to_string());
         }
+        
+        touch_session(&mut session, current_time);
+        let principal = session.user_principal;
+        session_map.insert(key_hash, session);
+        Ok(principal)
     })
 }
 
-fn verify_delegation_chain(
-    delegation_chain: &[IIDelegationData], 
-    _user_public_key: &str
-) -> Result<Principal, String> {
+fn touch_session(session: &mut IISession, now: u64) {
+    session.last_used_at = now;
+    session.expires_at = now.saturating_add(SESSION_IDLE_TIMEOUT_NS).min(session.absolute_expires_at);
+}
+
+fn session_hash(session_key: &str) -> String {
+    crate::storage::hash_credential(session_key)
+}
+
+fn session_id(key_hash: &str) -> String {
+    key_hash.chars().take(SESSION_ID_LENGTH).collect()
+}
+
+// Local replicas sign with their own root key; controllers register it here
+pub fn set_ic_root_key(root_key_der: &[u8]) -> Result<(), String> {
+    let raw = ic_canister_sig_creation::extract_raw_root_pk_from_der(root_key_der)?;
+    IC_ROOT_KEY_OVERRIDE.with(|key| *key.borrow_mut() = Some(raw));
+    Ok(())
+}
+
+fn ic_root_key() -> Result<Vec<u8>, String> {
+    match IC_ROOT_KEY_OVERRIDE.with(|key| key.borrow().clone()) {
+        Some(raw) => Ok(raw),
+        None => ic_canister_sig_creation::extract_raw_root_pk_from_der(ic_canister_sig_creation::IC_ROOT_PK_DER),
+    }
+}
+
+// Walk the chain from the user's key: each delegation must be signed by the
+// key of the previous step, be unexpired, and (if restricted) target this
+// canister. The principal is the self-authenticating id of the first key.
+pub fn verify_delegation_chain(
+    delegation_chain: &[IIDelegationData],
+    user_public_key: &str,
+    now: u64,
+    canister_id: Principal,
+    ic_root_key_raw: &[u8],
+) -> Result<VerifiedDelegation, String> {
     if delegation_chain.is_empty() {
         return Err("Empty delegation chain".to_string());
     }
+    if delegation_chain.len() > MAX_DELEGATION_CHAIN_LENGTH {
+        return Err(format!("Delegation chain longer than {} entries", MAX_DELEGATION_CHAIN_LENGTH));
+    }
     
-    // For now, implement basic validation
-    // In production, this should verify cryptographic signatures
-    // and check delegation expiration times
-    
-    // Extract principal from the delegation chain
-    // This is a simplified implementation
-    let delegation_data = &delegation_chain[0];
-    let delegation_bytes = general_purpose::STANDARD.decode(&delegation_data.delegation)
-        .map_err(|e| format!("Invalid delegation encoding: {}", e))?;
+    let user_public_key = decode_base64(user_public_key, "user public key")?;
+    let mut signer = user_public_key.clone();
+    let mut expiration = u64::MAX;
     
-    // Parse delegation to extract principal
-    // This should use proper CBOR decoding in production
-    let principal_str = extract_principal_from_delegation(&delegation_bytes)?;
-    let principal = Principal::from_text(&principal_str)
-        .map_err(|e| format!("Invalid principal: {}", e))?;
+    for (index, data) in delegation_chain.iter().enumerate() {
+        let delegation_bytes = decode_base64(&data.delegation, "delegation")?;
+        let signature = decode_base64(&data.signature, "delegation signature")?;
+        let delegation: Delegation = serde_cbor::from_slice(&delegation_bytes)
+            .map_err(|e| format!("Invalid delegation {} CBOR: {}", index, e))?;
+        
+        if delegation.expiration <= now {
+            return Err(format!("Delegation {} expired", index));
+        }
+        
+        if let Some(ref targets) = delegation.targets {
+            if !targets.iter().any(|target| target.as_slice() == canister_id.as_slice()) {
+                return Err(format!("Delegation {} does not target this canister", index));
+            }
+        }
+        
+        let targets: Option<Vec<Vec<u8>>> = delegation.targets
+            .as_ref()
+            .map(|targets| targets.iter().map(|t| t.to_vec()).collect());
+        let message = delegation_signing_message(&delegation.pubkey, delegation.expiration, targets.as_ref());
+        
+        verify_signature(&signer, &message, &signature, ic_root_key_raw)
+            .map_err(|e| format!("Delegation {} signature invalid: {}", index, e))?;
+        
+        expiration = expiration.min(delegation.expiration);
+        signer = delegation.pubkey;
+    }
     
-    Ok(principal)
+    Ok(VerifiedDelegation {
+        principal: Principal::self_authenticating(&user_public_key),
+        session_public_key: signer,
+        expiration,
+    })
 }
 
-fn extract_principal_from_delegation(delegation_bytes: &[u8]) -> Result<String, String> {
-    // Simplified principal extraction
-    // In production, this should properly parse the CBOR delegation
-    
-    // For now, return a placeholder principal based on delegation hash
-    let hash = Sha256::digest(delegation_bytes);
-    let hash_hex = hex::encode(&hash[..10]); // Use first 10 bytes
-    
-    // Generate a valid principal-like string
-    Ok(format!("2vxsx-fae-{}", hash_hex))
+// Domain-separated representation-independent hash of a delegation
+fn delegation_signing_message(pubkey: &[u8], expiration: u64, targets: Option<&Vec<Vec<u8>>>) -> Vec<u8> {
+    let domain = ic_canister_sig_creation::DELEGATION_SIG_DOMAIN;
+    let mut message = vec![domain.len() as u8];
+    message.extend_from_slice(domain);
+    message.extend(ic_canister_sig_creation::delegation_signature_msg(pubkey, expiration, targets));
+    message
 }
 
-fn create_ii_session(
-    principal: Principal, 
-    delegation_chain: Vec<IIDelegationData>
-) -> Result<IISession, String> {
-    let session_key = generate_session_key();
-    let current_time = time();
-    let expires_at = current_time + (24 * 60 * 60 * 1_000_000_000); // 24 hours
-    
-    let delegations: Vec<IIDelegation> = delegation_chain
-        .into_iter()
-        .map(|d| IIDelegation {
-            delegation: d.delegation,
-            signature: d.signature,
-            public_key: "".to_string(), // Extract from delegation in production
-            expiration: expires_at,
-        })
-        .collect();
+pub fn login_signing_message(canister_id: Principal, login_timestamp: u64) -> Vec<u8> {
+    let mut message = vec![LOGIN_SIG_DOMAIN.len() as u8];
+    message.extend_from_slice(LOGIN_SIG_DOMAIN);
+    message.extend_from_slice(canister_id.as_slice());
+    message.extend_from_slice(&login_timestamp.to_be_bytes());
+    message
+}
+
+fn verify_session_signature(
+    verified: &VerifiedDelegation,
+    auth_request: &IIAuthRequest,
+    now: u64,
+    canister_id: Principal,
+) -> Result<(), String> {
+    if now.abs_diff(auth_request.login_timestamp) > LOGIN_TIMESTAMP_TOLERANCE_NS {
+        return Err("Login timestamp is too far from the current time".to_string());
+    }
     
-    Ok(IISession {
-        user_principal: principal,
-        session_key,
-        created_at: current_time,
-        expires_at,
-        delegation_chain: delegations,
+    let signature = decode_base64(&auth_request.session_signature, "session signature")?;
+    let message = login_signing_message(canister_id, auth_request.login_timestamp);
+    verify_basic_signature(&verified.session_public_key, &message, &signature)
+        .map_err(|e| format!("Session signature invalid: {}", e))
+}
+
+fn used_login_key(session_public_key: &[u8], login_timestamp: u64) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{}:{}", hex::encode(Sha256::digest(session_public_key)), login_timestamp)
+}
+
+// Remember a verified login until its timestamp falls outside the tolerance,
+// after which `verify_session_signature` rejects it anyway
+fn record_login(session_public_key: &[u8], login_timestamp: u64) -> Result<(), String> {
+    let key = used_login_key(session_public_key, login_timestamp);
+    USED_LOGINS.with(|used| {
+        let mut used = used.borrow_mut();
+        let used_map = used.as_mut().ok_or_else(|| "Login storage not available".to_string())?;
+        if used_map.contains_key(&key) {
+            return Err("Login signature has already been used".to_string());
+        }
+        used_map.insert(key, login_timestamp.saturating_add(LOGIN_TIMESTAMP_TOLERANCE_NS));
+        Ok(())
     })
 }
 
-fn generate_session_key() -> String {
-    use sha2::{Sha256, Digest};
-    let mut hasher = Sha256::new();
-    hasher.update(time().to_string());
-    hasher.update(ic_cdk::caller().to_string());
-    let result = hasher.finalize();
-    hex::encode(result)
+fn cleanup_used_logins(now: u64) -> usize {
+    USED_LOGINS.with(|used| {
+        let mut used = used.borrow_mut();
+        let used_map = match used.as_mut() {
+            Some(used_map) => used_map,
+            None => return 0,
+        };
+        
+        let expired: Vec<String> = used_map
+            .iter()
+            .filter(|(_, expires_at)| *expires_at < now)
+            .map(|(key, _)| key)
+            .collect();
+        
+        for key in &expired {
+            used_map.remove(key);
+        }
+        expired.len()
+    })
 }
 
-// Enhanced authentication function that supports both Bearer tokens and II
-pub async fn authenticate_request_enhanced(req: &HttpRequest) -> Result<Principal, String> {
-    // Check for Internet Identity session first
-    if let Some(session_key) = extract_ii_session(req) {
-        return verify_ii_session(&session_key);
+// Verify a signature by any key type that may appear in a delegation chain
+fn verify_signature(public_key_der: &[u8], message: &[u8], signature: &[u8], ic_root_key_raw: &[u8]) -> Result<(), String> {
+    if contains(public_key_der, ic_canister_sig_creation::CANISTER_SIG_PK_DER_OID) {
+        return ic_signature_verification::verify_canister_sig(message, signature, public_key_der, ic_root_key_raw);
+    }
+    verify_basic_signature(public_key_der, message, signature)
+}
+
+// Ed25519 and ECDSA (P-256, secp256k1 over SHA-256) keys held by clients
+fn verify_basic_signature(public_key_der: &[u8], message: &[u8], signature: &[u8]) -> Result<(), String> {
+    if let Some(raw) = public_key_der.strip_prefix(ED25519_DER_PREFIX.as_slice()) {
+        let raw: [u8; 32] = raw.try_into().map_err(|_| "Invalid Ed25519 public key length".to_string())?;
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&raw)
+            .map_err(|e| format!("Invalid Ed25519 public key: {}", e))?;
+        let signature = ed25519_dalek::Signature::from_slice(signature)
+            .map_err(|e| format!("Invalid Ed25519 signature: {}", e))?;
+        return key.verify_strict(message, &signature).map_err(|e| e.to_string());
     }
     
-    // Check for Authorization header (Bearer token)
-    if let Some(auth_header) = req.headers.iter()
-        .find(|(name, _)| name.to_lowercase() == "authorization")
-        .map(|(_, value)| value) {
-        
-        if let Some(token) = auth_header.strip_prefix("Bearer ") {
-            return crate::auth::verify_token(token).await;
-        }
+    if contains(public_key_der, &P256_CURVE_OID) {
+        use p256::ecdsa::signature::Verifier;
+        use p256::pkcs8::DecodePublicKey;
+        let key = p256::ecdsa::VerifyingKey::from_public_key_der(public_key_der)
+            .map_err(|e| format!("Invalid P-256 public key: {}", e))?;
+        let signature = p256::ecdsa::Signature::from_slice(signature)
+            .map_err(|e| format!("Invalid P-256 signature: {}", e))?;
+        return key.verify(message, &signature).map_err(|e| e.to_string());
     }
     
-    // Check for II authentication in request body (for login endpoints)
-    if !req.body.is_empty() {
-        let body_str = String::from_utf8_lossy(&req.body);
-        if body_str.contains("delegation_chain") {
-            return authenticate_with_ii(&body_str).await;
-        }
+    if contains(public_key_der, &SECP256K1_CURVE_OID) {
+        use k256::ecdsa::signature::Verifier;
+        use k256::pkcs8::DecodePublicKey;
+        let key = k256::ecdsa::VerifyingKey::from_public_key_der(public_key_der)
+            .map_err(|e| format!("Invalid secp256k1 public key: {}", e))?;
+        let signature = k256::ecdsa::Signature::from_slice(signature)
+            .map_err(|e| format!("Invalid secp256k1 signature: {}", e))?;
+        return key.verify(message, &signature).map_err(|e| e.to_string());
     }
     
-    Err("No valid authentication found".to_string())
+    Err("Unsupported public key type".to_string())
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
 }
 
+fn decode_base64(value: &str, what: &str) -> Result<Vec<u8>, String> {
+    general_purpose::STANDARD.decode(value)
+        .map_err(|e| format!("Invalid {} encoding: {}", what, e))
+}
+
+fn create_ii_session(verified: &VerifiedDelegation, now: u64) -> IISession {
+    // Sessions never outlive the delegation they were created from
+    let absolute_expires_at = now.saturating_add(SESSION_MAX_LIFETIME_NS).min(verified.expiration);
+    let mut session = IISession {
+        user_principal: verified.principal,
+        session_public_key: verified.session_public_key.clone(),
+        created_at: now,
+        last_used_at: now,
+        expires_at: now,
+        absolute_expires_at,
+    };
+    touch_session(&mut session, now);
+    session
+}
+
+fn generate_session_key() -> Result<String, String> {
+    Ok(format!("{}{}", SESSION_KEY_PREFIX, crate::rng::random_hex(32)?))
+}
+
+// Session keys are accepted from the `X-II-Session` header, a bearer token or the session cookie
 pub fn extract_ii_session(req: &HttpRequest) -> Option<String> {
-    // Check for session in headers
     for (name, value) in &req.headers {
-        if name.to_lowercase() == "x-ii-session" {
-            return Some(value.clone());
+        match name.to_lowercase().as_str() {
+            "x-ii-session" => return Some(value.clone()),
+            "authorization" => {
+                if let Some(token) = value.strip_prefix("Bearer ") {
+                    if token.starts_with(SESSION_KEY_PREFIX) {
+                        return Some(token.to_string());
+                    }
+                }
+            }
+            _ => {}
         }
     }
     
@@ -201,43 +457,97 @@ pub fn extract_ii_session(req: &HttpRequest) -> Option<String> {
 }
 
 fn extract_session_from_cookie(cookie_header: &str) -> Option<String> {
+    let prefix = format!("{}=", SESSION_COOKIE);
     for cookie in cookie_header.split(';') {
         let cookie = cookie.trim();
-        if cookie.starts_with("ii_session=") {
-            return Some(cookie.strip_prefix("ii_session=").unwrap_or("").to_string());
+        if let Some(session) = cookie.strip_prefix(&prefix) {
+            return Some(session.to_string());
         }
     }
     None
 }
 
+// `Set-Cookie` value delivering a session key to browsers
+pub fn session_cookie(session_key: &str, expires_at: u64, now: u64) -> String {
+    let max_age = expires_at.saturating_sub(now) / 1_000_000_000;
+    format!("{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Strict", SESSION_COOKIE, session_key, max_age)
+}
+
+pub fn clear_session_cookie() -> String {
+    format!("{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Strict", SESSION_COOKIE)
+}
+
 // Session management functions
-pub fn list_active_sessions() -> Vec<IISession> {
+
+// The user's unexpired sessions, oldest first; `current_key` marks the one in use
+pub fn list_user_sessions(user_principal: Principal, current_key: Option<&str>) -> Vec<SessionInfo> {
+    let current_hash = current_key.map(session_hash);
+    let current_time = time();
+    
     II_SESSIONS.with(|sessions| {
-        let current_time = time();
-        sessions.borrow()
-            .values()
-            .filter(|session| session.expires_at > current_time)
-            .cloned()
-            .collect()
+        let sessions = sessions.borrow();
+        let mut user_sessions: Vec<SessionInfo> = match sessions.as_ref() {
+            Some(session_map) => session_map
+                .iter()
+                .filter(|(_, session)| session.user_principal == user_principal && session.expires_at > current_time)
+                .map(|(key_hash, session)| SessionInfo {
+                    id: session_id(&key_hash),
+                    current: current_hash.as_deref() == Some(key_hash.as_str()),
+                    created_at: session.created_at,
+                    last_used_at: session.last_used_at,
+                    expires_at: session.expires_at,
+                    absolute_expires_at: session.absolute_expires_at,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        user_sessions.sort_by_key(|session| session.created_at);
+        user_sessions
     })
 }
 
 pub fn revoke_session(session_key: &str) -> bool {
+    let key_hash = session_hash(session_key);
     II_SESSIONS.with(|sessions| {
-        sessions.borrow_mut().remove(session_key).is_some()
+        sessions.borrow_mut().as_mut().and_then(|session_map| session_map.remove(&key_hash)).is_some()
     })
 }
 
-pub fn cleanup_expired_sessions() {
+// Revoke one of the user's sessions by the id shown in the listing
+pub fn revoke_user_session(user_principal: Principal, id: &str) -> bool {
+    remove_sessions(|key_hash, session| session.user_principal == user_principal && session_id(key_hash) == id) > 0
+}
+
+// "Log out everywhere"
+pub fn revoke_all_user_sessions(user_principal: Principal) -> usize {
+    remove_sessions(|_, session| session.user_principal == user_principal)
+}
+
+pub fn cleanup_expired_sessions() -> usize {
     let current_time = time();
-    II_SESSIONS.with(|sessions| {
-        let mut sessions_map = sessions.borrow_mut();
-        sessions_map.retain(|_, session| session.expires_at > current_time);
-    });
+    remove_sessions(|_, session| session.expires_at <= current_time)
 }
 
-// Helper function to add hex dependency
-use hex;
+fn remove_sessions(predicate: impl Fn(&str, &IISession) -> bool) -> usize {
+    II_SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let session_map = match sessions.as_mut() {
+            Some(session_map) => session_map,
+            None => return 0,
+        };
+        
+        let matching: Vec<String> = session_map
+            .iter()
+            .filter(|(key_hash, session)| predicate(key_hash, session))
+            .map(|(key_hash, _)| key_hash)
+            .collect();
+        
+        for key_hash in &matching {
+            session_map.remove(key_hash);
+        }
+        matching.len()
+    })
+}
 
 #[cfg(test)]
 mod tests {
@@ -245,10 +555,131 @@ mod tests {
 
     #[test]
     fn test_session_key_generation() {
-        let key1 = generate_session_key();
-        let key2 = generate_session_key();
+        crate::rng::mix_seed(b"session key test seed");
+        let key1 = generate_session_key().unwrap();
+        let key2 = generate_session_key().unwrap();
         assert_ne!(key1, key2);
-        assert_eq!(key1.len(), 64); // SHA256 hex string length
+        assert!(key1.starts_with(SESSION_KEY_PREFIX));
+        assert_eq!(key1.len(), SESSION_KEY_PREFIX.len() + 64); // 32 random bytes as hex
+    }
+
+    fn ed25519_der(key: &ed25519_dalek::SigningKey) -> Vec<u8> {
+        let mut der = ED25519_DER_PREFIX.to_vec();
+        der.extend_from_slice(key.verifying_key().as_bytes());
+        der
+    }
+
+    fn signed_delegation(signer: &ed25519_dalek::SigningKey, delegation: &Delegation) -> IIDelegationData {
+        use ed25519_dalek::Signer;
+        let targets: Option<Vec<Vec<u8>>> = delegation.targets
+            .as_ref()
+            .map(|targets| targets.iter().map(|t| t.to_vec()).collect());
+        let message = delegation_signing_message(&delegation.pubkey, delegation.expiration, targets.as_ref());
+        IIDelegationData {
+            delegation: general_purpose::STANDARD.encode(serde_cbor::to_vec(delegation).unwrap()),
+            signature: general_purpose::STANDARD.encode(signer.sign(&message).to_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_verify_delegation_chain() {
+        let user_key = ed25519_dalek::SigningKey::from_bytes(&[1u8; 32]);
+        let session_key = ed25519_dalek::SigningKey::from_bytes(&[2u8; 32]);
+        let canister_id = Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap();
+        let other_canister = Principal::from_text("rdmx6-jaaaa-aaaaa-aaadq-cai").unwrap();
+        let user_public_key = general_purpose::STANDARD.encode(ed25519_der(&user_key));
+        let now = 1_000;
+
+        let delegation = Delegation {
+            pubkey: ed25519_der(&session_key),
+            expiration: 2_000,
+            targets: Some(vec![serde_bytes::ByteBuf::from(canister_id.as_slice().to_vec())]),
+        };
+        let chain = vec![signed_delegation(&user_key, &delegation)];
+
+        let verified = verify_delegation_chain(&chain, &user_public_key, now, canister_id, &[]).unwrap();
+        assert_eq!(verified.principal, Principal::self_authenticating(ed25519_der(&user_key)));
+        assert_eq!(verified.session_public_key, ed25519_der(&session_key));
+        assert_eq!(verified.expiration, 2_000);
+
+        // Expired, wrong target and forged chains are rejected
+        assert!(verify_delegation_chain(&chain, &user_public_key, 2_000, canister_id, &[]).is_err());
+        assert!(verify_delegation_chain(&chain, &user_public_key, now, other_canister, &[]).is_err());
+        let forged = vec![signed_delegation(&session_key, &delegation)];
+        assert!(verify_delegation_chain(&forged, &user_public_key, now, canister_id, &[]).is_err());
+        let mut tampered = chain.clone();
+        tampered[0].delegation = general_purpose::STANDARD.encode(serde_cbor::to_vec(&Delegation {
+            expiration: 9_000,
+            ..delegation.clone()
+        }).unwrap());
+        assert!(verify_delegation_chain(&tampered, &user_public_key, now, canister_id, &[]).is_err());
+    }
+
+    #[test]
+    fn test_verify_ecdsa_signatures() {
+        use p256::ecdsa::signature::Signer;
+        use p256::pkcs8::EncodePublicKey;
+        let key = p256::ecdsa::SigningKey::from_slice(&[3u8; 32]).unwrap();
+        let der = key.verifying_key().to_public_key_der().unwrap().as_bytes().to_vec();
+        let message = login_signing_message(Principal::anonymous(), 42);
+        let signature: p256::ecdsa::Signature = key.sign(&message);
+
+        assert!(verify_basic_signature(&der, &message, &signature.to_bytes()).is_ok());
+        assert!(verify_basic_signature(&der, &login_signing_message(Principal::anonymous(), 43), &signature.to_bytes()).is_err());
+        assert!(verify_basic_signature(&[0u8; 10], &message, &signature.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_login_cannot_be_replayed() {
+        USED_LOGINS.with(|used| {
+            let memory_manager = ic_stable_structures::memory_manager::MemoryManager::init(DefaultMemoryImpl::default());
+            *used.borrow_mut() = Some(StableBTreeMap::init(memory_manager.get(crate::storage::MEMORY_ID_II_USED_LOGINS)));
+        });
+        let session_key = [4u8; 44];
+
+        assert!(record_login(&session_key, 1_000).is_ok());
+        assert!(record_login(&session_key, 1_000).is_err());
+        // Another timestamp or session key is a different login
+        assert!(record_login(&session_key, 1_001).is_ok());
+        assert!(record_login(&[5u8; 44], 1_000).is_ok());
+
+        // Entries are kept while the timestamp is still accepted
+        assert_eq!(cleanup_used_logins(1_000 + LOGIN_TIMESTAMP_TOLERANCE_NS), 0);
+        assert_eq!(cleanup_used_logins(1_001 + LOGIN_TIMESTAMP_TOLERANCE_NS), 2);
+        assert_eq!(cleanup_used_logins(u64::MAX), 1);
+    }
+
+    #[test]
+    fn test_sliding_expiry_is_capped() {
+        let verified = VerifiedDelegation {
+            principal: Principal::anonymous(),
+            session_public_key: vec![1, 2, 3],
+            expiration: SESSION_IDLE_TIMEOUT_NS * 3,
+        };
+        let mut session = create_ii_session(&verified, 0);
+        assert_eq!(session.expires_at, SESSION_IDLE_TIMEOUT_NS);
+        assert_eq!(session.absolute_expires_at, SESSION_IDLE_TIMEOUT_NS * 3);
+
+        touch_session(&mut session, SESSION_IDLE_TIMEOUT_NS);
+        assert_eq!(session.expires_at, SESSION_IDLE_TIMEOUT_NS * 2);
+        assert_eq!(session.last_used_at, SESSION_IDLE_TIMEOUT_NS);
+
+        // Use near the end cannot extend past the absolute cap
+        touch_session(&mut session, SESSION_IDLE_TIMEOUT_NS * 5 / 2);
+        assert_eq!(session.expires_at, SESSION_IDLE_TIMEOUT_NS * 3);
+    }
+
+    #[test]
+    fn test_extract_ii_session_from_bearer() {
+        let req = |name: &str, value: &str| HttpRequest {
+            method: "GET".to_string(),
+            url: "/".to_string(),
+            headers: vec![(name.to_string(), value.to_string())],
+            body: Vec::new(),
+        };
+        assert_eq!(extract_ii_session(&req("Authorization", "Bearer om_session_abc")), Some("om_session_abc".to_string()));
+        assert_eq!(extract_ii_session(&req("Authorization", "Bearer om_token_abc")), None);
+        assert_eq!(extract_ii_session(&req("Cookie", "ii_session=om_session_abc")), Some("om_session_abc".to_string()));
     }
 
     #[test]
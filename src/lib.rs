@@ -7,16 +7,25 @@ mod storage;
 mod search;
 mod utils;
 mod embedding;
+mod embedding_cache;
 mod internet_identity;
 mod vector_store;
+mod hnsw;
+mod text_index;
+mod rng;
 mod suggestions;
 mod clustering;
 mod errors;
 mod validation;
+mod candid_api;
+mod certification;
+mod reembed;
+mod categories;
+mod backfill;
 
 pub use types::*;
 pub use http_handlers::*;
-pub use auth::{authenticate_request, verify_token, verify_api_key, extract_api_key};
+pub use auth::{authenticate_request, verify_api_key, extract_api_key, AuthContext};
 pub use storage::*;
 pub use search::*;
 pub use utils::*;
@@ -38,11 +47,35 @@ pub async fn http_request_update(req: HttpRequest) -> HttpResponse {
     http_handlers::handle_http_request_update(req).await
 }
 
+// Normalizes embedding outcall responses so replicas reach consensus
+#[query]
+pub fn transform_embedding_response(args: ic_cdk::api::management_canister::http_request::TransformArgs) -> ic_cdk::api::management_canister::http_request::HttpResponse {
+    embedding::trim_embedding_response(args)
+}
+
+// Local replicas sign Internet Identity delegations with their own root key
+#[update]
+pub fn set_ii_root_key(root_key_der: Vec<u8>) -> std::result::Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can set the root key".to_string());
+    }
+    internet_identity::set_ic_root_key(&root_key_der)
+}
+
 #[init]
 pub async fn init() {
     storage::init_storage().await;
+    rng::start();
+    internet_identity::init_sessions();
+    backfill::init();
     vector_store::AdvancedVectorStore::init().expect("Failed to initialize vector store");
-    clustering::ClusteringEngine::init_categories();
+    text_index::TextIndex::init();
+    embedding_cache::EmbeddingCache::init();
+    clustering::ClusteringEngine::init();
+    categories::init();
+    reembed::init();
+    certification::init();
+    backfill::resume();
 }
 
 #[pre_upgrade]
@@ -53,4 +86,39 @@ pub fn pre_upgrade() {
 #[post_upgrade]
 pub async fn post_upgrade() {
     storage::post_upgrade().await;
-}
\ No newline at end of file
+    rng::start();
+    internet_identity::init_sessions();
+    backfill::init();
+    vector_store::AdvancedVectorStore::init().expect("Failed to re-open vector store");
+    text_index::TextIndex::init();
+    embedding_cache::EmbeddingCache::init();
+    clustering::ClusteringEngine::init();
+    categories::init();
+    reembed::init();
+    certification::init();
+    backfill::resume();
+}
+
+// Generates the service description from the exported methods; `src/openmemory.did` must match it
+ic_cdk_macros::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_candid_interface_matches_did_file() {
+        let generated = super::__export_service();
+        let did_path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/openmemory.did");
+
+        // UPDATE_CANDID=1 cargo test rewrites the .did file after interface changes
+        if std::env::var("UPDATE_CANDID").is_ok() {
+            std::fs::write(did_path, &generated).unwrap();
+        }
+
+        let committed = std::fs::read_to_string(did_path).unwrap();
+        assert_eq!(
+            committed.trim(),
+            generated.trim(),
+            "src/openmemory.did is out of date; run `UPDATE_CANDID=1 cargo test` to regenerate it"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::time::Duration;
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use serde::{Deserialize, Serialize};
+
+// Background re-embedding of a user's memories after their embedding model
+// changes. Each user has at most one job; its cursor and counters live in
+// stable memory, and a timer works through the memories in id order a batch
+// at a time, so a job resumes where it stopped after an upgrade.
+
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+type JobMap = StableBTreeMap<Principal, ReembedJob, VMem>;
+
+/// Memories re-embedded per timer tick and user (one batched outcall)
+pub const REEMBED_BATCH_SIZE: usize = 20;
+const REEMBED_TICK_INTERVAL: Duration = Duration::from_secs(10);
+// A job fails after this many consecutive batches in which nothing succeeded
+const MAX_FAILED_BATCHES: u32 = 3;
+
+thread_local! {
+    static JOBS: RefCell<Option<JobMap>> = const { RefCell::new(None) };
+    // Outcalls span several messages; a tick must not start while one runs
+    static TICK_RUNNING: RefCell<bool> = const { RefCell::new(false) };
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum ReembedStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ReembedJob {
+    pub user_id: Principal,
+    pub target_model: String,
+    pub status: ReembedStatus,
+    pub total: u64,              // Memories that needed re-embedding when the job started
+    pub processed: u64,          // Re-embedded with the target model
+    pub failed: u64,             // Skipped after an embedding error
+    pub cursor: Option<String>,  // Last memory id examined
+    pub failed_batches: u32,     // Consecutive batches without a single success
+    pub last_error: Option<String>,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+impl Storable for ReembedJob {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl ReembedJob {
+    pub fn percent_complete(&self) -> f64 {
+        if self.total == 0 || self.status == ReembedStatus::Completed {
+            return 100.0;
+        }
+        ((self.processed + self.failed) as f64 / self.total as f64 * 100.0).min(100.0)
+    }
+}
+
+// Called from init and post_upgrade; timers do not survive upgrades
+pub fn init() {
+    JOBS.with(|jobs| {
+        *jobs.borrow_mut() = Some(StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_REEMBED_JOBS)
+        ));
+    });
+
+    ic_cdk_timers::set_timer_interval(REEMBED_TICK_INTERVAL, || ic_cdk::spawn(run_tick()));
+
+    // Embeddings stored before models were tracked are labeled once, in the background
+    if crate::storage::get_memory_count() > 0 {
+        crate::backfill::start_once(crate::backfill::LEGACY_MODEL_JOB);
+    }
+}
+
+pub fn get_job(user: Principal) -> Option<ReembedJob> {
+    JOBS.with(|jobs| jobs.borrow().as_ref().and_then(|jobs| jobs.get(&user)))
+}
+
+fn save_job(job: &ReembedJob) {
+    JOBS.with(|jobs| {
+        if let Some(ref mut jobs) = *jobs.borrow_mut() {
+            jobs.insert(job.user_id, job.clone());
+        }
+    });
+}
+
+fn needs_reembedding(memory: &crate::types::Memory, target_model: &str) -> bool {
+    memory.embedding_model.as_deref() != Some(target_model)
+}
+
+// Start (or restart) migrating the user's memories to their current model.
+// A running job for the same model is returned unchanged.
+pub fn start_job(user: Principal) -> Result<ReembedJob, String> {
+    let target_model = crate::embedding::embedding_model_for_user(user)
+        .ok_or("No embedding provider configured. Please set your API key in settings.")?;
+
+    if let Some(job) = get_job(user) {
+        if job.status == ReembedStatus::Running && job.target_model == target_model {
+            return Ok(job);
+        }
+    }
+
+    let total = crate::storage::user_memory_ids(user)
+        .iter()
+        .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+        .filter(|memory| needs_reembedding(memory, &target_model))
+        .count() as u64;
+
+    let now = ic_cdk::api::time();
+    let job = ReembedJob {
+        user_id: user,
+        target_model,
+        status: if total == 0 { ReembedStatus::Completed } else { ReembedStatus::Running },
+        total,
+        processed: 0,
+        failed: 0,
+        cursor: None,
+        failed_batches: 0,
+        last_error: None,
+        started_at: now,
+        updated_at: now,
+        finished_at: if total == 0 { Some(now) } else { None },
+    };
+    save_job(&job);
+    ic_cdk::println!("Re-embedding job for {}: {} memories to {}", user, total, job.target_model);
+    Ok(job)
+}
+
+pub fn cancel_job(user: Principal) -> Option<ReembedJob> {
+    let mut job = get_job(user)?;
+    if job.status == ReembedStatus::Running {
+        job.status = ReembedStatus::Cancelled;
+        job.updated_at = ic_cdk::api::time();
+        job.finished_at = Some(job.updated_at);
+        save_job(&job);
+    }
+    Some(job)
+}
+
+// Clears TICK_RUNNING when the tick ends, also when a batch traps or its
+// future is dropped midway, so a failed tick cannot stall every later one
+struct TickGuard;
+
+impl Drop for TickGuard {
+    fn drop(&mut self) {
+        TICK_RUNNING.with(|running| *running.borrow_mut() = false);
+    }
+}
+
+async fn run_tick() {
+    if TICK_RUNNING.with(|running| running.replace(true)) {
+        return;
+    }
+    let _guard = TickGuard;
+
+    let running: Vec<ReembedJob> = JOBS.with(|jobs| {
+        jobs.borrow()
+            .as_ref()
+            .map(|jobs| {
+                jobs.iter()
+                    .map(|(_, job)| job)
+                    .filter(|job| job.status == ReembedStatus::Running)
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+
+    for job in running {
+        run_batch(job).await;
+    }
+}
+
+// Ids after `cursor` in id order, up to `batch_size` of which need work. Returns
+// them, the new cursor, and whether the end of the list was reached.
+fn select_batch<F>(ids: &[String], cursor: Option<&str>, batch_size: usize, needs_work: F) -> (Vec<String>, Option<String>, bool)
+where
+    F: Fn(&str) -> bool,
+{
+    let mut sorted: Vec<&String> = ids.iter().filter(|id| cursor.is_none_or(|cursor| id.as_str() > cursor)).collect();
+    sorted.sort();
+
+    let mut batch = Vec::new();
+    let mut last = cursor.map(str::to_string);
+    for id in &sorted {
+        if batch.len() >= batch_size {
+            return (batch, last, false);
+        }
+        last = Some(id.to_string());
+        if needs_work(id) {
+            batch.push(id.to_string());
+        }
+    }
+    (batch, last, true)
+}
+
+async fn run_batch(mut job: ReembedJob) {
+    let user = job.user_id;
+    let ids = crate::storage::user_memory_ids(user);
+    let (batch, cursor, done) = select_batch(&ids, job.cursor.as_deref(), REEMBED_BATCH_SIZE, |id| {
+        crate::storage::get_memory(id)
+            .ok()
+            .flatten()
+            .is_some_and(|memory| needs_reembedding(&memory, &job.target_model))
+    });
+
+    let mut succeeded = 0;
+    let mut errors = Vec::new();
+    if !batch.is_empty() {
+        let provider = match crate::embedding::provider_for_user(user) {
+            Ok(provider) if provider.model() == job.target_model => provider,
+            Ok(provider) => {
+                finish(&mut job, ReembedStatus::Failed, Some(format!(
+                    "Embedding model changed to {}; start a new job", provider.model()
+                )));
+                return;
+            }
+            Err(e) => {
+                finish(&mut job, ReembedStatus::Failed, Some(e));
+                return;
+            }
+        };
+
+        let memories: Vec<crate::types::Memory> = batch
+            .iter()
+            .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+            .collect();
+        let texts: Vec<String> = memories.iter().map(|memory| memory.content.clone()).collect();
+        let results = provider.embed_batch(&texts).await;
+
+        for (memory, result) in memories.into_iter().zip(results) {
+            let embedding = match result {
+                Ok(embedding) => embedding,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+
+            // The memory may have been edited or deleted while the outcall ran
+            let current = match crate::storage::get_memory(&memory.id) {
+                Ok(Some(current)) if current.content == memory.content => current,
+                _ => continue,
+            };
+            let updated = crate::types::Memory {
+                embedding,
+                embedding_model: Some(job.target_model.clone()),
+                ..current
+            };
+            match crate::storage::store_memory(updated).await {
+                Ok(()) => succeeded += 1,
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    // The job may have been cancelled or replaced while the outcall ran
+    match get_job(user) {
+        Some(latest) if latest.status == ReembedStatus::Running && latest.started_at == job.started_at => {}
+        _ => return,
+    }
+
+    job.processed += succeeded;
+    job.updated_at = ic_cdk::api::time();
+    if let Some(error) = errors.last() {
+        job.last_error = Some(error.clone());
+    }
+
+    if !batch.is_empty() && succeeded == 0 && !errors.is_empty() {
+        // Nothing worked (e.g. the provider is down); retry the same batch
+        job.failed_batches += 1;
+        if job.failed_batches >= MAX_FAILED_BATCHES {
+            let error = job.last_error.clone();
+            finish(&mut job, ReembedStatus::Failed, error);
+        } else {
+            save_job(&job);
+        }
+        return;
+    }
+
+    job.failed += errors.len() as u64;
+    job.failed_batches = 0;
+    job.cursor = cursor;
+    if done {
+        finish(&mut job, ReembedStatus::Completed, None);
+    } else {
+        save_job(&job);
+    }
+}
+
+fn finish(job: &mut ReembedJob, status: ReembedStatus, error: Option<String>) {
+    job.status = status;
+    if error.is_some() {
+        job.last_error = error;
+    }
+    job.updated_at = ic_cdk::api::time();
+    job.finished_at = Some(job.updated_at);
+    save_job(job);
+    ic_cdk::println!("Re-embedding job for {} finished: {:?}", job.user_id, job.status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_batch_resumes_after_cursor() {
+        let ids: Vec<String> = ["d", "a", "c", "b", "e"].iter().map(|id| id.to_string()).collect();
+        let needs_work = |id: &str| id != "b";
+
+        let (batch, cursor, done) = select_batch(&ids, None, 2, needs_work);
+        assert_eq!(batch, vec!["a", "c"]);
+        assert_eq!(cursor.as_deref(), Some("c"));
+        assert!(!done);
+
+        let (batch, cursor, done) = select_batch(&ids, cursor.as_deref(), 2, needs_work);
+        assert_eq!(batch, vec!["d", "e"]);
+        assert_eq!(cursor.as_deref(), Some("e"));
+        assert!(done);
+
+        let (batch, cursor, done) = select_batch(&ids, Some("e"), 2, needs_work);
+        assert!(batch.is_empty());
+        assert_eq!(cursor.as_deref(), Some("e"));
+        assert!(done);
+    }
+}
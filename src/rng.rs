@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::time::Duration;
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use sha2::{Digest, Sha256};
+
+// Canister-wide CSPRNG for credentials and identifiers. ChaCha20 is seeded
+// from the management canister's `raw_rand` and reseeded on a timer; every
+// reseed mixes the fresh randomness with the current state, so neither a
+// single seed nor a single snapshot of the state predicts later output.
+
+/// How often fresh randomness is mixed into the generator
+pub const RESEED_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+thread_local! {
+    static RNG: RefCell<Option<ChaCha20Rng>> = const { RefCell::new(None) };
+}
+
+// Called from init and post_upgrade. Inter-canister calls are not allowed
+// there, so the first seed is fetched from a zero-delay timer.
+pub fn start() {
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(reseed_in_background()));
+    ic_cdk_timers::set_timer_interval(RESEED_INTERVAL, || ic_cdk::spawn(reseed_in_background()));
+}
+
+async fn reseed_in_background() {
+    if let Err(e) = reseed().await {
+        ic_cdk::println!("RNG reseed failed: {}", e);
+    }
+}
+
+pub async fn reseed() -> Result<(), String> {
+    let (entropy,) = ic_cdk::api::management_canister::main::raw_rand()
+        .await
+        .map_err(|(code, msg)| format!("Failed to get randomness: {:?} {}", code, msg))?;
+    mix_seed(&entropy);
+    Ok(())
+}
+
+// Fold entropy into the generator; the first call seeds it
+pub fn mix_seed(entropy: &[u8]) {
+    RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let mut hasher = Sha256::new();
+        if let Some(ref mut current) = *rng {
+            let mut state = [0u8; 32];
+            current.fill_bytes(&mut state);
+            hasher.update(state);
+        }
+        hasher.update(entropy);
+        *rng = Some(ChaCha20Rng::from_seed(hasher.finalize().into()));
+    });
+}
+
+pub fn is_seeded() -> bool {
+    RNG.with(|rng| rng.borrow().is_some())
+}
+
+// Seed on demand when an update call needs randomness before the timer has run
+pub async fn ensure_seeded() -> Result<(), String> {
+    if !is_seeded() {
+        reseed().await?;
+    }
+    Ok(())
+}
+
+// Random bytes, or an error if the generator has not been seeded yet
+pub fn random_bytes(len: usize) -> Result<Vec<u8>, String> {
+    RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        let rng = rng.as_mut().ok_or_else(|| "Random number generator not seeded yet".to_string())?;
+        let mut bytes = vec![0u8; len];
+        rng.fill_bytes(&mut bytes);
+        Ok(bytes)
+    })
+}
+
+pub fn random_hex(len: usize) -> Result<String, String> {
+    random_bytes(len).map(hex::encode)
+}
+
+pub async fn secure_random_hex(len: usize) -> Result<String, String> {
+    ensure_seeded().await?;
+    random_hex(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_bytes_require_seed() {
+        assert!(random_bytes(16).is_err());
+
+        mix_seed(&[7u8; 32]);
+        let first = random_bytes(32).unwrap();
+        let second = random_bytes(32).unwrap();
+        assert_eq!(first.len(), 32);
+        assert_ne!(first, second);
+
+        // Reseeding with the same entropy does not replay earlier output
+        mix_seed(&[7u8; 32]);
+        assert_ne!(random_bytes(32).unwrap(), first);
+        assert_eq!(random_hex(16).unwrap().len(), 32);
+    }
+}
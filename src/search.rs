@@ -1,86 +1,219 @@
 use crate::types::*;
 use crate::storage::*;
 use candid::Principal;
+use std::collections::HashSet;
 
 // This module will contain embedding-based search functionality
 // For now, it provides a placeholder for future vector search implementation
 
+// Upper bound on candidates pulled from a ranking while filling a filtered page
+const MAX_FILTER_CANDIDATES: usize = crate::validation::MAX_MEMORIES_PER_USER;
+
 pub async fn semantic_search(
+    query: &str,
     query_embedding: Vec<f32>,
+    model: &str,
     limit: usize,
-    user_filter: Option<Principal>,
-    tags: Option<Vec<String>>,
+    user_id: Principal,
+    filters: &SearchFilters,
 ) -> Result<Vec<SearchResult>, String> {
-    // Use advanced vector store for semantic search
-    let similar_ids = crate::vector_store::AdvancedVectorStore::search_similar(
-        &query_embedding, 
-        limit * 2, // Get more results to filter by user/tags
-        None
-    )?;
-    
-    let mut results = Vec::new();
-    
-    for (memory_id, similarity_score) in similar_ids {
-        // Get memory details
-        if let Ok(Some(memory)) = crate::storage::get_memory(&memory_id) {
-            // Apply user filter
-            if let Some(user) = user_filter {
-                if memory.user_id != user {
-                    continue;
-                }
-            }
-            
-            // Apply tag filter
-            if let Some(ref required_tags) = tags {
-                let has_required_tags = required_tags.iter().any(|tag| {
-                    memory.tags.iter().any(|memory_tag| 
-                        memory_tag.to_lowercase().contains(&tag.to_lowercase())
-                    )
-                });
-                if !has_required_tags {
-                    continue;
-                }
-            }
-            
-            // Calculate enhanced relevance score
-            let enhanced_score = calculate_relevance_score(&memory, "", similarity_score);
-            
-            results.push(SearchResult {
-                memory,
-                similarity_score: enhanced_score,
-            });
-            
-            // Stop when we have enough results
-            if results.len() >= limit {
-                break;
-            }
-        }
-    }
-    
-    Ok(results)
+    // Search only the user's vector partition
+    collect_filtered_results(
+        |candidates| {
+            // The requested minimum replaces the store's default threshold
+            let similar_ids = crate::vector_store::AdvancedVectorStore::search_similar(
+                user_id,
+                &query_embedding, 
+                model,
+                candidates,
+                filters.min_similarity
+            )?;
+            let exhausted = similar_ids.len() < candidates;
+            let ranked = similar_ids
+                .into_iter()
+                .map(|(id, similarity)| (id, similarity, Some(similarity)))
+                .collect();
+            Ok((ranked, exhausted))
+        },
+        limit,
+        filters,
+        // Calculate enhanced relevance score
+        |memory, similarity_score| calculate_relevance_score(memory, query, similarity_score),
+    )
 }
 
 pub async fn generate_embedding_and_search(
     query: &str,
     limit: usize,
-    user_filter: Option<Principal>,
-    tags: Option<Vec<String>>,
+    user_id: Principal,
+    filters: &SearchFilters,
 ) -> Result<Vec<SearchResult>, String> {
-    // Use the user_filter as the user_id for generating embeddings
-    let user_id = user_filter.ok_or("User ID required for search")?;
-    
     // Generate embedding for query using the user's API configuration
-    match crate::embedding::generate_embedding_for_user(query, user_id).await {
-        Ok(query_embedding) => {
+    match crate::embedding::generate_query_embedding_for_user(query, user_id).await {
+        Ok((query_embedding, model)) => {
             // Perform semantic search
-            semantic_search(query_embedding, limit, Some(user_id), tags).await
+            semantic_search(query, query_embedding, &model, limit, user_id, filters).await
         }
         Err(e) => {
             ic_cdk::println!("Failed to generate embedding for search: {}", e);
-            // Fallback to simple text search
-            crate::storage::search_memories_simple(query, limit, tags, Some(user_id))
+            // Fallback to simple text search; filters are applied to the full match set
+            let matches = crate::storage::search_memories_simple(query, usize::MAX, None, Some(user_id))?;
+            let mut results = apply_search_filters(matches, filters);
+            results.truncate(limit);
+            Ok(results)
+        }
+    }
+}
+
+// How POST /memories/search ranks results
+#[derive(candid::CandidType, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    #[default]
+    Semantic,
+    Keyword,
+    Hybrid,
+}
+
+// Rank constant for reciprocal rank fusion; 60 is the value from the original RRF paper
+const RRF_K: f32 = 60.0;
+
+// Keyword (BM25) and/or vector search over the user's memories, fused with
+// weighted reciprocal rank fusion. `semantic_weight` is the share given to the
+// vector ranking (0.0 = keyword only, 1.0 = vector only).
+pub async fn hybrid_search(
+    query: &str,
+    limit: usize,
+    user_id: Principal,
+    filters: &SearchFilters,
+    mode: SearchMode,
+    semantic_weight: f32,
+) -> Result<Vec<SearchResult>, String> {
+    if mode == SearchMode::Semantic {
+        return generate_embedding_and_search(query, limit, user_id, filters).await;
+    }
+    
+    let mut semantic_weight = semantic_weight;
+    let query_embedding = if mode == SearchMode::Hybrid {
+        match crate::embedding::generate_query_embedding_for_user(query, user_id).await {
+            Ok(query_embedding) => Some(query_embedding),
+            Err(e) => {
+                ic_cdk::println!("Failed to generate embedding for hybrid search, using keywords only: {}", e);
+                None
+            }
         }
+    } else {
+        None
+    };
+    if query_embedding.is_none() {
+        semantic_weight = 0.0;
     }
+    
+    collect_filtered_results(
+        |candidates| {
+            // Fusion needs candidates beyond the final limit from both rankings
+            let keyword_ranked = crate::text_index::TextIndex::search(user_id, query, candidates);
+            // Every vector hit takes part in the fusion; weak ones still rank
+            // below strong ones, and `min_similarity` is left to the filters
+            let vector_ranked = match query_embedding {
+                Some((ref query_embedding, ref model)) => crate::vector_store::AdvancedVectorStore::search_similar(
+                    user_id,
+                    query_embedding,
+                    model,
+                    candidates,
+                    Some(f32::MIN)
+                )?,
+                None => Vec::new(),
+            };
+            let exhausted = keyword_ranked.len() < candidates && vector_ranked.len() < candidates;
+            let similarities: HashMap<&str, f32> = vector_ranked
+                .iter()
+                .map(|(id, similarity)| (id.as_str(), *similarity))
+                .collect();
+            let ranked = reciprocal_rank_fusion(&vector_ranked, &keyword_ranked, semantic_weight)
+                .into_iter()
+                .map(|(id, score)| {
+                    let similarity = similarities.get(id.as_str()).copied();
+                    (id, score, similarity)
+                })
+                .collect();
+            Ok((ranked, exhausted))
+        },
+        limit,
+        filters,
+        |_, score| score,
+    )
+}
+
+// Best-first (id, ranking score, raw cosine similarity) candidates. The
+// similarity is None for candidates the vector search did not return.
+type RankedCandidates = Vec<(String, f32, Option<f32>)>;
+
+// Build a full page of filtered results from a best-first ranking. `fetch`
+// returns the top `n` candidates and whether the ranking has no more; the
+// candidate window grows until the page is full or the ranking is exhausted.
+fn collect_filtered_results<F, S>(
+    mut fetch: F,
+    limit: usize,
+    filters: &SearchFilters,
+    score: S,
+) -> Result<Vec<SearchResult>, String>
+where
+    F: FnMut(usize) -> Result<(RankedCandidates, bool), String>,
+    S: Fn(&Memory, f32) -> f32,
+{
+    let mut candidates = (limit * 2).max(10);
+    
+    loop {
+        let (ranked, exhausted) = fetch(candidates)?;
+        
+        let mut results = Vec::new();
+        for (memory_id, ranked_score, similarity) in ranked {
+            // Get memory details
+            if let Ok(Some(memory)) = crate::storage::get_memory(&memory_id) {
+                let similarity_score = score(&memory, ranked_score);
+                let result = SearchResult { memory, similarity_score };
+                if matches_search_filters(&result, similarity, filters) {
+                    results.push(result);
+                    // Stop when we have enough results
+                    if results.len() >= limit {
+                        return Ok(results);
+                    }
+                }
+            }
+        }
+        
+        if !filters.is_active() || exhausted || candidates >= MAX_FILTER_CANDIDATES {
+            return Ok(results);
+        }
+        candidates = (candidates * 4).min(MAX_FILTER_CANDIDATES);
+    }
+}
+
+// Weighted RRF over two best-first rankings. Scores are scaled so that an
+// item ranked first in both lists scores 1.0.
+pub fn reciprocal_rank_fusion(
+    vector_ranked: &[(String, f32)],
+    keyword_ranked: &[(String, f32)],
+    semantic_weight: f32,
+) -> Vec<(String, f32)> {
+    let weight = semantic_weight.clamp(0.0, 1.0);
+    let mut scores: HashMap<String, f32> = HashMap::new();
+    
+    for (rank, (id, _)) in vector_ranked.iter().enumerate() {
+        *scores.entry(id.clone()).or_insert(0.0) += weight / (RRF_K + rank as f32 + 1.0);
+    }
+    for (rank, (id, _)) in keyword_ranked.iter().enumerate() {
+        *scores.entry(id.clone()).or_insert(0.0) += (1.0 - weight) / (RRF_K + rank as f32 + 1.0);
+    }
+    
+    let mut fused: Vec<(String, f32)> = scores
+        .into_iter()
+        .filter(|(_, score)| *score > 0.0)
+        .map(|(id, score)| (id, score * (RRF_K + 1.0)))
+        .collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    fused
 }
 
 pub async fn generate_query_embedding(query: &str) -> Result<Vec<f32>, String> {
@@ -129,8 +262,11 @@ pub fn calculate_relevance_score(
 ) -> f32 {
     let mut score = similarity_score;
     
-    // Boost score based on metadata matches
-    let query_lower = query.to_lowercase();
+    // Boost score based on metadata matches; an empty query matches nothing
+    let query_lower = query.trim().to_lowercase();
+    if query_lower.is_empty() {
+        return (score * calculate_recency_factor(memory.created_at)).min(1.0);
+    }
     for (key, value) in &memory.metadata {
         if key.to_lowercase().contains(&query_lower) || 
            value.to_lowercase().contains(&query_lower) {
@@ -181,71 +317,145 @@ pub fn preprocess_query(query: &str) -> String {
         .join(" ")
 }
 
+// How multiple requested tags combine
+#[derive(candid::CandidType, serde::Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatchMode {
+    // At least one of the tags (OR)
+    #[default]
+    Any,
+    // Every tag (AND)
+    All,
+}
+
 // Advanced search filters
+#[derive(Default)]
 pub struct SearchFilters {
     pub user_filter: Option<Principal>,
     pub tags: Option<Vec<String>>,
+    pub tag_mode: TagMatchMode,
     pub metadata_filters: Option<HashMap<String, String>>,
     pub date_range: Option<(u64, u64)>, // (start, end) timestamps
     pub min_similarity: Option<f32>,
+    pub memory_ids: Option<HashSet<String>>, // e.g. the members of a collection
 }
 
+impl SearchFilters {
+    // Fails if the request names a collection the user does not have
+    pub fn from_request(user_id: Principal, req: &crate::validation::SearchRequest) -> crate::errors::Result<Self> {
+        let date_range = match (req.created_after, req.created_before) {
+            (None, None) => None,
+            (start, end) => Some((start.unwrap_or(0), end.unwrap_or(u64::MAX))),
+        };
+        
+        let memory_ids = match req.collection_id {
+            Some(ref collection_id) => {
+                let cluster = crate::clustering::ClusteringEngine::get_user_cluster(user_id, collection_id)?;
+                Some(cluster.memory_ids.into_iter().collect())
+            }
+            None => None,
+        };
+        
+        Ok(Self {
+            user_filter: Some(user_id),
+            tags: req.tags.clone().filter(|tags| !tags.is_empty()),
+            tag_mode: req.tag_mode.unwrap_or_default(),
+            metadata_filters: req.metadata.clone().filter(|metadata| !metadata.is_empty()),
+            date_range,
+            min_similarity: req.min_similarity,
+            memory_ids,
+        })
+    }
+    
+    // Whether any filter beyond the user partition can drop results
+    pub fn is_active(&self) -> bool {
+        self.tags.is_some()
+            || self.metadata_filters.is_some()
+            || self.date_range.is_some()
+            || self.min_similarity.is_some()
+            || self.memory_ids.is_some()
+    }
+}
+
+// For results that have no vector similarity, e.g. from the text fallback
 pub fn apply_search_filters(
     memories: Vec<SearchResult>,
     filters: &SearchFilters,
 ) -> Vec<SearchResult> {
     memories
         .into_iter()
-        .filter(|result| {
-            // Apply user filter
-            if let Some(user) = filters.user_filter {
-                if result.memory.user_id != user {
-                    return false;
-                }
-            }
-            
-            // Apply tag filter
-            if let Some(ref required_tags) = filters.tags {
-                let has_required_tags = required_tags.iter().any(|tag| {
-                    result.memory.tags.iter().any(|memory_tag| 
-                        memory_tag.to_lowercase().contains(&tag.to_lowercase())
-                    )
-                });
-                if !has_required_tags {
-                    return false;
-                }
-            }
-            
-            // Apply metadata filters
-            if let Some(ref metadata_filters) = filters.metadata_filters {
-                for (key, value) in metadata_filters {
-                    if let Some(memory_value) = result.memory.metadata.get(key) {
-                        if !memory_value.to_lowercase().contains(&value.to_lowercase()) {
-                            return false;
-                        }
-                    } else {
-                        return false;
-                    }
-                }
-            }
-            
-            // Apply date range filter
-            if let Some((start, end)) = filters.date_range {
-                if result.memory.created_at < start || result.memory.created_at > end {
-                    return false;
-                }
-            }
-            
-            // Apply minimum similarity filter
-            if let Some(min_sim) = filters.min_similarity {
-                if result.similarity_score < min_sim {
+        .filter(|result| matches_search_filters(result, None, filters))
+        .collect()
+}
+
+// `similarity` is the raw cosine similarity from the vector search, before any
+// relevance boosting or rank fusion; `min_similarity` is checked against it,
+// so results without one never pass a similarity threshold
+pub fn matches_search_filters(result: &SearchResult, similarity: Option<f32>, filters: &SearchFilters) -> bool {
+    if !matches_memory_filters(&result.memory, filters) {
+        return false;
+    }
+    
+    // Apply minimum similarity filter
+    if let Some(min_sim) = filters.min_similarity {
+        if !similarity.is_some_and(|similarity| similarity >= min_sim) {
+            return false;
+        }
+    }
+    
+    true
+}
+
+// Every filter except the similarity threshold, for use outside of search
+pub fn matches_memory_filters(memory: &Memory, filters: &SearchFilters) -> bool {
+    // Apply user filter
+    if let Some(user) = filters.user_filter {
+        if memory.user_id != user {
+            return false;
+        }
+    }
+    
+    if let Some(ref memory_ids) = filters.memory_ids {
+        if !memory_ids.contains(&memory.id) {
+            return false;
+        }
+    }
+    
+    // Apply tag filter (tags match whole, case-insensitively)
+    if let Some(ref required_tags) = filters.tags {
+        let has_tag = |tag: &String| {
+            memory.tags.iter().any(|memory_tag| memory_tag.eq_ignore_ascii_case(tag))
+        };
+        let matched = match filters.tag_mode {
+            TagMatchMode::Any => required_tags.iter().any(has_tag),
+            TagMatchMode::All => required_tags.iter().all(has_tag),
+        };
+        if !matched {
+            return false;
+        }
+    }
+    
+    // Apply metadata filters
+    if let Some(ref metadata_filters) = filters.metadata_filters {
+        for (key, value) in metadata_filters {
+            if let Some(memory_value) = memory.metadata.get(key) {
+                if !memory_value.to_lowercase().contains(&value.to_lowercase()) {
                     return false;
                 }
+            } else {
+                return false;
             }
-            
-            true
-        })
-        .collect()
+        }
+    }
+    
+    // Apply date range filter
+    if let Some((start, end)) = filters.date_range {
+        if memory.created_at < start || memory.created_at > end {
+            return false;
+        }
+    }
+    
+    true
 }
 
 use std::collections::HashMap;
@@ -261,6 +471,63 @@ mod tests {
         assert_eq!(preprocess_query("multiple   spaces"), "multiple spaces");
     }
     
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let vector = vec![("a".to_string(), 0.9), ("b".to_string(), 0.8)];
+        let keyword = vec![("b".to_string(), 7.0), ("c".to_string(), 3.0)];
+        
+        // Present in both rankings wins under an even weight
+        let fused = reciprocal_rank_fusion(&vector, &keyword, 0.5);
+        assert_eq!(fused[0].0, "b");
+        assert_eq!(fused.len(), 3);
+        
+        // Keyword only ignores the vector ranking entirely
+        let fused = reciprocal_rank_fusion(&vector, &keyword, 0.0);
+        assert_eq!(fused.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["b", "c"]);
+        assert!((fused[0].1 - 1.0).abs() < 1e-6);
+    }
+    
+    #[test]
+    fn test_tag_match_modes() {
+        let result = SearchResult {
+            memory: Memory {
+                id: "m1".to_string(),
+                user_id: Principal::anonymous(),
+                content: "content".to_string(),
+                embedding: Vec::new(),
+                metadata: HashMap::from([("project".to_string(), "OpenMemory".to_string())]),
+                tags: vec!["rust".to_string(), "icp".to_string()],
+                created_at: 100,
+                updated_at: 100,
+                embedding_model: None,
+            },
+            similarity_score: 0.8,
+        };
+        let filters = |tags: &[&str], tag_mode| SearchFilters {
+            tags: Some(tags.iter().map(|t| t.to_string()).collect()),
+            tag_mode,
+            ..Default::default()
+        };
+        
+        assert!(matches_search_filters(&result, None, &filters(&["rust", "python"], TagMatchMode::Any)));
+        assert!(!matches_search_filters(&result, None, &filters(&["rust", "python"], TagMatchMode::All)));
+        assert!(matches_search_filters(&result, None, &filters(&["Rust", "ICP"], TagMatchMode::All)));
+        // Whole-tag matching: a substring of a tag is not a match
+        assert!(!matches_search_filters(&result, None, &filters(&["ru"], TagMatchMode::Any)));
+        
+        let filters = SearchFilters {
+            metadata_filters: Some(HashMap::from([("project".to_string(), "openmemory".to_string())])),
+            date_range: Some((50, 150)),
+            min_similarity: Some(0.9),
+            ..Default::default()
+        };
+        // The threshold applies to the raw similarity, not the boosted score (0.8)
+        assert!(!matches_search_filters(&result, Some(0.85), &filters));
+        assert!(matches_search_filters(&result, Some(0.95), &filters));
+        // Results without a vector similarity never pass a threshold
+        assert!(!matches_search_filters(&result, None, &SearchFilters { min_similarity: Some(0.5), ..filters }));
+    }
+    
     #[test]
     fn test_calculate_recency_factor() {
         let current_time = ic_cdk::api::time();
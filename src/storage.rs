@@ -11,6 +11,9 @@ type ConversationMap = StableBTreeMap<String, Conversation, VMem>;
 type UserConversationMap = StableBTreeMap<Principal, UserConversationList, VMem>;
 type UserConfigMap = StableBTreeMap<Principal, UserConfig, VMem>;
 type AccessTokenMap = StableBTreeMap<String, AccessToken, VMem>;
+type MemoryRevisionMap = StableBTreeMap<String, MemoryRevisionList, VMem>;
+type ApiKeyMap = StableBTreeMap<String, ApiKey, VMem>;
+type ListingDigestMap = StableBTreeMap<Principal, ListingDigest, VMem>;
 
 const MEMORY_ID_MEMORIES: MemoryId = MemoryId::new(0);
 const MEMORY_ID_USER_MEMORIES: MemoryId = MemoryId::new(1);  
@@ -18,6 +21,44 @@ const MEMORY_ID_CONVERSATIONS: MemoryId = MemoryId::new(2);
 const MEMORY_ID_USER_CONVERSATIONS: MemoryId = MemoryId::new(3);
 const MEMORY_ID_USER_CONFIG: MemoryId = MemoryId::new(4);
 const MEMORY_ID_ACCESS_TOKENS: MemoryId = MemoryId::new(5);
+pub(crate) const MEMORY_ID_HNSW_NODES: MemoryId = MemoryId::new(6);
+pub(crate) const MEMORY_ID_HNSW_ENTRY_POINTS: MemoryId = MemoryId::new(7);
+pub(crate) const MEMORY_ID_USER_VECTORS: MemoryId = MemoryId::new(8);
+pub(crate) const MEMORY_ID_VECTORS: MemoryId = MemoryId::new(9);
+pub(crate) const MEMORY_ID_TEXT_POSTINGS: MemoryId = MemoryId::new(10);
+pub(crate) const MEMORY_ID_TEXT_DOCUMENTS: MemoryId = MemoryId::new(11);
+pub(crate) const MEMORY_ID_TEXT_CORPUS: MemoryId = MemoryId::new(12);
+const MEMORY_ID_MEMORY_REVISIONS: MemoryId = MemoryId::new(13);
+const MEMORY_ID_API_KEYS: MemoryId = MemoryId::new(14);
+pub(crate) const MEMORY_ID_II_SESSIONS: MemoryId = MemoryId::new(15);
+pub(crate) const MEMORY_ID_QUERY_EMBEDDINGS: MemoryId = MemoryId::new(16);
+pub(crate) const MEMORY_ID_QUERY_EMBEDDING_RECENCY: MemoryId = MemoryId::new(17);
+pub(crate) const MEMORY_ID_REEMBED_JOBS: MemoryId = MemoryId::new(18);
+pub(crate) const MEMORY_ID_CLUSTERS: MemoryId = MemoryId::new(19);
+pub(crate) const MEMORY_ID_USER_CLUSTERS: MemoryId = MemoryId::new(20);
+pub(crate) const MEMORY_ID_CATEGORIES: MemoryId = MemoryId::new(21);
+pub(crate) const MEMORY_ID_CATEGORY_ASSIGNMENTS: MemoryId = MemoryId::new(22);
+const MEMORY_ID_LISTING_DIGESTS: MemoryId = MemoryId::new(23);
+pub(crate) const MEMORY_ID_BACKFILL_JOBS: MemoryId = MemoryId::new(24);
+pub(crate) const MEMORY_ID_HNSW_PARTITION_ENTRY_POINTS: MemoryId = MemoryId::new(25);
+pub(crate) const MEMORY_ID_II_USED_LOGINS: MemoryId = MemoryId::new(26);
+pub(crate) const MEMORY_ID_BACKFILL_FINISHED: MemoryId = MemoryId::new(27);
+pub(crate) const MEMORY_ID_OWNER_VECTOR_IDS: MemoryId = MemoryId::new(28);
+pub(crate) const MEMORY_ID_OWNER_VECTOR_COUNTS: MemoryId = MemoryId::new(29);
+pub(crate) const MEMORY_ID_PARTITION_NODE_COUNTS: MemoryId = MemoryId::new(30);
+pub(crate) const MEMORY_ID_TEXT_TERM_POSTINGS: MemoryId = MemoryId::new(31);
+pub(crate) const MEMORY_ID_CATEGORY_ENTRIES: MemoryId = MemoryId::new(32);
+pub(crate) const MEMORY_ID_TAXONOMY_OWNERS: MemoryId = MemoryId::new(33);
+
+/// Maximum number of past versions kept per memory
+pub const MAX_REVISIONS_PER_MEMORY: usize = 10;
+
+/// Every API key starts with this marker
+pub const API_KEY_PREFIX: &str = "om_key_";
+// Characters of the random part kept in the listing prefix
+const API_KEY_PREFIX_RANDOM_CHARS: usize = 12;
+// Leading hex characters of a token's hash that identify it in listings
+const ACCESS_TOKEN_ID_CHARS: usize = 16;
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
@@ -27,7 +68,16 @@ thread_local! {
     static USER_CONVERSATIONS: RefCell<Option<UserConversationMap>> = RefCell::new(None);
     static USER_CONFIG: RefCell<Option<UserConfigMap>> = RefCell::new(None);
     static ACCESS_TOKENS: RefCell<Option<AccessTokenMap>> = RefCell::new(None);
-    static STORAGE_INITIALIZED: RefCell<bool> = RefCell::new(false);
+    static MEMORY_REVISIONS: RefCell<Option<MemoryRevisionMap>> = const { RefCell::new(None) };
+    static API_KEYS: RefCell<Option<ApiKeyMap>> = const { RefCell::new(None) };
+    static LISTING_DIGESTS: RefCell<Option<ListingDigestMap>> = const { RefCell::new(None) };
+    static STORAGE_INITIALIZED: RefCell<bool> = const { RefCell::new(false) };
+}
+
+// Hand out a virtual memory from the shared manager so other modules never
+// allocate stable memory outside of it
+pub(crate) fn virtual_memory(id: MemoryId) -> VMem {
+    MEMORY_MANAGER.with(|mm| mm.borrow().get(id))
 }
 
 pub async fn init_storage() {
@@ -57,12 +107,26 @@ pub async fn init_storage() {
         ACCESS_TOKENS.with(|tokens| {
             *tokens.borrow_mut() = Some(StableBTreeMap::init(memory_manager.get(MEMORY_ID_ACCESS_TOKENS)));
         });
+        
+        MEMORY_REVISIONS.with(|revisions| {
+            *revisions.borrow_mut() = Some(StableBTreeMap::init(memory_manager.get(MEMORY_ID_MEMORY_REVISIONS)));
+        });
+        
+        API_KEYS.with(|keys| {
+            *keys.borrow_mut() = Some(StableBTreeMap::init(memory_manager.get(MEMORY_ID_API_KEYS)));
+        });
+        
+        LISTING_DIGESTS.with(|digests| {
+            *digests.borrow_mut() = Some(StableBTreeMap::init(memory_manager.get(MEMORY_ID_LISTING_DIGESTS)));
+        });
     });
     
     STORAGE_INITIALIZED.with(|init| {
         *init.borrow_mut() = true;
     });
     
+    hash_plaintext_access_tokens();
+    
     ic_cdk::println!("Storage initialized successfully");
 }
 
@@ -86,10 +150,9 @@ pub async fn store_memory(memory: crate::types::Memory) -> Result<(), String> {
     let user_id = memory.user_id;
     
     // Store the memory
-    MEMORIES.with(|m| {
+    let previous = MEMORIES.with(|m| {
         if let Some(ref mut memories) = *m.borrow_mut() {
-            memories.insert(memory_id.clone(), memory.clone());
-            Ok(())
+            Ok(memories.insert(memory_id.clone(), memory.clone()))
         } else {
             Err("Memory storage not available".to_string())
         }
@@ -98,8 +161,10 @@ pub async fn store_memory(memory: crate::types::Memory) -> Result<(), String> {
     // Add to vector store if embedding exists
     if !memory.embedding.is_empty() {
         if let Err(e) = crate::vector_store::AdvancedVectorStore::add_vector(
+            user_id,
             memory_id.clone(), 
-            memory.embedding.clone()
+            memory.embedding.clone(),
+            memory.embedding_model.as_deref()
         ) {
             ic_cdk::println!("Failed to add vector to store: {}", e);
             // Continue anyway - memory is still stored even if vector indexing fails
@@ -107,22 +172,44 @@ pub async fn store_memory(memory: crate::types::Memory) -> Result<(), String> {
     }
     
     // Update user's memory index
-    USER_MEMORIES.with(|um| {
+    let is_new = USER_MEMORIES.with(|um| {
         if let Some(ref mut user_memories) = *um.borrow_mut() {
             let mut user_memory_list = user_memories.get(&user_id).unwrap_or_default();
-            if !user_memory_list.0.contains(&memory_id) {
-                user_memory_list.0.push(memory_id.clone());
-                user_memories.insert(user_id, user_memory_list);
+            if user_memory_list.0.contains(&memory_id) {
+                return Ok(false);
             }
-            Ok(())
+            user_memory_list.0.push(memory_id.clone());
+            user_memories.insert(user_id, user_memory_list);
+            Ok(true)
         } else {
             Err("User memory index not available".to_string())
         }
     })?;
     
+    // New memories join the nearest existing k-means cluster and are
+    // categorized; edits that change what a memory says are categorized again
+    if is_new {
+        crate::clustering::ClusteringEngine::assign_new_memory(&memory);
+        crate::categories::classify_new_memory(&memory);
+    } else if previous.as_ref().is_some_and(|previous| {
+        previous.content != memory.content
+            || previous.tags != memory.tags
+            || previous.embedding != memory.embedding
+            || previous.embedding_model != memory.embedding_model
+    }) {
+        crate::categories::reclassify_memory(&memory);
+    }
+    
+    // Index content and tags for keyword (BM25) search
+    crate::text_index::TextIndex::index_memory(&memory_id, user_id, &memory.content, &memory.tags);
+    
     // Index memory content for suggestions
     crate::suggestions::SuggestionsEngine::index_memory_content(&memory);
     
+    // Keep the certified /health and listing digest responses current
+    update_listing_digest(user_id, &memory_id, previous.as_ref().map(|p| p.updated_at), Some(memory.updated_at));
+    crate::certification::certify_user(user_id);
+    
     ic_cdk::println!("Memory stored successfully: {}", memory_id);
     Ok(())
 }
@@ -135,31 +222,42 @@ pub fn store_memory_sync(memory: crate::types::Memory) -> Result<(), String> {
     let memory_id = memory.id.clone();
     
     // Store in main memory map
-    MEMORIES.with(|m| {
+    let previous = MEMORIES.with(|m| {
         if let Some(ref mut memories) = *m.borrow_mut() {
-            memories.insert(memory.id.clone(), memory.clone());
+            Ok(memories.insert(memory.id.clone(), memory.clone()))
         } else {
-            return Err("Memory storage not available".to_string());
+            Err("Memory storage not available".to_string())
         }
-        Ok(())
     })?;
     
     // Store in user memory map
-    USER_MEMORIES.with(|um| {
+    let is_new = USER_MEMORIES.with(|um| {
         if let Some(ref mut user_memories) = *um.borrow_mut() {
             // Get or create user memory list
             let mut user_memory_list = user_memories.get(&memory.user_id).unwrap_or_default();
+            if user_memory_list.0.contains(&memory.id) {
+                return Ok(false);
+            }
             user_memory_list.0.push(memory.id.clone());
             user_memories.insert(memory.user_id, user_memory_list);
+            Ok(true)
         } else {
-            return Err("User memory storage not available".to_string());
+            Err("User memory storage not available".to_string())
         }
-        Ok(())
     })?;
     
+    // Index content and tags for keyword (BM25) search
+    crate::text_index::TextIndex::index_memory(&memory.id, memory.user_id, &memory.content, &memory.tags);
+    
     // Update suggestions engine
     crate::suggestions::SuggestionsEngine::index_memory_content(&memory);
     
+    if is_new {
+        crate::categories::classify_new_memory(&memory);
+    }
+    update_listing_digest(memory.user_id, &memory.id, previous.map(|p| p.updated_at), Some(memory.updated_at));
+    crate::certification::certify_user(memory.user_id);
+    
     ic_cdk::println!("Memory stored synchronously: {}", memory_id);
     Ok(())
 }
@@ -207,6 +305,15 @@ pub async fn delete_memory(id: &str, user_id: Principal) -> Result<bool, String>
         if let Err(e) = crate::vector_store::AdvancedVectorStore::remove_vector(id) {
             ic_cdk::println!("Failed to remove vector from store: {}", e);
         }
+        crate::text_index::TextIndex::remove_memory(id);
+        crate::clustering::ClusteringEngine::remove_memory(user_id, id);
+        crate::categories::remove_memory(&memory);
+        
+        MEMORY_REVISIONS.with(|revisions| {
+            if let Some(ref mut revisions) = *revisions.borrow_mut() {
+                revisions.remove(&id.to_string());
+            }
+        });
         
         // Remove from user's memory index
         USER_MEMORIES.with(|um| {
@@ -218,12 +325,113 @@ pub async fn delete_memory(id: &str, user_id: Principal) -> Result<bool, String>
             }
         });
         
+        update_listing_digest(user_id, id, Some(memory.updated_at), None);
+        crate::certification::certify_user(user_id);
+        
         ic_cdk::println!("Memory deleted successfully: {}", id);
     }
     
     Ok(removed)
 }
 
+// Replace a memory with an edited version, keeping the previous version in
+// its revision history. The caller decides whether the embedding changes;
+// store_memory refreshes the vector and text indexes either way.
+pub async fn update_memory(updated: crate::types::Memory) -> Result<(), String> {
+    let current = get_memory(&updated.id)?
+        .ok_or_else(|| format!("Memory not found: {}", updated.id))?;
+    
+    if current.user_id != updated.user_id {
+        return Err("Permission denied".to_string());
+    }
+    
+    push_revision(&current);
+    store_memory(updated).await
+}
+
+pub fn list_memory_revisions(id: &str) -> Vec<MemoryRevision> {
+    if !is_storage_initialized() {
+        return Vec::new();
+    }
+    
+    MEMORY_REVISIONS.with(|revisions| {
+        revisions
+            .borrow()
+            .as_ref()
+            .and_then(|revisions| revisions.get(&id.to_string()))
+            .map(|list| list.0)
+            .unwrap_or_default()
+    })
+}
+
+// Whether an edit changes the text the memory's embedding was generated from;
+// edits to tags or metadata keep the existing embedding
+pub fn edit_needs_embedding(current: &crate::types::Memory, content: &str) -> bool {
+    current.content != content
+}
+
+// Bring back a previous version. The version being replaced becomes a new
+// revision, so a restore can itself be undone. Another user's memory is
+// reported as not found.
+pub async fn restore_memory_revision(
+    id: &str,
+    revision: u32,
+    user_id: Principal,
+) -> crate::errors::Result<crate::types::Memory> {
+    let current = get_memory(id)
+        .map_err(|e| crate::errors::OpenMemoryError::storage(e, "restore_memory_revision"))?
+        .filter(|memory| memory.user_id == user_id)
+        .ok_or_else(|| crate::errors::OpenMemoryError::not_found("memory", id))?;
+    
+    let restored = restored_version(&current, revision, ic_cdk::api::time())?;
+    push_revision(&current);
+    store_memory(restored.clone())
+        .await
+        .map_err(|e| crate::errors::OpenMemoryError::storage(e, "restore_memory_revision"))?;
+    Ok(restored)
+}
+
+// `current` with the content of one of its revisions, updated at `now`
+fn restored_version(current: &crate::types::Memory, revision: u32, now: u64) -> crate::errors::Result<crate::types::Memory> {
+    let target = list_memory_revisions(&current.id)
+        .into_iter()
+        .find(|r| r.revision == revision)
+        .ok_or_else(|| crate::errors::OpenMemoryError::not_found("revision", revision.to_string()))?;
+    
+    Ok(crate::types::Memory {
+        content: target.content,
+        embedding: target.embedding,
+        metadata: target.metadata,
+        tags: target.tags,
+        updated_at: now,
+        embedding_model: target.embedding_model,
+        ..current.clone()
+    })
+}
+
+fn push_revision(memory: &crate::types::Memory) {
+    MEMORY_REVISIONS.with(|revisions| {
+        if let Some(ref mut revisions) = *revisions.borrow_mut() {
+            let mut list = revisions.get(&memory.id).unwrap_or_default();
+            let next = list.0.last().map(|r| r.revision + 1).unwrap_or(1);
+            list.0.push(MemoryRevision {
+                revision: next,
+                content: memory.content.clone(),
+                embedding: memory.embedding.clone(),
+                metadata: memory.metadata.clone(),
+                tags: memory.tags.clone(),
+                updated_at: memory.updated_at,
+                embedding_model: memory.embedding_model.clone(),
+            });
+            
+            // Drop the oldest versions beyond the bound
+            let excess = list.0.len().saturating_sub(MAX_REVISIONS_PER_MEMORY);
+            list.0.drain(..excess);
+            revisions.insert(memory.id.clone(), list);
+        }
+    });
+}
+
 pub fn list_memories(offset: usize, limit: usize, user_filter: Option<Principal>) -> Result<Vec<crate::types::Memory>, String> {
     if !is_storage_initialized() {
         return Err("Storage not initialized".to_string());
@@ -268,6 +476,20 @@ pub fn list_memories(offset: usize, limit: usize, user_filter: Option<Principal>
     Ok(memories)
 }
 
+pub fn get_user_memory_count(user_id: Principal) -> usize {
+    if !is_storage_initialized() {
+        return 0;
+    }
+    
+    USER_MEMORIES.with(|um| {
+        um.borrow()
+            .as_ref()
+            .and_then(|user_memories| user_memories.get(&user_id))
+            .map(|list| list.0.len())
+            .unwrap_or(0)
+    })
+}
+
 pub fn list_user_memories(user_id: Principal, offset: usize, limit: usize) -> Result<Vec<crate::types::Memory>, String> {
     if !is_storage_initialized() {
         return Err("Storage not initialized".to_string());
@@ -300,6 +522,211 @@ pub fn list_user_memories(user_id: Principal, offset: usize, limit: usize) -> Re
     })
 }
 
+// Ids of a user's memories in insertion order
+pub fn user_memory_ids(user_id: Principal) -> Vec<String> {
+    USER_MEMORIES.with(|um| {
+        um.borrow()
+            .as_ref()
+            .and_then(|user_memories| user_memories.get(&user_id))
+            .map(|list| list.0)
+            .unwrap_or_default()
+    })
+}
+
+// Embeddings stored before models were tracked are attributed to the owner's
+// current model when the dimension fits; anything else (e.g. placeholder
+// vectors) stays unlabeled until it is re-embedded. One step of the
+// `LEGACY_MODEL_JOB` backfill: the next `limit` memories after `cursor`.
+pub fn label_legacy_batch(cursor: Option<&str>, limit: usize) -> (usize, Option<String>) {
+    let batch = memories_after(cursor, limit);
+    
+    let mut models: std::collections::HashMap<Principal, Option<String>> = std::collections::HashMap::new();
+    for memory in &batch {
+        if memory.embedding_model.is_some() || memory.embedding.is_empty() {
+            continue;
+        }
+        let model = models
+            .entry(memory.user_id)
+            .or_insert_with(|| crate::embedding::embedding_model_for_user(memory.user_id))
+            .clone();
+        let Some(model) = model else { continue };
+        if crate::embedding::model_dimension(&model).is_some_and(|dimension| dimension != memory.embedding.len()) {
+            continue;
+        }
+        
+        crate::vector_store::AdvancedVectorStore::set_vector_model(&memory.id, &model);
+        let labeled = crate::types::Memory {
+            embedding_model: Some(model),
+            ..memory.clone()
+        };
+        MEMORIES.with(|m| {
+            if let Some(ref mut memories) = *m.borrow_mut() {
+                memories.insert(labeled.id.clone(), labeled);
+            }
+        });
+    }
+    
+    let next = if batch.len() < limit { None } else { batch.last().map(|memory| memory.id.clone()) };
+    (batch.len(), next)
+}
+
+// Principals that currently own at least one memory
+pub fn list_memory_owners() -> Vec<Principal> {
+    if !is_storage_initialized() {
+        return Vec::new();
+    }
+
+    USER_MEMORIES.with(|um| {
+        um.borrow()
+            .as_ref()
+            .map(|user_memories| {
+                user_memories
+                    .iter()
+                    .filter(|(_, list)| !list.0.is_empty())
+                    .map(|(user, _)| user)
+                    .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+// Listing digest for a user, computed from their memories the first time it
+// is needed (e.g. memories stored before digests were tracked) and kept up to
+// date by store_memory and delete_memory afterwards. The digest changes
+// whenever a memory is added, edited or removed, without revealing any content.
+pub fn user_listing_digest(user_id: Principal) -> ListingDigest {
+    let stored = LISTING_DIGESTS.with(|digests| digests.borrow().as_ref().and_then(|digests| digests.get(&user_id)));
+    match stored {
+        Some(listing) if listing.path_id.is_some() => listing,
+        Some(listing) => save_listing_digest(user_id, listing),
+        None => {
+            let mut accumulator = [0u8; 32];
+            for (id, updated_at) in user_listing_entries(user_id) {
+                add_entry(&mut accumulator, &entry_hash(&id, updated_at));
+            }
+            save_listing_digest(user_id, ListingDigest { accumulator: accumulator.to_vec(), ..Default::default() })
+        }
+    }
+}
+
+// Every stored listing digest, for re-certification after an upgrade
+pub fn list_listing_digests() -> Vec<ListingDigest> {
+    LISTING_DIGESTS.with(|digests| {
+        digests
+            .borrow()
+            .as_ref()
+            .map(|digests| digests.iter().map(|(_, listing)| listing).collect())
+            .unwrap_or_default()
+    })
+}
+
+// The listing digest published under a certified path id
+pub fn find_listing_digest(path_id: &str) -> Option<ListingDigest> {
+    LISTING_DIGESTS.with(|digests| {
+        digests
+            .borrow()
+            .as_ref()
+            .and_then(|digests| digests.iter().map(|(_, listing)| listing).find(|listing| listing.path_id.as_deref() == Some(path_id)))
+    })
+}
+
+// Swap one (id, updated_at) entry of a user's listing digest for another.
+// A user without a stored digest gets one computed from the current state,
+// which already includes the change.
+fn update_listing_digest(user_id: Principal, id: &str, removed: Option<u64>, added: Option<u64>) {
+    let stored = LISTING_DIGESTS.with(|digests| digests.borrow().as_ref().and_then(|digests| digests.get(&user_id)));
+    let Some(mut listing) = stored else {
+        user_listing_digest(user_id);
+        return;
+    };
+
+    let mut accumulator: [u8; 32] = listing.accumulator.as_slice().try_into().unwrap_or([0u8; 32]);
+    if let Some(updated_at) = removed {
+        sub_entry(&mut accumulator, &entry_hash(id, updated_at));
+    }
+    if let Some(updated_at) = added {
+        add_entry(&mut accumulator, &entry_hash(id, updated_at));
+    }
+    listing.accumulator = accumulator.to_vec();
+    save_listing_digest(user_id, listing);
+}
+
+// Refresh the published digest, hand out a path id if the user has none yet
+// (the RNG may not have been seeded the first time) and persist the entry
+fn save_listing_digest(user_id: Principal, mut listing: ListingDigest) -> ListingDigest {
+    listing.digest = listing_digest(&listing.accumulator);
+    if listing.path_id.is_none() {
+        listing.path_id = crate::rng::random_hex(16).ok();
+    }
+    LISTING_DIGESTS.with(|digests| {
+        if let Some(ref mut digests) = *digests.borrow_mut() {
+            digests.insert(user_id, listing.clone());
+        }
+    });
+    listing
+}
+
+fn user_listing_entries(user_id: Principal) -> Vec<(String, u64)> {
+    USER_MEMORIES.with(|um| {
+        let ids = um
+            .borrow()
+            .as_ref()
+            .and_then(|user_memories| user_memories.get(&user_id))
+            .map(|list| list.0)
+            .unwrap_or_default();
+
+        MEMORIES.with(|m| {
+            m.borrow()
+                .as_ref()
+                .map(|memories| {
+                    ids.into_iter()
+                        .filter_map(|id| memories.get(&id).map(|memory| (id, memory.updated_at)))
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    })
+}
+
+fn entry_hash(id: &str, updated_at: u64) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update((id.len() as u64).to_be_bytes());
+    hasher.update(id.as_bytes());
+    hasher.update(updated_at.to_be_bytes());
+    hasher.finalize().into()
+}
+
+// Big-endian addition and subtraction mod 2^256. Being commutative and
+// inverse to each other, the accumulator does not depend on the order in
+// which entries were added or removed.
+fn add_entry(accumulator: &mut [u8; 32], hash: &[u8; 32]) {
+    let mut carry = 0u16;
+    for i in (0..32).rev() {
+        let sum = accumulator[i] as u16 + hash[i] as u16 + carry;
+        accumulator[i] = sum as u8;
+        carry = sum >> 8;
+    }
+}
+
+fn sub_entry(accumulator: &mut [u8; 32], hash: &[u8; 32]) {
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let mut diff = accumulator[i] as i16 - hash[i] as i16 - borrow;
+        borrow = if diff < 0 { 1 } else { 0 };
+        if diff < 0 {
+            diff += 256;
+        }
+        accumulator[i] = diff as u8;
+    }
+}
+
+// The published digest is a hash of the accumulator rather than the raw sum
+fn listing_digest(accumulator: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(accumulator))
+}
+
 // Simple text search (without embeddings for now)
 pub fn search_memories_simple(
     query: &str, 
@@ -393,6 +820,32 @@ pub fn get_memory_count() -> usize {
     })
 }
 
+// Owner, id, embedding and model of every stored memory that has an
+// embedding; used to rebuild the vector store
+// The next `limit` memories after `after` in id order, for background jobs
+// that walk every memory a batch at a time
+pub fn memories_after(after: Option<&str>, limit: usize) -> Vec<crate::types::Memory> {
+    if !is_storage_initialized() {
+        return Vec::new();
+    }
+    
+    MEMORIES.with(|m| {
+        if let Some(ref memories) = *m.borrow() {
+            let start = match after {
+                Some(after) => std::ops::Bound::Excluded(after.to_string()),
+                None => std::ops::Bound::Unbounded,
+            };
+            memories
+                .range((start, std::ops::Bound::Unbounded))
+                .take(limit)
+                .map(|(_, memory)| memory)
+                .collect()
+        } else {
+            Vec::new()
+        }
+    })
+}
+
 pub fn get_user_count() -> usize {
     if !is_storage_initialized() {
         return 0;
@@ -559,6 +1012,9 @@ pub fn save_user_config(user_id: Principal, openai_api_key: String) -> Result<()
         embedding_model: "text-embedding-ada-002".to_string(),
         created_at: timestamp,
         updated_at: timestamp,
+        api_base_url: None,
+        compatible_api_key: None,
+        embedding_dimension: None,
     });
     
     config.openai_api_key = Some(openai_api_key);
@@ -624,13 +1080,12 @@ pub fn delete_user_openai_key(user_id: Principal) -> Result<bool, String> {
     Ok(result)
 }
 
-// New comprehensive config update function
+// New comprehensive config update function. `provider` is the request's
+// `api_provider`, already parsed by the caller.
 pub fn update_user_config(
-    user_id: Principal, 
-    openai_key: Option<String>,
-    openrouter_key: Option<String>,
+    user_id: Principal,
     provider: Option<crate::types::ApiProvider>,
-    model: Option<String>
+    request: crate::types::UpdateConfigRequest,
 ) -> Result<(), String> {
     if !is_storage_initialized() {
         return Err("Storage not initialized".to_string());
@@ -647,21 +1102,33 @@ pub fn update_user_config(
         embedding_model: "text-embedding-ada-002".to_string(),
         created_at: timestamp,
         updated_at: timestamp,
+        api_base_url: None,
+        compatible_api_key: None,
+        embedding_dimension: None,
     });
     
     // Update fields if provided
-    if let Some(key) = openai_key {
+    if let Some(key) = request.openai_api_key {
         config.openai_api_key = Some(key);
     }
-    if let Some(key) = openrouter_key {
+    if let Some(key) = request.openrouter_api_key {
         config.openrouter_api_key = Some(key);
     }
     if let Some(p) = provider {
         config.api_provider = p;
     }
-    if let Some(m) = model {
+    if let Some(m) = request.embedding_model {
         config.embedding_model = m;
     }
+    if let Some(url) = request.api_base_url {
+        config.api_base_url = Some(url);
+    }
+    if let Some(key) = request.compatible_api_key {
+        config.compatible_api_key = Some(key);
+    }
+    if let Some(dimension) = request.embedding_dimension {
+        config.embedding_dimension = Some(dimension);
+    }
     
     config.updated_at = timestamp;
     
@@ -745,12 +1212,26 @@ pub fn get_available_models(provider: &crate::types::ApiProvider) -> Vec<crate::
                 }),
             },
         ],
+        // Self-hosted servers expose whatever models they have loaded
+        crate::types::ApiProvider::OpenAICompatible => Vec::new(),
+        crate::types::ApiProvider::Local => vec![
+            crate::types::ModelInfo {
+                id: crate::embedding::LOCAL_HASH_MODEL.to_string(),
+                name: "Local hashing embedder".to_string(),
+                provider: "Local".to_string(),
+                context_length: crate::validation::MAX_MEMORY_CONTENT_SIZE as u32,
+                pricing: None,
+            },
+        ],
     }
 }
 
 // Access Token Management Functions
+// Store a newly generated token. Only its hash is kept, so the returned
+// record no longer contains a usable credential.
 pub fn create_access_token(
     owner_principal: Principal,
+    token: &str,
     description: Option<String>,
     permissions: Vec<Permission>,
     expires_in_days: u32,
@@ -761,12 +1242,10 @@ pub fn create_access_token(
     
     let timestamp = ic_cdk::api::time();
     let expires_at = timestamp + (expires_in_days as u64 * 24 * 60 * 60 * 1_000_000_000);
-    
-    // Generate secure token
-    let token = format!("om_token_{}", crate::utils::generate_uuid());
+    let token_hash = hash_credential(token);
     
     let access_token = AccessToken {
-        token: token.clone(),
+        token: token_hash.clone(),
         owner_principal,
         issued_to: description,
         permissions,
@@ -777,7 +1256,7 @@ pub fn create_access_token(
     
     ACCESS_TOKENS.with(|tokens| {
         if let Some(ref mut token_map) = *tokens.borrow_mut() {
-            token_map.insert(token.clone(), access_token.clone());
+            token_map.insert(token_hash, access_token.clone());
             Ok(access_token)
         } else {
             Err("Access token storage not available".to_string())
@@ -785,28 +1264,30 @@ pub fn create_access_token(
     })
 }
 
-pub fn verify_access_token(token: &str) -> Result<Principal, String> {
+pub fn verify_access_token(token: &str) -> Result<AccessToken, String> {
     if !is_storage_initialized() {
         return Err("Storage not initialized".to_string());
     }
     
+    let token_hash = hash_credential(token);
+    
     ACCESS_TOKENS.with(|tokens| {
         if let Some(ref mut token_map) = *tokens.borrow_mut() {
-            if let Some(mut access_token) = token_map.get(token) {
+            if let Some(mut access_token) = token_map.get(&token_hash) {
                 let current_time = ic_cdk::api::time();
                 
                 // Check if token is expired
                 if current_time > access_token.expires_at {
                     // Remove expired token
-                    token_map.remove(token);
+                    token_map.remove(&token_hash);
                     return Err("Token expired".to_string());
                 }
                 
                 // Update last used time
                 access_token.last_used_at = Some(current_time);
-                token_map.insert(token.to_string(), access_token.clone());
+                token_map.insert(token_hash, access_token.clone());
                 
-                Ok(access_token.owner_principal)
+                Ok(access_token)
             } else {
                 Err("Invalid token".to_string())
             }
@@ -828,6 +1309,7 @@ pub fn get_user_tokens(user_principal: Principal) -> Result<Vec<TokenInfo>, Stri
             for (_, token) in token_map.iter() {
                 if token.owner_principal == user_principal {
                     user_tokens.push(TokenInfo {
+                        id: access_token_id(&token.token),
                         description: token.issued_to.clone(),
                         permissions: token.permissions.clone(),
                         expires_at: token.expires_at,
@@ -844,30 +1326,57 @@ pub fn get_user_tokens(user_principal: Principal) -> Result<Vec<TokenInfo>, Stri
     })
 }
 
-pub fn revoke_access_token(token: &str, user_principal: Principal) -> Result<bool, String> {
+pub fn revoke_access_token(token_id: &str, user_principal: Principal) -> Result<bool, String> {
     if !is_storage_initialized() {
         return Err("Storage not initialized".to_string());
     }
     
     ACCESS_TOKENS.with(|tokens| {
         if let Some(ref mut token_map) = *tokens.borrow_mut() {
-            if let Some(access_token) = token_map.get(token) {
-                // Check if user owns this token
-                if access_token.owner_principal == user_principal {
-                    token_map.remove(token);
-                    Ok(true)
-                } else {
-                    Err("Unauthorized to revoke this token".to_string())
-                }
-            } else {
-                Ok(false) // Token doesn't exist
+            let matching: Vec<String> = token_map
+                .iter()
+                .filter(|(token_hash, token)| {
+                    token.owner_principal == user_principal && access_token_id(token_hash) == token_id
+                })
+                .map(|(token_hash, _)| token_hash)
+                .collect();
+            
+            for token_hash in &matching {
+                token_map.remove(token_hash);
             }
+            Ok(!matching.is_empty())
         } else {
             Err("Access token storage not available".to_string())
         }
     })
 }
 
+// Tokens issued before hashing was introduced are keyed by their plaintext;
+// re-key them by hash so stable memory holds no usable credentials
+fn hash_plaintext_access_tokens() {
+    ACCESS_TOKENS.with(|tokens| {
+        if let Some(ref mut token_map) = *tokens.borrow_mut() {
+            let plaintext: Vec<String> = token_map
+                .iter()
+                .map(|(token, _)| token)
+                .filter(|token| token.starts_with("om_token_"))
+                .collect();
+            
+            for token in &plaintext {
+                if let Some(mut access_token) = token_map.remove(token) {
+                    let token_hash = hash_credential(token);
+                    access_token.token = token_hash.clone();
+                    token_map.insert(token_hash, access_token);
+                }
+            }
+            
+            if !plaintext.is_empty() {
+                ic_cdk::println!("Re-keyed {} access tokens by hash", plaintext.len());
+            }
+        }
+    });
+}
+
 pub fn cleanup_expired_tokens() -> Result<usize, String> {
     if !is_storage_initialized() {
         return Err("Storage not initialized".to_string());
@@ -896,10 +1405,247 @@ pub fn cleanup_expired_tokens() -> Result<usize, String> {
     })
 }
 
+// API Key Management Functions
+// SHA-256 of a bearer credential; access tokens and API keys are stored under this
+pub fn hash_credential(credential: &str) -> String {
+    use sha2::{Digest, Sha256};
+    hex::encode(Sha256::digest(credential.as_bytes()))
+}
+
+// Stable, non-secret id of an access token, derived from its stored hash
+pub fn access_token_id(token_hash: &str) -> String {
+    token_hash.chars().take(ACCESS_TOKEN_ID_CHARS).collect()
+}
+
+pub fn api_key_prefix(api_key: &str) -> String {
+    api_key.chars().take(API_KEY_PREFIX.len() + API_KEY_PREFIX_RANDOM_CHARS).collect()
+}
+
+pub fn store_api_key(
+    owner_principal: Principal,
+    api_key: &str,
+    name: Option<String>,
+    permissions: Vec<Permission>,
+    expires_in_days: Option<u32>,
+) -> Result<ApiKey, String> {
+    if !is_storage_initialized() {
+        return Err("Storage not initialized".to_string());
+    }
+    
+    let timestamp = ic_cdk::api::time();
+    let record = ApiKey {
+        key_hash: hash_credential(api_key),
+        prefix: api_key_prefix(api_key),
+        owner_principal,
+        name,
+        permissions,
+        expires_at: expires_in_days.map(|days| timestamp + (days as u64 * 24 * 60 * 60 * 1_000_000_000)),
+        created_at: timestamp,
+        last_used_at: None,
+    };
+    
+    API_KEYS.with(|keys| {
+        if let Some(ref mut key_map) = *keys.borrow_mut() {
+            key_map.insert(record.key_hash.clone(), record.clone());
+            Ok(record)
+        } else {
+            Err("API key storage not available".to_string())
+        }
+    })
+}
+
+// Look up a presented key by its hash, dropping it if it has expired
+pub fn lookup_api_key(api_key: &str) -> Result<ApiKey, String> {
+    if !is_storage_initialized() {
+        return Err("Storage not initialized".to_string());
+    }
+    
+    let key_hash = hash_credential(api_key);
+    
+    API_KEYS.with(|keys| {
+        if let Some(ref mut key_map) = *keys.borrow_mut() {
+            let mut record = key_map.get(&key_hash).ok_or_else(|| "Invalid API key".to_string())?;
+            let current_time = ic_cdk::api::time();
+            
+            if record.expires_at.is_some_and(|expires_at| current_time > expires_at) {
+                key_map.remove(&key_hash);
+                return Err("API key expired".to_string());
+            }
+            
+            record.last_used_at = Some(current_time);
+            key_map.insert(key_hash, record.clone());
+            Ok(record)
+        } else {
+            Err("API key storage not available".to_string())
+        }
+    })
+}
+
+pub fn get_user_api_keys(user_principal: Principal) -> Result<Vec<ApiKeyInfo>, String> {
+    if !is_storage_initialized() {
+        return Err("Storage not initialized".to_string());
+    }
+    
+    API_KEYS.with(|keys| {
+        if let Some(ref key_map) = *keys.borrow() {
+            let mut user_keys: Vec<ApiKeyInfo> = key_map
+                .iter()
+                .filter(|(_, key)| key.owner_principal == user_principal)
+                .map(|(_, key)| ApiKeyInfo {
+                    prefix: key.prefix,
+                    name: key.name,
+                    permissions: key.permissions,
+                    expires_at: key.expires_at,
+                    created_at: key.created_at,
+                    last_used_at: key.last_used_at,
+                })
+                .collect();
+            user_keys.sort_by_key(|key| key.created_at);
+            Ok(user_keys)
+        } else {
+            Err("API key storage not available".to_string())
+        }
+    })
+}
+
+// Revoke the caller's key with the given listing prefix
+pub fn revoke_api_key(prefix: &str, user_principal: Principal) -> Result<bool, String> {
+    if !is_storage_initialized() {
+        return Err("Storage not initialized".to_string());
+    }
+    
+    API_KEYS.with(|keys| {
+        if let Some(ref mut key_map) = *keys.borrow_mut() {
+            let matching: Vec<String> = key_map
+                .iter()
+                .filter(|(_, key)| key.owner_principal == user_principal && key.prefix == prefix)
+                .map(|(key_hash, _)| key_hash)
+                .collect();
+            
+            for key_hash in &matching {
+                key_map.remove(key_hash);
+            }
+            Ok(!matching.is_empty())
+        } else {
+            Err("API key storage not available".to_string())
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn open_revisions() {
+        STORAGE_INITIALIZED.with(|init| *init.borrow_mut() = true);
+        MEMORY_REVISIONS.with(|revisions| {
+            *revisions.borrow_mut() = Some(StableBTreeMap::init(virtual_memory(MEMORY_ID_MEMORY_REVISIONS)));
+        });
+    }
+
+    fn memory(content: &str, updated_at: u64) -> crate::types::Memory {
+        crate::types::Memory {
+            id: "m1".to_string(),
+            user_id: Principal::anonymous(),
+            content: content.to_string(),
+            embedding: vec![content.len() as f32],
+            metadata: Default::default(),
+            tags: Vec::new(),
+            created_at: 1,
+            updated_at,
+            embedding_model: Some("model".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_edit_needs_embedding_only_for_content_changes() {
+        let current = memory("original text", 1);
+        assert!(!edit_needs_embedding(&current, "original text"));
+        assert!(edit_needs_embedding(&current, "edited text"));
+    }
+
+    #[test]
+    fn test_update_keeps_previous_versions_up_to_the_cap() {
+        open_revisions();
+        for version in 0..(MAX_REVISIONS_PER_MEMORY as u64 + 2) {
+            push_revision(&memory(&format!("version {}", version), version));
+        }
+
+        let revisions = list_memory_revisions("m1");
+        assert_eq!(revisions.len(), MAX_REVISIONS_PER_MEMORY);
+        // The two oldest are dropped; numbering keeps counting up
+        assert_eq!(revisions.first().map(|r| r.revision), Some(3));
+        assert_eq!(revisions.first().map(|r| r.content.as_str()), Some("version 2"));
+        assert_eq!(revisions.last().map(|r| r.revision), Some(MAX_REVISIONS_PER_MEMORY as u32 + 2));
+    }
+
+    #[test]
+    fn test_restore_brings_back_a_revision() {
+        open_revisions();
+        let original = memory("original text", 1);
+        push_revision(&original);
+        let edited = crate::types::Memory {
+            tags: vec!["edited".to_string()],
+            ..memory("edited text", 2)
+        };
+
+        let restored = restored_version(&edited, 1, 3).unwrap();
+        assert_eq!(restored.content, original.content);
+        assert_eq!(restored.embedding, original.embedding);
+        assert!(restored.tags.is_empty());
+        assert_eq!(restored.created_at, edited.created_at);
+        assert_eq!(restored.updated_at, 3);
+
+        let missing = restored_version(&edited, 2, 3).unwrap_err();
+        assert_eq!(missing.status_code(), 404);
+    }
+
+    #[test]
+    fn test_api_key_hash_and_prefix() {
+        let key = format!("{}{}", API_KEY_PREFIX, "0123456789abcdef0123456789abcdef");
+        let hash = hash_credential(&key);
+        assert_eq!(hash.len(), 64);
+        assert_ne!(hash, hash_credential(&format!("{}x", key)));
+        assert!(!hash.contains(&key));
+        
+        assert_eq!(api_key_prefix(&key), "om_key_0123456789ab");
+        assert_eq!(api_key_prefix("om_key_ab"), "om_key_ab");
+    }
+
+    #[test]
+    fn test_access_token_id_does_not_reveal_token() {
+        let token = "0123456789abcdef0123456789abcdef";
+        let hash = hash_credential(token);
+        let id = access_token_id(&hash);
+        assert_eq!(id.len(), ACCESS_TOKEN_ID_CHARS);
+        assert!(hash.starts_with(&id));
+        assert!(!token.contains(&id));
+        assert_eq!(id, access_token_id(&hash_credential(token)));
+    }
+
+    #[test]
+    fn test_listing_digest_ignores_order() {
+        let (m1, m2) = (entry_hash("m1", 1), entry_hash("m2", 2));
+        let mut a = [0u8; 32];
+        add_entry(&mut a, &m1);
+        add_entry(&mut a, &m2);
+        let mut b = [0u8; 32];
+        add_entry(&mut b, &m2);
+        add_entry(&mut b, &m1);
+        assert_eq!(listing_digest(&a), listing_digest(&b));
+        
+        // An edit swaps the entry and therefore changes the digest
+        let mut edited = a;
+        sub_entry(&mut edited, &m2);
+        add_entry(&mut edited, &entry_hash("m2", 3));
+        assert_ne!(listing_digest(&a), listing_digest(&edited));
+        
+        // Removing every entry gets back to the empty listing
+        sub_entry(&mut a, &m1);
+        sub_entry(&mut a, &m2);
+        assert_eq!(a, [0u8; 32]);
+    }
+
     #[test]
     fn test_calculate_simple_similarity() {
         assert_eq!(calculate_simple_similarity("hello world", "hello world"), 1.0);
@@ -0,0 +1,488 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use candid::{CandidType, Principal};
+use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
+use serde::{Deserialize, Serialize};
+
+// Persistent inverted index over memory content and tags, scored with BM25.
+// Like the vector store it is partitioned per user: postings are keyed by
+// owner, term and memory id, and corpus statistics are kept per owner.
+
+type VMem = VirtualMemory<DefaultMemoryImpl>;
+// One entry per (owner, term, memory), see `posting_key`
+type PostingMap = StableBTreeMap<String, Posting, VMem>;
+type DocumentMap = StableBTreeMap<String, IndexedDocument, VMem>;
+type CorpusMap = StableBTreeMap<Principal, CorpusStats, VMem>;
+
+thread_local! {
+    static POSTINGS: RefCell<Option<PostingMap>> = const { RefCell::new(None) };
+    static DOCUMENTS: RefCell<Option<DocumentMap>> = const { RefCell::new(None) };
+    static CORPUS: RefCell<Option<CorpusMap>> = const { RefCell::new(None) };
+}
+
+// BM25 parameters (standard Okapi defaults)
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+// Memories containing a term, one list per owner and term. Layout of the
+// previous index; only opened to be cleared.
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+pub struct PostingList(pub Vec<(String, u32)>);
+
+// A term's frequency in one memory, with the memory's length so scoring
+// does not have to look the document up
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct Posting {
+    pub frequency: u32,
+    pub length: u32,
+}
+
+// What was indexed for a memory, so it can be removed without re-tokenizing
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct IndexedDocument {
+    pub owner: Principal,
+    pub length: u32,
+    pub terms: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+pub struct CorpusStats {
+    pub document_count: u64,
+    pub total_length: u64,
+}
+
+impl Storable for PostingList {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for Posting {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for IndexedDocument {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl Storable for CorpusStats {
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+pub struct TextIndex;
+
+impl TextIndex {
+    pub fn init() {
+        Self::open();
+
+        // Postings used to be one list per owner and term; the documents
+        // indexed with them are indexed again in the new layout
+        let mut legacy_postings: StableBTreeMap<String, PostingList, VMem> = StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_TEXT_POSTINGS)
+        );
+        let reindex = !legacy_postings.is_empty();
+        if reindex {
+            legacy_postings.clear_new();
+            DOCUMENTS.with(|d| {
+                if let Some(ref mut documents) = *d.borrow_mut() {
+                    documents.clear_new();
+                }
+            });
+            CORPUS.with(|c| {
+                if let Some(ref mut corpus) = *c.borrow_mut() {
+                    corpus.clear_new();
+                }
+            });
+        }
+
+        // Memories stored before the index existed are indexed once, in the
+        // background; until then searches scan the owner's memories. A
+        // re-index starts over even if a build was under way.
+        if reindex
+            || (Self::get_document_count() == 0
+                && crate::storage::get_memory_count() > 0
+                && !crate::backfill::is_running(crate::backfill::TEXT_INDEX_JOB))
+        {
+            crate::backfill::start(crate::backfill::TEXT_INDEX_JOB);
+        }
+
+        ic_cdk::println!("Text index initialized");
+    }
+
+    fn open() {
+        POSTINGS.with(|p| {
+            *p.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_TEXT_TERM_POSTINGS)
+            ));
+        });
+
+        DOCUMENTS.with(|d| {
+            *d.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_TEXT_DOCUMENTS)
+            ));
+        });
+
+        CORPUS.with(|c| {
+            *c.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_TEXT_CORPUS)
+            ));
+        });
+    }
+
+    // One step of the initial build: index the next `limit` memories after
+    // `cursor` in id order. Re-indexing a memory stored since the build
+    // started replaces its entry, so nothing is counted twice.
+    pub fn index_batch(cursor: Option<&str>, limit: usize) -> (usize, Option<String>) {
+        let batch = crate::storage::memories_after(cursor, limit);
+        for memory in &batch {
+            Self::index_memory(&memory.id, memory.user_id, &memory.content, &memory.tags);
+        }
+
+        let next = if batch.len() < limit { None } else { batch.last().map(|memory| memory.id.clone()) };
+        (batch.len(), next)
+    }
+
+    // Index (or re-index) a memory's content and tags
+    pub fn index_memory(id: &str, owner: Principal, content: &str, tags: &[String]) {
+        Self::remove_memory(id);
+
+        let (term_frequencies, length) = document_terms(content, tags);
+
+        POSTINGS.with(|p| {
+            if let Some(ref mut postings) = *p.borrow_mut() {
+                for (term, frequency) in &term_frequencies {
+                    postings.insert(posting_key(owner, term, id), Posting { frequency: *frequency, length });
+                }
+            }
+        });
+
+        DOCUMENTS.with(|d| {
+            if let Some(ref mut documents) = *d.borrow_mut() {
+                documents.insert(id.to_string(), IndexedDocument {
+                    owner,
+                    length,
+                    terms: term_frequencies.into_keys().collect(),
+                });
+            }
+        });
+
+        Self::update_corpus(owner, 1, length as i64);
+    }
+
+    pub fn remove_memory(id: &str) -> bool {
+        let document = DOCUMENTS.with(|d| {
+            d.borrow_mut().as_mut().and_then(|documents| documents.remove(&id.to_string()))
+        });
+
+        let document = match document {
+            Some(document) => document,
+            None => return false,
+        };
+
+        POSTINGS.with(|p| {
+            if let Some(ref mut postings) = *p.borrow_mut() {
+                for term in &document.terms {
+                    postings.remove(&posting_key(document.owner, term, id));
+                }
+            }
+        });
+
+        Self::update_corpus(document.owner, -1, -(document.length as i64));
+        true
+    }
+
+    // BM25-ranked memory ids for the owner, best first
+    pub fn search(owner: Principal, query: &str, limit: usize) -> Vec<(String, f32)> {
+        if crate::backfill::is_running(crate::backfill::TEXT_INDEX_JOB) {
+            return Self::scan_search(owner, query, limit);
+        }
+
+        let corpus = CORPUS.with(|c| {
+            c.borrow().as_ref().and_then(|corpus| corpus.get(&owner))
+        }).unwrap_or_default();
+
+        if corpus.document_count == 0 {
+            return Vec::new();
+        }
+
+        let avg_length = corpus.total_length as f32 / corpus.document_count as f32;
+        let mut query_terms = tokenize(query);
+        query_terms.sort();
+        query_terms.dedup();
+
+        let mut scores: HashMap<String, f32> = HashMap::new();
+        for term in &query_terms {
+            let postings = Self::term_postings(owner, term);
+            if postings.is_empty() {
+                continue;
+            }
+
+            let idf = idf(corpus.document_count, postings.len() as u64);
+            for (doc_id, posting) in postings {
+                *scores.entry(doc_id).or_insert(0.0) += bm25_term_score(idf, posting.frequency, posting.length, avg_length);
+            }
+        }
+
+        let mut ranked: Vec<(String, f32)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+        ranked
+    }
+
+    // The same ranking computed directly from the owner's memories, for use
+    // while the index is still being built
+    fn scan_search(owner: Principal, query: &str, limit: usize) -> Vec<(String, f32)> {
+        let documents: Vec<(String, HashMap<String, u32>, u32)> = crate::storage::user_memory_ids(owner)
+            .iter()
+            .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+            .map(|memory| {
+                let (term_frequencies, length) = document_terms(&memory.content, &memory.tags);
+                (memory.id, term_frequencies, length)
+            })
+            .collect();
+        rank_documents(&documents, query, limit)
+    }
+
+    pub fn get_document_count() -> usize {
+        DOCUMENTS.with(|d| d.borrow().as_ref().map(|documents| documents.len() as usize).unwrap_or(0))
+    }
+
+    // The owner's memories containing `term`, by memory id
+    fn term_postings(owner: Principal, term: &str) -> Vec<(String, Posting)> {
+        let prefix = posting_key(owner, term, "");
+        POSTINGS.with(|p| {
+            p.borrow()
+                .as_ref()
+                .map(|postings| {
+                    postings
+                        .range(prefix.clone()..)
+                        .take_while(|(key, _)| key.starts_with(&prefix))
+                        .map(|(key, posting)| (key[prefix.len()..].to_string(), posting))
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    }
+
+    fn update_corpus(owner: Principal, document_delta: i64, length_delta: i64) {
+        CORPUS.with(|c| {
+            if let Some(ref mut corpus) = *c.borrow_mut() {
+                let mut stats = corpus.get(&owner).unwrap_or_default();
+                stats.document_count = (stats.document_count as i64 + document_delta).max(0) as u64;
+                stats.total_length = (stats.total_length as i64 + length_delta).max(0) as u64;
+                if stats.document_count == 0 {
+                    corpus.remove(&owner);
+                } else {
+                    corpus.insert(owner, stats);
+                }
+            }
+        });
+    }
+}
+
+// Term frequencies and length (in tokens) of a memory's content and tags
+fn document_terms(content: &str, tags: &[String]) -> (HashMap<String, u32>, u32) {
+    let mut tokens = tokenize(content);
+    for tag in tags {
+        tokens.extend(tokenize(tag));
+    }
+
+    let mut term_frequencies: HashMap<String, u32> = HashMap::new();
+    for token in &tokens {
+        *term_frequencies.entry(token.clone()).or_insert(0) += 1;
+    }
+    (term_frequencies, tokens.len() as u32)
+}
+
+// BM25 over in-memory (id, term frequencies, length) documents, best first
+fn rank_documents(documents: &[(String, HashMap<String, u32>, u32)], query: &str, limit: usize) -> Vec<(String, f32)> {
+    if documents.is_empty() {
+        return Vec::new();
+    }
+
+    let document_count = documents.len() as u64;
+    let total_length: u64 = documents.iter().map(|(_, _, length)| *length as u64).sum();
+    let avg_length = total_length as f32 / document_count as f32;
+    let mut query_terms = tokenize(query);
+    query_terms.sort();
+    query_terms.dedup();
+
+    let mut scores: HashMap<&str, f32> = HashMap::new();
+    for term in &query_terms {
+        let document_frequency = documents.iter().filter(|(_, terms, _)| terms.contains_key(term)).count() as u64;
+        if document_frequency == 0 {
+            continue;
+        }
+
+        let idf = idf(document_count, document_frequency);
+        for (id, terms, length) in documents {
+            if let Some(frequency) = terms.get(term) {
+                *scores.entry(id.as_str()).or_insert(0.0) += bm25_term_score(idf, *frequency, *length, avg_length);
+            }
+        }
+    }
+
+    let mut ranked: Vec<(String, f32)> = scores.into_iter().map(|(id, score)| (id.to_string(), score)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+// Neither principal text nor tokens contain ':', so the postings of one
+// owner and term share the prefix "{owner}:{term}:"
+fn posting_key(owner: Principal, term: &str, id: &str) -> String {
+    format!("{}:{}:{}", owner.to_text(), term, id)
+}
+
+// Lowercased tokens. Identifiers such as `parse_query`, `v1.2` or `PROJ-123`
+// are kept whole and their parts are emitted as well, so both the exact
+// identifier and its components match.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    for raw in text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.')) {
+        let token = raw.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+        if token.is_empty() {
+            continue;
+        }
+
+        let parts: Vec<&str> = token
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect();
+        if parts.len() > 1 {
+            tokens.extend(parts.iter().map(|part| part.to_string()));
+        }
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+fn idf(document_count: u64, document_frequency: u64) -> f32 {
+    let n = document_count as f32;
+    let df = document_frequency as f32;
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+}
+
+fn bm25_term_score(idf: f32, term_frequency: u32, document_length: u32, avg_length: f32) -> f32 {
+    let tf = term_frequency as f32;
+    let length_norm = if avg_length > 0.0 {
+        document_length as f32 / avg_length
+    } else {
+        1.0
+    };
+    idf * (tf * (BM25_K1 + 1.0)) / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * length_norm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_keeps_identifiers() {
+        let tokens = tokenize("Fixed parse_query for PROJ-123.");
+        assert!(tokens.contains(&"fixed".to_string()));
+        assert!(tokens.contains(&"parse_query".to_string()));
+        assert!(tokens.contains(&"parse".to_string()));
+        assert!(tokens.contains(&"query".to_string()));
+        assert!(tokens.contains(&"proj-123".to_string()));
+        assert!(tokens.contains(&"123".to_string()));
+        assert!(!tokens.iter().any(|t| t.ends_with('.')));
+    }
+
+    #[test]
+    fn test_idf_favours_rare_terms() {
+        assert!(idf(100, 1) > idf(100, 50));
+        assert!(idf(100, 100) > 0.0);
+    }
+
+    #[test]
+    fn test_bm25_term_score() {
+        let idf = idf(10, 2);
+        // More occurrences score higher, with diminishing returns
+        let one = bm25_term_score(idf, 1, 10, 10.0);
+        let two = bm25_term_score(idf, 2, 10, 10.0);
+        let four = bm25_term_score(idf, 4, 10, 10.0);
+        assert!(two > one);
+        assert!(four - two < two - one);
+        // Longer documents are penalized
+        assert!(bm25_term_score(idf, 1, 5, 10.0) > bm25_term_score(idf, 1, 20, 10.0));
+    }
+
+    #[test]
+    fn test_rank_documents_scans_without_index() {
+        let documents: Vec<(String, HashMap<String, u32>, u32)> = [
+            ("m1", "rust canister upgrade", vec![]),
+            ("m2", "grocery list", vec!["rust".to_string()]),
+            ("m3", "weekend plans", vec![]),
+        ]
+        .into_iter()
+        .map(|(id, content, tags)| {
+            let (terms, length) = document_terms(content, &tags);
+            (id.to_string(), terms, length)
+        })
+        .collect();
+
+        let ranked = rank_documents(&documents, "rust upgrade", 10);
+        let ids: Vec<&str> = ranked.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["m1", "m2"]);
+        assert!(rank_documents(&documents, "nothing", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_reads_lengths_from_postings() {
+        TextIndex::open();
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+
+        TextIndex::index_memory("m1", alice, "rust canister upgrade", &[]);
+        TextIndex::index_memory("m2", alice, "grocery list with some rust", &[]);
+        TextIndex::index_memory("m3", bob, "rust", &[]);
+
+        let ranked = TextIndex::search(alice, "rust", 10);
+        let ids: Vec<&str> = ranked.iter().map(|(id, _)| id.as_str()).collect();
+        // The shorter document ranks first; bob's memory is not visible
+        assert_eq!(ids, vec!["m1", "m2"]);
+
+        // Re-indexing replaces the old postings
+        TextIndex::index_memory("m1", alice, "weekend plans", &[]);
+        let ids: Vec<String> = TextIndex::search(alice, "rust", 10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["m2"]);
+        assert!(TextIndex::remove_memory("m2"));
+        assert!(TextIndex::search(alice, "rust", 10).is_empty());
+        assert_eq!(TextIndex::term_postings(bob, "rust").len(), 1);
+    }
+}
@@ -32,6 +32,9 @@ pub struct Memory {
     pub tags: Vec<String>,
     pub created_at: u64,
     pub updated_at: u64,
+    // Model that produced `embedding`; None for placeholder or unlabeled vectors
+    #[serde(default)]
+    pub embedding_model: Option<String>,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -55,14 +58,67 @@ pub struct Conversation {
 }
 
 // API Request/Response Types
-#[derive(Deserialize)]
+#[derive(CandidType, Deserialize)]
 pub struct AddMemoryRequest {
     pub content: String,
     pub metadata: Option<HashMap<String, String>>,
     pub tags: Option<Vec<String>>,
 }
 
+// Fields to change on an existing memory; PUT requires `content` and
+// replaces tags and metadata, PATCH only touches what is given
+#[derive(Deserialize)]
+pub struct UpdateMemoryRequest {
+    pub content: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct BulkAddMemoryRequest {
+    pub memories: Vec<AddMemoryRequest>,
+}
+
+// Outcome of one item in a bulk operation; exactly one of `id`/`error` is set for adds
+#[derive(Serialize)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkAddResponse {
+    pub created: usize,
+    pub failed: usize,
+    pub results: Vec<BulkItemResult>,
+}
+
+// Delete either the listed ids or every memory matching `filter`
 #[derive(Deserialize)]
+pub struct BulkDeleteRequest {
+    pub ids: Option<Vec<String>>,
+    pub filter: Option<BulkDeleteFilter>,
+}
+
+#[derive(Deserialize)]
+pub struct BulkDeleteFilter {
+    pub tags: Option<Vec<String>>,
+    pub tag_mode: Option<crate::search::TagMatchMode>,
+    pub created_after: Option<u64>,
+    pub created_before: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct BulkDeleteResponse {
+    pub deleted: usize,
+    pub failed: usize,
+    // Filter matches left over beyond the per-request limit
+    pub remaining: usize,
+    pub results: Vec<BulkItemResult>,
+}
+
+#[derive(CandidType, Deserialize)]
 pub struct SaveConversationRequest {
     pub title: String,
     pub content: String,
@@ -70,7 +126,7 @@ pub struct SaveConversationRequest {
     pub metadata: Option<HashMap<String, String>>,
 }
 
-#[derive(Serialize)]
+#[derive(CandidType, Serialize)]
 pub struct AddMemoryResponse {
     pub id: String,
     pub created_at: u64,
@@ -83,7 +139,7 @@ pub struct SearchResponse {
     pub query_time_ms: u64,
 }
 
-#[derive(Serialize)]
+#[derive(CandidType, Serialize)]
 pub struct ListMemoriesResponse {
     pub memories: Vec<Memory>,
     pub total_count: usize,
@@ -115,20 +171,22 @@ pub struct StatsResponse {
 }
 
 // OpenAI API Types
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct EmbeddingResponse {
     pub data: Vec<EmbeddingData>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct EmbeddingData {
     pub embedding: Vec<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
 }
 
 #[derive(Serialize)]
 pub struct EmbeddingRequest {
     pub model: String,
-    pub input: String,
+    pub input: Vec<String>,
     pub encoding_format: String,
 }
 
@@ -143,7 +201,7 @@ pub struct AuthToken {
 // Hybrid Token Authentication Types
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct AccessToken {
-    pub token: String,
+    pub token: String, // SHA-256 hash of the token; the plaintext is only returned at creation
     pub owner_principal: Principal, // The II Principal
     pub issued_to: Option<String>,  // Description like "CLI on MacBook"
     pub permissions: Vec<Permission>,
@@ -152,7 +210,7 @@ pub struct AccessToken {
     pub last_used_at: Option<u64>,
 }
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub enum Permission {
     Read,
     Write,
@@ -160,22 +218,30 @@ pub enum Permission {
     ManageConfig,
 }
 
-#[derive(Deserialize)]
+impl Permission {
+    pub fn all() -> Vec<Permission> {
+        vec![Permission::Read, Permission::Write, Permission::Delete, Permission::ManageConfig]
+    }
+}
+
+#[derive(CandidType, Deserialize)]
 pub struct CreateTokenRequest {
     pub description: Option<String>, // "CLI on MacBook", "Android App", etc.
     pub permissions: Option<Vec<Permission>>,
     pub expires_in_days: Option<u32>, // Default 30 days
 }
 
-#[derive(Serialize)]
+#[derive(CandidType, Serialize)]
 pub struct CreateTokenResponse {
     pub token: String,
+    pub id: String,
     pub expires_at: u64,
     pub permissions: Vec<Permission>,
 }
 
-#[derive(Serialize)]
+#[derive(CandidType, Serialize)]
 pub struct TokenInfo {
+    pub id: String, // Leading characters of the token's hash, used to revoke it
     pub description: Option<String>,
     pub permissions: Vec<Permission>,
     pub expires_at: u64,
@@ -183,11 +249,64 @@ pub struct TokenInfo {
     pub last_used_at: Option<u64>,
 }
 
+// Per-user API keys. Only the SHA-256 hash of the key is stored; the
+// plaintext is returned once, when the key is created.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct ApiKey {
+    pub key_hash: String,
+    pub prefix: String, // Leading characters of the key, used to list and revoke it
+    pub owner_principal: Principal,
+    pub name: Option<String>,
+    pub permissions: Vec<Permission>,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+    pub last_used_at: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: Option<String>,
+    pub permissions: Option<Vec<Permission>>, // Default: all permissions of the caller
+    pub expires_in_days: Option<u32>,         // Default: never expires
+}
+
+#[derive(Serialize)]
+pub struct CreateApiKeyResponse {
+    pub api_key: String,
+    pub prefix: String,
+    pub permissions: Vec<Permission>,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyInfo {
+    pub prefix: String,
+    pub name: Option<String>,
+    pub permissions: Vec<Permission>,
+    pub expires_at: Option<u64>,
+    pub created_at: u64,
+    pub last_used_at: Option<u64>,
+}
+
 // API Provider Types
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub enum ApiProvider {
     OpenAI,
     OpenRouter,
+    OpenAICompatible, // Self-hosted endpoint at `UserConfig.api_base_url`
+    Local,            // In-canister hashing embedder, no API key required
+}
+
+impl ApiProvider {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_'], "").as_str() {
+            "openai" => Some(ApiProvider::OpenAI),
+            "openrouter" => Some(ApiProvider::OpenRouter),
+            "openaicompatible" => Some(ApiProvider::OpenAICompatible),
+            "local" => Some(ApiProvider::Local),
+            _ => None,
+        }
+    }
 }
 
 impl Default for ApiProvider {
@@ -206,6 +325,9 @@ pub struct UserConfig {
     pub embedding_model: String,
     pub created_at: u64,
     pub updated_at: u64,
+    pub api_base_url: Option<String>,       // For ApiProvider::OpenAICompatible
+    pub compatible_api_key: Option<String>, // Optional key for the compatible endpoint
+    pub embedding_dimension: Option<u32>,   // Output dimension of an OpenAICompatible model, if not a well-known one
 }
 
 #[derive(Deserialize)]
@@ -218,8 +340,58 @@ pub struct SetApiKeyRequest {
 pub struct UpdateConfigRequest {
     pub openai_api_key: Option<String>,
     pub openrouter_api_key: Option<String>,
-    pub api_provider: Option<String>, // "openai" or "openrouter"
+    pub api_provider: Option<String>, // "openai", "openrouter", "openai_compatible" or "local"
     pub embedding_model: Option<String>,
+    pub api_base_url: Option<String>,
+    pub compatible_api_key: Option<String>,
+    pub embedding_dimension: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ComputeClustersRequest {
+    pub method: Option<String>,      // "kmeans", "dbscan", "hierarchical", "content_based", "tag_based" or "temporal"
+    pub k: Option<usize>,            // KMeans cluster count; if omitted, the best silhouette over 2..=MAX_AUTO_K
+    pub time_period: Option<String>, // Temporal buckets: "day", "week", "month" or "year"
+    pub eps: Option<f32>,                  // DBSCAN radius in cosine distance (0-2)
+    pub min_points: Option<usize>,         // DBSCAN points needed for a dense region
+    pub similarity_threshold: Option<f32>, // Hierarchical cut, cosine similarity (-1 to 1)
+    pub seed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCollectionRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub memory_ids: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCollectionRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CollectionMemoriesRequest {
+    pub memory_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCategoryRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub confidence_threshold: Option<f32>, // 0-1; defaults to 0.7
+    pub parent_category: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCategoryRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub confidence_threshold: Option<f32>,
+    pub parent_category: Option<String>, // "" moves the category to the top level
 }
 
 #[derive(Serialize)]
@@ -230,6 +402,9 @@ pub struct ConfigResponse {
     pub openrouter_key_preview: Option<String>,
     pub api_provider: String,
     pub embedding_model: String,
+    pub api_base_url: Option<String>,
+    pub has_compatible_key: bool,
+    pub embedding_dimension: Option<u32>,
     pub available_models: Vec<ModelInfo>,
     pub updated_at: Option<u64>,
 }
@@ -279,6 +454,24 @@ impl Storable for Conversation {
 #[derive(CandidType, Deserialize, Serialize, Clone, Default)]
 pub struct UserMemoryList(pub Vec<String>);
 
+// A previous version of a memory, kept so edits can be undone
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct MemoryRevision {
+    pub revision: u32,
+    pub content: String,
+    #[serde(skip_serializing)] // Kept for restore, too large for API listings
+    pub embedding: Vec<f32>,
+    pub metadata: HashMap<String, String>,
+    pub tags: Vec<String>,
+    pub updated_at: u64, // When this version was written
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+}
+
+// Revisions of one memory, oldest first
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+pub struct MemoryRevisionList(pub Vec<MemoryRevision>);
+
 // Create a wrapper type for user conversation lists to implement Storable
 #[derive(CandidType, Deserialize, Serialize, Clone, Default)]
 pub struct UserConversationList(pub Vec<String>);
@@ -295,6 +488,18 @@ impl Storable for UserMemoryList {
     }
 }
 
+impl Storable for MemoryRevisionList {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
 impl Storable for UserConversationList {
     const BOUND: Bound = Bound::Unbounded;
 
@@ -320,6 +525,18 @@ impl Storable for UserConfig {
     }
 }
 
+impl Storable for ApiKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
 // Implement Storable for AccessToken
 impl Storable for AccessToken {
     const BOUND: Bound = Bound::Unbounded;
@@ -331,4 +548,26 @@ impl Storable for AccessToken {
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
         candid::decode_one(&bytes).unwrap()
     }
-}
\ No newline at end of file
+}
+// Running listing digest of a user's memories. The accumulator is the sum
+// (mod 2^256) of one hash per (id, updated_at) entry, so an entry can be
+// added or removed without reading the others. `path_id` names the public
+// certified path and is only handed to the owner.
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct ListingDigest {
+    pub accumulator: Vec<u8>,
+    pub digest: String,
+    pub path_id: Option<String>,
+}
+
+impl Storable for ListingDigest {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
@@ -56,7 +56,20 @@ pub fn extract_path_param(path: &str, pattern: &str) -> Option<String> {
 }
 
 pub fn generate_uuid() -> String {
-    // Create a unique ID using time, counter, and caller for uniqueness
+    // Random (version 4) UUID once the canister RNG is seeded
+    if let Ok(bytes) = crate::rng::random_bytes(16) {
+        return format!(
+            "{}-{}-4{}-{:x}{}-{}",
+            hex::encode(&bytes[0..4]),
+            hex::encode(&bytes[4..6]),
+            &hex::encode(&bytes[6..8])[1..],
+            0x8 | (bytes[8] >> 6),
+            &hex::encode(&bytes[8..10])[1..],
+            hex::encode(&bytes[10..16])
+        );
+    }
+    
+    // Before the first seed arrives, derive a unique ID from time, counter, and caller
     let counter = UUID_COUNTER.with(|c| {
         let mut count = c.borrow_mut();
         *count = count.wrapping_add(1);
@@ -92,7 +105,7 @@ pub fn get_current_time() -> u64 {
 pub fn create_cors_headers() -> Vec<(String, String)> {
     vec![
         ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
-        ("Access-Control-Allow-Methods".to_string(), "GET, POST, PUT, DELETE, OPTIONS".to_string()),
+        ("Access-Control-Allow-Methods".to_string(), "GET, POST, PUT, PATCH, DELETE, OPTIONS".to_string()),
         ("Access-Control-Allow-Headers".to_string(), "Content-Type, Authorization, X-API-Key, X-Requested-With".to_string()),
         ("Access-Control-Expose-Headers".to_string(), "Content-Length, Date, Server".to_string()),
         ("Access-Control-Max-Age".to_string(), "86400".to_string()),
@@ -167,6 +180,17 @@ mod tests {
         assert_eq!(params.get("limit"), Some(&"5".to_string()));
     }
 
+    #[test]
+    fn test_generate_uuid_uses_seeded_rng() {
+        crate::rng::mix_seed(b"uuid test seed");
+        let id = generate_uuid();
+        let parts: Vec<&str> = id.split('-').collect();
+        assert_eq!(parts.iter().map(|p| p.len()).collect::<Vec<_>>(), vec![8, 4, 4, 4, 12]);
+        assert!(parts[2].starts_with('4'));
+        assert!(matches!(parts[3].chars().next(), Some('8' | '9' | 'a' | 'b')));
+        assert_ne!(id, generate_uuid());
+    }
+
     #[test]
     fn test_extract_path_param() {
         assert_eq!(
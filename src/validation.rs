@@ -23,12 +23,33 @@ pub const MAX_METADATA_VALUE_LENGTH: usize = 500;
 /// Maximum number of metadata entries per memory
 pub const MAX_METADATA_ENTRIES: usize = 20;
 
+/// Maximum number of items in one bulk add or bulk delete request
+pub const MAX_BULK_ITEMS: usize = 500;
+
 /// Maximum conversation content size (100KB)
 pub const MAX_CONVERSATION_CONTENT_SIZE: usize = 100 * 1024;
 
 /// Maximum conversation title length
 pub const MAX_CONVERSATION_TITLE_LENGTH: usize = 200;
 
+/// Maximum collection name length
+pub const MAX_COLLECTION_NAME_LENGTH: usize = 100;
+
+/// Maximum collection description length
+pub const MAX_COLLECTION_DESCRIPTION_LENGTH: usize = 500;
+
+/// Maximum category name length
+pub const MAX_CATEGORY_NAME_LENGTH: usize = 50;
+
+/// Maximum category description length
+pub const MAX_CATEGORY_DESCRIPTION_LENGTH: usize = 500;
+
+/// Maximum number of keywords per category
+pub const MAX_CATEGORY_KEYWORDS: usize = 50;
+
+/// Maximum length of a single category keyword
+pub const MAX_CATEGORY_KEYWORD_LENGTH: usize = 50;
+
 /// Validation for add memory requests
 pub fn validate_add_memory_request(req: &AddMemoryRequest) -> Result<()> {
     // Validate content
@@ -176,6 +197,95 @@ pub fn validate_save_conversation_request(req: &SaveConversationRequest) -> Resu
     Ok(())
 }
 
+/// Validation for collection names and descriptions (None leaves a field unchanged)
+pub fn validate_collection_fields(name: Option<&str>, description: Option<&str>) -> Result<()> {
+    if let Some(name) = name {
+        if name.trim().is_empty() {
+            return Err(OpenMemoryError::validation(
+                "Collection name cannot be empty",
+                Some("name")
+            ));
+        }
+        if name.chars().count() > MAX_COLLECTION_NAME_LENGTH {
+            return Err(OpenMemoryError::validation(
+                format!("Collection name too long (max {} characters)", MAX_COLLECTION_NAME_LENGTH),
+                Some("name")
+            ));
+        }
+    }
+    
+    if let Some(description) = description {
+        if description.chars().count() > MAX_COLLECTION_DESCRIPTION_LENGTH {
+            return Err(OpenMemoryError::validation(
+                format!("Collection description too long (max {} characters)", MAX_COLLECTION_DESCRIPTION_LENGTH),
+                Some("description")
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Validation for category fields; `None` means the field is not being set
+pub fn validate_category_fields(
+    name: Option<&str>,
+    description: Option<&str>,
+    keywords: Option<&[String]>,
+    confidence_threshold: Option<f32>,
+) -> Result<()> {
+    if let Some(name) = name {
+        if name.trim().is_empty() {
+            return Err(OpenMemoryError::validation(
+                "Category name cannot be empty",
+                Some("name")
+            ));
+        }
+        if name.chars().count() > MAX_CATEGORY_NAME_LENGTH {
+            return Err(OpenMemoryError::validation(
+                format!("Category name too long (max {} characters)", MAX_CATEGORY_NAME_LENGTH),
+                Some("name")
+            ));
+        }
+    }
+    
+    if let Some(description) = description {
+        if description.chars().count() > MAX_CATEGORY_DESCRIPTION_LENGTH {
+            return Err(OpenMemoryError::validation(
+                format!("Category description too long (max {} characters)", MAX_CATEGORY_DESCRIPTION_LENGTH),
+                Some("description")
+            ));
+        }
+    }
+    
+    if let Some(keywords) = keywords {
+        if keywords.len() > MAX_CATEGORY_KEYWORDS {
+            return Err(OpenMemoryError::validation(
+                format!("Too many keywords (max {})", MAX_CATEGORY_KEYWORDS),
+                Some("keywords")
+            ));
+        }
+        for keyword in keywords {
+            if keyword.trim().is_empty() || keyword.chars().count() > MAX_CATEGORY_KEYWORD_LENGTH {
+                return Err(OpenMemoryError::validation(
+                    format!("Invalid keyword: '{}' (1-{} characters)", keyword, MAX_CATEGORY_KEYWORD_LENGTH),
+                    Some("keywords")
+                ));
+            }
+        }
+    }
+    
+    if let Some(threshold) = confidence_threshold {
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            return Err(OpenMemoryError::validation(
+                "Confidence threshold must be greater than 0 and at most 1",
+                Some("confidence_threshold")
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
 /// Validation for search requests
 pub fn validate_search_request(req: &SearchRequest) -> Result<()> {
     if req.query.trim().is_empty() {
@@ -227,6 +337,135 @@ pub fn validate_search_request(req: &SearchRequest) -> Result<()> {
         }
     }
     
+    if let Some(ref metadata) = req.metadata {
+        if metadata.len() > MAX_METADATA_ENTRIES {
+            return Err(OpenMemoryError::validation(
+                format!("Too many metadata filters (max {})", MAX_METADATA_ENTRIES),
+                Some("metadata")
+            ));
+        }
+        
+        for (key, value) in metadata {
+            if key.trim().is_empty() || key.len() > MAX_METADATA_KEY_LENGTH || value.len() > MAX_METADATA_VALUE_LENGTH {
+                return Err(OpenMemoryError::validation(
+                    format!("Invalid metadata filter '{}'", key),
+                    Some("metadata")
+                ));
+            }
+        }
+    }
+    
+    if let (Some(after), Some(before)) = (req.created_after, req.created_before) {
+        if after > before {
+            return Err(OpenMemoryError::validation(
+                "created_after must not be later than created_before",
+                Some("created_after")
+            ));
+        }
+    }
+    
+    if let Some(min_similarity) = req.min_similarity {
+        if !(0.0..=1.0).contains(&min_similarity) {
+            return Err(OpenMemoryError::validation(
+                "min_similarity must be between 0.0 and 1.0",
+                Some("min_similarity")
+            ));
+        }
+    }
+    
+    // Keyword search ranks by BM25 and has no similarity to compare against
+    if req.min_similarity.is_some() && req.mode == Some(crate::search::SearchMode::Keyword) {
+        return Err(OpenMemoryError::validation(
+            "min_similarity is only supported in semantic and hybrid search",
+            Some("min_similarity")
+        ));
+    }
+    
+    if let Some(semantic_weight) = req.semantic_weight {
+        if !(0.0..=1.0).contains(&semantic_weight) {
+            return Err(OpenMemoryError::validation(
+                "semantic_weight must be between 0.0 and 1.0",
+                Some("semantic_weight")
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Validation for bulk add requests (items are validated individually)
+pub fn validate_bulk_add_request(req: &BulkAddMemoryRequest) -> Result<()> {
+    if req.memories.is_empty() {
+        return Err(OpenMemoryError::validation(
+            "No memories provided",
+            Some("memories")
+        ));
+    }
+    
+    if req.memories.len() > MAX_BULK_ITEMS {
+        return Err(OpenMemoryError::validation(
+            format!("Too many memories in one request (max {})", MAX_BULK_ITEMS),
+            Some("memories")
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Validation for bulk delete requests
+pub fn validate_bulk_delete_request(req: &BulkDeleteRequest) -> Result<()> {
+    match (&req.ids, &req.filter) {
+        (Some(ids), None) => {
+            if ids.is_empty() {
+                return Err(OpenMemoryError::validation(
+                    "No memory ids provided",
+                    Some("ids")
+                ));
+            }
+            
+            if ids.len() > MAX_BULK_ITEMS {
+                return Err(OpenMemoryError::validation(
+                    format!("Too many ids in one request (max {})", MAX_BULK_ITEMS),
+                    Some("ids")
+                ));
+            }
+        }
+        (None, Some(filter)) => {
+            let has_tags = filter.tags.as_ref().map(|tags| !tags.is_empty()).unwrap_or(false);
+            // An empty filter would delete every memory the user has
+            if !has_tags && filter.created_after.is_none() && filter.created_before.is_none() {
+                return Err(OpenMemoryError::validation(
+                    "Filter must specify tags or a date range",
+                    Some("filter")
+                ));
+            }
+            
+            if let Some(ref tags) = filter.tags {
+                if let Some(tag) = tags.iter().find(|tag| !is_valid_tag(tag)) {
+                    return Err(OpenMemoryError::validation(
+                        format!("Invalid filter tag '{}'", tag),
+                        Some("filter.tags")
+                    ));
+                }
+            }
+            
+            if let (Some(after), Some(before)) = (filter.created_after, filter.created_before) {
+                if after > before {
+                    return Err(OpenMemoryError::validation(
+                        "created_after must not be later than created_before",
+                        Some("filter.created_after")
+                    ));
+                }
+            }
+        }
+        _ => {
+            return Err(OpenMemoryError::validation(
+                "Specify either ids or filter",
+                None::<String>
+            ));
+        }
+    }
+    
     Ok(())
 }
 
@@ -355,11 +594,99 @@ pub fn sanitize_tags(tags: &[String]) -> Vec<String> {
         .collect()
 }
 
-#[derive(Deserialize)]
+#[derive(candid::CandidType, serde::Deserialize)]
 pub struct SearchRequest {
     pub query: String,
     pub limit: Option<usize>,
     pub tags: Option<Vec<String>>,
+    /// `any` (default) or `all` of the tags must be present
+    pub tag_mode: Option<crate::search::TagMatchMode>,
+    /// Metadata key/value pairs that must all match
+    pub metadata: Option<std::collections::HashMap<String, String>>,
+    /// Inclusive creation time bounds in nanoseconds
+    pub created_after: Option<u64>,
+    pub created_before: Option<u64>,
+    pub min_similarity: Option<f32>,
+    pub mode: Option<crate::search::SearchMode>,
+    pub semantic_weight: Option<f32>,
+    /// Only memories in this collection (or cluster)
+    pub collection_id: Option<String>,
+}
+
+impl SearchRequest {
+    /// Build a search from `GET /memories/search` query parameters:
+    /// `q` (or `query`), `limit`, comma-separated `tags`, `tag_mode`, `mode`,
+    /// `semantic_weight`, `min_similarity`, `created_after`, `created_before`,
+    /// `collection` and `meta.<key>=<value>` metadata filters
+    pub fn from_query_params(params: &std::collections::HashMap<String, String>) -> Result<Self> {
+        fn number<T: std::str::FromStr>(params: &std::collections::HashMap<String, String>, name: &str) -> Result<Option<T>> {
+            params
+                .get(name)
+                .map(|value| {
+                    value.parse().map_err(|_| {
+                        OpenMemoryError::validation(format!("Invalid value for '{}': {}", name, value), Some(name))
+                    })
+                })
+                .transpose()
+        }
+        
+        let query = params
+            .get("q")
+            .or_else(|| params.get("query"))
+            .cloned()
+            .unwrap_or_default();
+        
+        let tags = params.get("tags").map(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>()
+        });
+        
+        let tag_mode = match params.get("tag_mode").map(|m| m.to_lowercase()).as_deref() {
+            None => None,
+            Some("any") => Some(crate::search::TagMatchMode::Any),
+            Some("all") => Some(crate::search::TagMatchMode::All),
+            Some(other) => return Err(OpenMemoryError::validation(
+                format!("Invalid tag_mode '{}': expected any or all", other),
+                Some("tag_mode")
+            )),
+        };
+        
+        let mode = match params.get("mode").map(|m| m.to_lowercase()).as_deref() {
+            None => None,
+            Some("semantic") => Some(crate::search::SearchMode::Semantic),
+            Some("keyword") => Some(crate::search::SearchMode::Keyword),
+            Some("hybrid") => Some(crate::search::SearchMode::Hybrid),
+            Some(other) => return Err(OpenMemoryError::validation(
+                format!("Invalid mode '{}': expected semantic, keyword or hybrid", other),
+                Some("mode")
+            )),
+        };
+        
+        let metadata: std::collections::HashMap<String, String> = params
+            .iter()
+            .filter_map(|(key, value)| key.strip_prefix("meta.").map(|key| (key.to_string(), value.clone())))
+            .collect();
+        
+        Ok(SearchRequest {
+            query,
+            limit: number(params, "limit")?,
+            tags,
+            tag_mode,
+            metadata: if metadata.is_empty() { None } else { Some(metadata) },
+            created_after: number(params, "created_after")?,
+            created_before: number(params, "created_before")?,
+            min_similarity: number(params, "min_similarity")?,
+            mode,
+            semantic_weight: number(params, "semantic_weight")?,
+            collection_id: params
+                .get("collection")
+                .or_else(|| params.get("collection_id"))
+                .filter(|id| !id.is_empty())
+                .cloned(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -402,6 +729,56 @@ mod tests {
         assert!(validate_add_memory_request(&invalid_tag).is_err());
     }
     
+    #[test]
+    fn test_validate_search_request() {
+        let parse = |json: &str| serde_json::from_str::<SearchRequest>(json).unwrap();
+        
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "tags": ["a", "b"], "tag_mode": "all"}"#)).is_ok());
+        assert!(validate_search_request(&parse(r#"{"query": "  "}"#)).is_err());
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "limit": 0}"#)).is_err());
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "min_similarity": 1.5}"#)).is_err());
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "mode": "keyword", "min_similarity": 0.5}"#)).is_err());
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "mode": "hybrid", "min_similarity": 0.5}"#)).is_ok());
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "created_after": 10, "created_before": 5}"#)).is_err());
+        assert!(validate_search_request(&parse(r#"{"query": "rust", "tags": ["bad tag"]}"#)).is_err());
+    }
+    
+    #[test]
+    fn test_validate_collection_fields() {
+        assert!(validate_collection_fields(Some("Reading list"), Some("Articles to revisit")).is_ok());
+        assert!(validate_collection_fields(None, None).is_ok());
+        assert!(validate_collection_fields(Some("   "), None).is_err());
+        assert!(validate_collection_fields(Some(&"a".repeat(MAX_COLLECTION_NAME_LENGTH + 1)), None).is_err());
+        assert!(validate_collection_fields(None, Some(&"a".repeat(MAX_COLLECTION_DESCRIPTION_LENGTH + 1))).is_err());
+    }
+    
+    #[test]
+    fn test_validate_category_fields() {
+        let keywords = vec!["rust".to_string(), "how-to".to_string()];
+        assert!(validate_category_fields(Some("Programming"), Some("Code notes"), Some(&keywords), Some(0.5)).is_ok());
+        assert!(validate_category_fields(None, None, None, None).is_ok());
+        assert!(validate_category_fields(Some(" "), None, None, None).is_err());
+        assert!(validate_category_fields(Some(&"a".repeat(MAX_CATEGORY_NAME_LENGTH + 1)), None, None, None).is_err());
+        assert!(validate_category_fields(None, None, Some(&["".to_string()]), None).is_err());
+        assert!(validate_category_fields(None, None, None, Some(0.0)).is_err());
+        assert!(validate_category_fields(None, None, None, Some(1.5)).is_err());
+        assert!(validate_category_fields(None, None, None, Some(f32::NAN)).is_err());
+    }
+    
+    #[test]
+    fn test_validate_bulk_delete_request() {
+        let parse = |json: &str| serde_json::from_str::<BulkDeleteRequest>(json).unwrap();
+        
+        assert!(validate_bulk_delete_request(&parse(r#"{"ids": ["a", "b"]}"#)).is_ok());
+        assert!(validate_bulk_delete_request(&parse(r#"{"filter": {"tags": ["old"]}}"#)).is_ok());
+        assert!(validate_bulk_delete_request(&parse(r#"{"filter": {"created_before": 100}}"#)).is_ok());
+        // Neither, both, or an empty filter are rejected
+        assert!(validate_bulk_delete_request(&parse(r#"{}"#)).is_err());
+        assert!(validate_bulk_delete_request(&parse(r#"{"ids": ["a"], "filter": {"tags": ["old"]}}"#)).is_err());
+        assert!(validate_bulk_delete_request(&parse(r#"{"filter": {"tags": []}}"#)).is_err());
+        assert!(validate_bulk_delete_request(&parse(r#"{"ids": []}"#)).is_err());
+    }
+    
     #[test]
     fn test_is_valid_tag() {
         assert!(is_valid_tag("valid_tag"));
@@ -435,4 +812,31 @@ mod tests {
         let sanitized = sanitize_tags(&tags);
         assert_eq!(sanitized, vec!["valid_tag", "invalidtag", "another-valid"]);
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_search_request_from_query_params() {
+        let mut params = HashMap::new();
+        params.insert("q".to_string(), "rust ownership".to_string());
+        params.insert("limit".to_string(), "5".to_string());
+        params.insert("tags".to_string(), "rust, programming,".to_string());
+        params.insert("mode".to_string(), "Hybrid".to_string());
+        params.insert("meta.source".to_string(), "cli".to_string());
+        params.insert("collection".to_string(), "collection_1".to_string());
+        
+        let req = SearchRequest::from_query_params(&params).unwrap();
+        assert_eq!(req.query, "rust ownership");
+        assert_eq!(req.limit, Some(5));
+        assert_eq!(req.tags, Some(vec!["rust".to_string(), "programming".to_string()]));
+        assert_eq!(req.mode, Some(crate::search::SearchMode::Hybrid));
+        assert_eq!(req.metadata.unwrap().get("source").map(String::as_str), Some("cli"));
+        assert!(req.tag_mode.is_none());
+        assert_eq!(req.collection_id.as_deref(), Some("collection_1"));
+        
+        params.insert("limit".to_string(), "ten".to_string());
+        assert!(SearchRequest::from_query_params(&params).is_err());
+        
+        params.remove("limit");
+        params.insert("mode".to_string(), "fuzzy".to_string());
+        assert!(SearchRequest::from_query_params(&params).is_err());
+    }
+}
@@ -1,25 +1,40 @@
 use crate::types::*;
+use crate::hnsw::{self, EntryPoint, GraphStore, HnswNode, HnswParams};
 use std::collections::HashMap;
 use std::cell::RefCell;
+use std::ops::Bound;
 use ic_stable_structures::{StableBTreeMap, DefaultMemoryImpl, Storable};
+use ic_stable_structures::memory_manager::VirtualMemory;
 use std::borrow::Cow;
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
 // Vector storage using stable memory
-type VectorMemory = DefaultMemoryImpl;
-type VectorMap = StableBTreeMap<String, VectorEntry, VectorMemory>;
-type VectorIndexMap = StableBTreeMap<u64, VectorIndexList, VectorMemory>;
+type VectorMap = StableBTreeMap<String, VectorEntry, VirtualMemory<DefaultMemoryImpl>>;
+type HnswNodeMap = StableBTreeMap<String, HnswNode, VirtualMemory<DefaultMemoryImpl>>;
+// Keyed by graph partition, see `partition_key`
+type HnswEntryPointMap = StableBTreeMap<String, EntryPoint, VirtualMemory<DefaultMemoryImpl>>;
+// One entry per vector, keyed by `owner_vector_key`, so a write never touches
+// the owner's other ids
+type UserVectorMap = StableBTreeMap<String, (), VirtualMemory<DefaultMemoryImpl>>;
+type UserVectorCountMap = StableBTreeMap<Principal, u64, VirtualMemory<DefaultMemoryImpl>>;
+// Graph nodes per partition, see `partition_key`
+type PartitionNodeCountMap = StableBTreeMap<String, u64, VirtualMemory<DefaultMemoryImpl>>;
 
 thread_local! {
     static VECTORS: RefCell<Option<VectorMap>> = RefCell::new(None);
-    static VECTOR_INDEX: RefCell<Option<VectorIndexMap>> = RefCell::new(None);
+    static HNSW_NODES: RefCell<Option<HnswNodeMap>> = const { RefCell::new(None) };
+    static HNSW_ENTRY_POINTS: RefCell<Option<HnswEntryPointMap>> = const { RefCell::new(None) };
+    static USER_VECTORS: RefCell<Option<UserVectorMap>> = const { RefCell::new(None) };
+    static USER_VECTOR_COUNTS: RefCell<Option<UserVectorCountMap>> = const { RefCell::new(None) };
+    static PARTITION_NODE_COUNTS: RefCell<Option<PartitionNodeCountMap>> = const { RefCell::new(None) };
     static VECTOR_CONFIG: RefCell<VectorStoreConfig> = RefCell::new(VectorStoreConfig::default());
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct VectorEntry {
     pub id: String,
+    pub owner: Principal, // Vectors are partitioned per owner; searches never cross partitions
     pub vector: Vec<f32>,
     pub metadata: HashMap<String, String>,
     pub created_at: u64,
@@ -32,6 +47,10 @@ pub struct VectorStoreConfig {
     pub similarity_function: SimilarityFunction,
     pub max_vectors: usize,
     pub index_threshold: f32, // Minimum similarity threshold for indexing
+    pub hnsw_m: usize, // Max links per node in the ANN graph
+    pub hnsw_ef_construction: usize, // Candidate list size when inserting into the graph
+    pub hnsw_ef_search: usize, // Candidate list size when searching; higher means better recall
+    pub exact_search_threshold: usize, // Collections up to this size use an exact scan
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -48,6 +67,10 @@ impl Default for VectorStoreConfig {
             similarity_function: SimilarityFunction::Cosine,
             max_vectors: 1_000_000,
             index_threshold: 0.7,
+            hnsw_m: 16,
+            hnsw_ef_construction: 100,
+            hnsw_ef_search: 64,
+            exact_search_threshold: 1_000,
         }
     }
 }
@@ -56,27 +79,27 @@ impl Default for VectorStoreConfig {
 impl Storable for VectorEntry {
     const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
         candid::decode_one(&bytes).unwrap()
     }
 }
 
-// Wrapper type for vector index to implement Storable
+// Per-owner id list of the previous layout; only opened to be cleared
 #[derive(CandidType, Deserialize, Serialize, Clone, Default)]
 pub struct VectorIndexList(pub Vec<String>);
 
 impl Storable for VectorIndexList {
     const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+    fn from_bytes(bytes: Cow<'_, [u8]>) -> Self {
         candid::decode_one(&bytes).unwrap()
     }
 }
@@ -85,38 +108,183 @@ pub struct AdvancedVectorStore;
 
 impl AdvancedVectorStore {
     pub fn init() -> Result<(), String> {
+        Self::open();
+        
+        // Graphs used to be one per owner with every model's vectors mixed in;
+        // they are rebuilt into one graph per owner and model
+        let mut legacy_entry_points: StableBTreeMap<Principal, EntryPoint, _> = StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_HNSW_ENTRY_POINTS)
+        );
+        let regraph = !legacy_entry_points.is_empty();
+        legacy_entry_points.clear_new();
+        
+        // Owners' ids used to be kept as one list per owner; the rebuild
+        // below records them again one entry per vector
+        let mut legacy_user_vectors: StableBTreeMap<Principal, VectorIndexList, _> = StableBTreeMap::init(
+            crate::storage::virtual_memory(crate::storage::MEMORY_ID_USER_VECTORS)
+        );
+        let regraph = regraph || !legacy_user_vectors.is_empty();
+        legacy_user_vectors.clear_new();
+        
+        // Vectors used to live outside the memory manager and did not survive
+        // upgrades; recover them from the embeddings kept on each memory
+        if Self::get_vector_count() == 0
+            && crate::storage::get_memory_count() > 0
+            && !crate::backfill::is_running(crate::backfill::VECTOR_MIGRATION_JOB)
+        {
+            Self::migrate_from_memories();
+        }
+        
+        // Vectors stored before the graph existed are not reachable through it,
+        // and graphs built before nodes were counted per partition would never
+        // be searched; a rebuild already in progress carries on from its saved cursor
+        let uncounted = Self::get_index_size() > 0 && PARTITION_NODE_COUNTS.with(|counts| {
+            counts.borrow().as_ref().is_some_and(|counts| counts.is_empty())
+        });
+        if (regraph || uncounted || Self::get_index_size() != Self::get_vector_count())
+            && !crate::backfill::is_running(crate::backfill::VECTOR_INDEX_JOB)
+        {
+            Self::rebuild_index()?;
+        }
+        
+        ic_cdk::println!("Advanced Vector Store initialized");
+        Ok(())
+    }
+
+    // Attach the maps to their stable memories; entries written before an
+    // upgrade are picked up as they are
+    fn open() {
         VECTORS.with(|v| {
-            *v.borrow_mut() = Some(StableBTreeMap::init(DefaultMemoryImpl::default()));
+            *v.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_VECTORS)
+            ));
         });
         
-        VECTOR_INDEX.with(|vi| {
-            *vi.borrow_mut() = Some(StableBTreeMap::init(DefaultMemoryImpl::default()));
+        HNSW_NODES.with(|n| {
+            *n.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_HNSW_NODES)
+            ));
         });
         
-        ic_cdk::println!("Advanced Vector Store initialized");
+        HNSW_ENTRY_POINTS.with(|ep| {
+            *ep.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_HNSW_PARTITION_ENTRY_POINTS)
+            ));
+        });
+        
+        USER_VECTORS.with(|uv| {
+            *uv.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_OWNER_VECTOR_IDS)
+            ));
+        });
+        
+        USER_VECTOR_COUNTS.with(|counts| {
+            *counts.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_OWNER_VECTOR_COUNTS)
+            ));
+        });
+        
+        PARTITION_NODE_COUNTS.with(|counts| {
+            *counts.borrow_mut() = Some(StableBTreeMap::init(
+                crate::storage::virtual_memory(crate::storage::MEMORY_ID_PARTITION_NODE_COUNTS)
+            ));
+        });
+    }
+
+    // One-time rebuild of the store from `Memory.embedding`, run by a
+    // background job. Requires storage to be initialized.
+    fn migrate_from_memories() {
+        // Graph state left behind without its vectors would point at missing entries
+        HNSW_NODES.with(|n| {
+            if let Some(ref mut nodes) = *n.borrow_mut() {
+                nodes.clear_new();
+            }
+        });
+        HNSW_ENTRY_POINTS.with(|ep| {
+            if let Some(ref mut entry_points) = *ep.borrow_mut() {
+                entry_points.clear_new();
+            }
+        });
+        USER_VECTORS.with(|uv| {
+            if let Some(ref mut user_vectors) = *uv.borrow_mut() {
+                user_vectors.clear_new();
+            }
+        });
+        USER_VECTOR_COUNTS.with(|counts| {
+            if let Some(ref mut counts) = *counts.borrow_mut() {
+                counts.clear_new();
+            }
+        });
+        PARTITION_NODE_COUNTS.with(|counts| {
+            if let Some(ref mut counts) = *counts.borrow_mut() {
+                counts.clear_new();
+            }
+        });
+        
+        crate::backfill::start(crate::backfill::VECTOR_MIGRATION_JOB);
+    }
+
+    // One step of the migration: the embeddings of the next `limit` memories
+    // after `cursor` in id order. add_vector replaces an existing entry, so
+    // memories stored since the migration started are not duplicated.
+    pub fn migrate_batch(cursor: Option<&str>, limit: usize) -> (usize, Option<String>) {
+        let batch = crate::storage::memories_after(cursor, limit);
+        for memory in &batch {
+            if memory.embedding.is_empty() {
+                continue;
+            }
+            if let Err(e) = Self::add_vector(memory.user_id, memory.id.clone(), memory.embedding.clone(), memory.embedding_model.as_deref()) {
+                ic_cdk::println!("Skipping vector migration for {}: {}", memory.id, e);
+            }
+        }
+        
+        let next = if batch.len() < limit { None } else { batch.last().map(|memory| memory.id.clone()) };
+        (batch.len(), next)
+    }
+
+    // `model` is the embedding model that produced the vector. Vectors of a
+    // known model must have its native dimension; unlabeled vectors must have
+    // the store's default dimension.
+    pub fn add_vector(owner: Principal, id: String, vector: Vec<f32>, model: Option<&str>) -> Result<(), String> {
+        Self::put_vector(owner, id.clone(), vector, model, ic_cdk::api::time())?;
+        ic_cdk::println!("Vector added: {}", id);
         Ok(())
     }
 
-    pub fn add_vector(id: String, vector: Vec<f32>) -> Result<(), String> {
+    fn put_vector(owner: Principal, id: String, vector: Vec<f32>, model: Option<&str>, created_at: u64) -> Result<(), String> {
         let config = VECTOR_CONFIG.with(|c| c.borrow().clone());
         
-        if vector.len() != config.dimension {
+        let expected = match model {
+            Some(model) => crate::embedding::model_dimension(model),
+            None => Some(config.dimension),
+        };
+        if vector.is_empty() || expected.is_some_and(|dimension| vector.len() != dimension) {
             return Err(format!(
                 "Vector dimension {} does not match expected dimension {}", 
                 vector.len(), 
-                config.dimension
+                expected.unwrap_or(0)
             ));
         }
 
+        let mut metadata = HashMap::new();
+        if let Some(model) = model {
+            metadata.insert(MODEL_METADATA_KEY.to_string(), model.to_string());
+        }
+
         let norm = compute_norm(&vector);
         let entry = VectorEntry {
             id: id.clone(),
+            owner,
             vector,
-            metadata: HashMap::new(),
-            created_at: ic_cdk::api::time(),
+            metadata,
+            created_at,
             norm,
         };
 
+        // Re-adding an id replaces its position in the graph
+        Self::take_vector(&id);
+        let similarity = similarity_fn(&config.similarity_function);
+
         VECTORS.with(|v| {
             if let Some(ref mut vectors) = *v.borrow_mut() {
                 vectors.insert(id.clone(), entry.clone());
@@ -126,70 +294,92 @@ impl AdvancedVectorStore {
             }
         })?;
 
-        // Update vector index for efficient similarity search
-        Self::update_vector_index(&id, &entry.vector)?;
+        track_owner_vector(owner, &id);
 
-        ic_cdk::println!("Vector added: {}", id);
+        // Update the owner's ANN graph for efficient similarity search
+        hnsw::insert(&mut StableGraph::of(&entry), &id, &entry.vector, &hnsw_params(&config), &similarity);
         Ok(())
     }
 
     pub fn remove_vector(id: &str) -> Result<bool, String> {
-        let removed = VECTORS.with(|v| {
-            if let Some(ref mut vectors) = *v.borrow_mut() {
-                vectors.remove(&id.to_string()).is_some()
-            } else {
-                false
-            }
-        });
-
-        if removed {
-            Self::remove_from_index(id)?;
+        if Self::take_vector(id).is_some() {
             ic_cdk::println!("Vector removed: {}", id);
+            Ok(true)
+        } else {
+            Ok(false)
         }
+    }
 
-        Ok(removed)
+    // Remove a vector from the map, its owner's ids and its graph
+    fn take_vector(id: &str) -> Option<VectorEntry> {
+        let entry = VECTORS.with(|v| v.borrow_mut().as_mut().and_then(|vectors| vectors.remove(&id.to_string())))?;
+
+        untrack_owner_vector(entry.owner, id);
+
+        let config = VECTOR_CONFIG.with(|c| c.borrow().clone());
+        let similarity = similarity_fn(&config.similarity_function);
+        hnsw::remove(&mut StableGraph::of(&entry), id, &hnsw_params(&config), &similarity);
+        Some(entry)
     }
 
+    // Search only the owner's partition, so other users' vectors can never
+    // crowd the owner's results out of the top-k
+    // Only vectors produced by `model` are compared with the query; vectors
+    // from other models (e.g. during a re-embedding job) live in a graph of
+    // their own and are never mixed in.
     pub fn search_similar(
+        owner: Principal,
         query_vector: &[f32], 
+        model: &str,
         limit: usize, 
         threshold: Option<f32>
     ) -> Result<Vec<(String, f32)>, String> {
         let config = VECTOR_CONFIG.with(|c| c.borrow().clone());
         let threshold = threshold.unwrap_or(config.index_threshold);
 
-        if query_vector.len() != config.dimension {
-            return Err(format!(
-                "Query vector dimension {} does not match expected dimension {}", 
-                query_vector.len(), 
-                config.dimension
-            ));
+        if let Some(dimension) = crate::embedding::model_dimension(model) {
+            if query_vector.len() != dimension {
+                return Err(format!(
+                    "Query vector dimension {} does not match dimension {} of model {}", 
+                    query_vector.len(), 
+                    dimension,
+                    model
+                ));
+            }
         }
 
-        let mut similarities = Vec::new();
-        let query_norm = compute_norm(query_vector);
-
-        VECTORS.with(|v| {
-            if let Some(ref vectors) = *v.borrow() {
-                for (id, entry) in vectors.iter() {
-                    let similarity = match config.similarity_function {
-                        SimilarityFunction::Cosine => {
-                            cosine_similarity_normalized(query_vector, &entry.vector, query_norm, entry.norm)
-                        }
-                        SimilarityFunction::Euclidean => {
-                            1.0 / (1.0 + euclidean_distance(query_vector, &entry.vector))
-                        }
-                        SimilarityFunction::DotProduct => {
-                            dot_product(query_vector, &entry.vector)
-                        }
-                    };
+        // Small graphs (or an index that is not populated yet, e.g. while it
+        // is rebuilt in the background) are scanned directly. Outside of a
+        // rebuild every vector of the partition has a node.
+        let use_index = partition_node_count(owner, Some(model)) > config.exact_search_threshold
+            && !crate::backfill::is_running(crate::backfill::VECTOR_INDEX_JOB);
+        let migrating = crate::backfill::is_running(crate::backfill::VECTOR_MIGRATION_JOB);
+
+        let indexed = if use_index && !migrating {
+            let similarity = similarity_fn(&config.similarity_function);
+            Some(hnsw::search(
+                &StableGraph { owner, model: Some(model.to_string()) },
+                query_vector,
+                limit,
+                config.hnsw_ef_search.max(limit),
+                &similarity,
+            ))
+        } else {
+            None
+        };
 
-                    if similarity >= threshold {
-                        similarities.push((id, similarity));
-                    }
-                }
+        // Until the migration has copied every embedding into the store,
+        // compare against the embeddings kept on the memories themselves
+        let mut similarities = if migrating {
+            Self::scan_memories(owner, query_vector, model, &config)
+        } else {
+            match indexed {
+                Some(found) => found,
+                None => Self::exact_scan(query_vector, model, &Self::get_user_vector_ids(owner), &config),
             }
-        });
+        };
+
+        similarities.retain(|(_, similarity)| *similarity >= threshold);
 
         // Sort by similarity (highest first)
         similarities.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
@@ -200,6 +390,41 @@ impl AdvancedVectorStore {
         Ok(similarities)
     }
 
+    fn exact_scan(query_vector: &[f32], model: &str, ids: &[String], config: &VectorStoreConfig) -> Vec<(String, f32)> {
+        let query_norm = compute_norm(query_vector);
+
+        VECTORS.with(|v| {
+            if let Some(ref vectors) = *v.borrow() {
+                ids
+                    .iter()
+                    .filter_map(|id| vectors.get(id))
+                    .filter(|entry| vector_model(entry) == Some(model) && entry.vector.len() == query_vector.len())
+                    .map(|entry| {
+                        let similarity = score(config, query_vector, query_norm, &entry.vector, entry.norm);
+                        (entry.id, similarity)
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            }
+        })
+    }
+
+    fn scan_memories(owner: Principal, query_vector: &[f32], model: &str, config: &VectorStoreConfig) -> Vec<(String, f32)> {
+        let query_norm = compute_norm(query_vector);
+
+        crate::storage::user_memory_ids(owner)
+            .iter()
+            .filter_map(|id| crate::storage::get_memory(id).ok().flatten())
+            .filter(|memory| memory.embedding_model.as_deref() == Some(model) && memory.embedding.len() == query_vector.len())
+            .map(|memory| {
+                let norm = compute_norm(&memory.embedding);
+                let similarity = score(config, query_vector, query_norm, &memory.embedding, norm);
+                (memory.id, similarity)
+            })
+            .collect()
+    }
+
     pub fn get_vector(id: &str) -> Result<Option<VectorEntry>, String> {
         VECTORS.with(|v| {
             if let Some(ref vectors) = *v.borrow() {
@@ -210,6 +435,15 @@ impl AdvancedVectorStore {
         })
     }
 
+    // Record the model of a vector stored before models were tracked. The
+    // vector moves to that model's graph, so it is added again.
+    pub fn set_vector_model(id: &str, model: &str) {
+        let Ok(Some(entry)) = Self::get_vector(id) else { return };
+        if let Err(e) = Self::add_vector(entry.owner, entry.id, entry.vector, Some(model)) {
+            ic_cdk::println!("Failed to label vector {} with model {}: {}", id, model, e);
+        }
+    }
+
     pub fn get_vector_count() -> usize {
         VECTORS.with(|v| {
             if let Some(ref vectors) = *v.borrow() {
@@ -220,6 +454,39 @@ impl AdvancedVectorStore {
         })
     }
 
+    pub fn get_user_vector_ids(owner: Principal) -> Vec<String> {
+        let prefix = owner_vector_key(owner, "");
+        USER_VECTORS.with(|uv| {
+            uv.borrow()
+                .as_ref()
+                .map(|user_vectors| {
+                    user_vectors
+                        .range(prefix.clone()..)
+                        .map(|(key, _)| key)
+                        .take_while(|key| key.starts_with(&prefix))
+                        .map(|key| key[prefix.len()..].to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    }
+
+    pub fn get_user_vector_count(owner: Principal) -> usize {
+        USER_VECTOR_COUNTS.with(|counts| {
+            counts.borrow().as_ref().and_then(|counts| counts.get(&owner)).unwrap_or(0) as usize
+        })
+    }
+
+    pub fn get_index_size() -> usize {
+        HNSW_NODES.with(|n| {
+            if let Some(ref nodes) = *n.borrow() {
+                nodes.len() as usize
+            } else {
+                0
+            }
+        })
+    }
+
     pub fn update_config(config: VectorStoreConfig) -> Result<(), String> {
         VECTOR_CONFIG.with(|c| {
             *c.borrow_mut() = config;
@@ -231,66 +498,72 @@ impl AdvancedVectorStore {
         VECTOR_CONFIG.with(|c| c.borrow().clone())
     }
 
-    // Advanced indexing for faster search
-    fn update_vector_index(id: &str, vector: &[f32]) -> Result<(), String> {
-        // Simple hash-based indexing
-        // In production, this could use more sophisticated indexing like LSH or HNSW
-        let hash = Self::vector_hash(vector);
-        
-        VECTOR_INDEX.with(|vi| {
-            if let Some(ref mut index) = *vi.borrow_mut() {
-                let mut bucket = index.get(&hash).unwrap_or_default();
-                if !bucket.0.contains(&id.to_string()) {
-                    bucket.0.push(id.to_string());
-                    index.insert(hash, bucket);
-                }
+    // Drop the ANN graphs and re-insert every stored vector into its owner's
+    // partition from a background job; searches scan exactly until it is done
+    pub fn rebuild_index() -> Result<(), String> {
+        HNSW_NODES.with(|n| {
+            if let Some(ref mut nodes) = *n.borrow_mut() {
+                nodes.clear_new();
                 Ok(())
             } else {
                 Err("Vector index not initialized".to_string())
             }
-        })
-    }
-
-    fn remove_from_index(id: &str) -> Result<(), String> {
-        VECTOR_INDEX.with(|vi| {
-            if let Some(ref mut index) = *vi.borrow_mut() {
-                // Remove from all buckets (inefficient but simple)
-                let keys: Vec<u64> = index.iter().map(|(k, _)| k).collect();
-                for key in keys {
-                    if let Some(mut bucket) = index.get(&key) {
-                        bucket.0.retain(|item| item != id);
-                        if bucket.0.is_empty() {
-                            index.remove(&key);
-                        } else {
-                            index.insert(key, bucket);
-                        }
-                    }
-                }
-                Ok(())
-            } else {
-                Err("Vector index not initialized".to_string())
+        })?;
+        HNSW_ENTRY_POINTS.with(|ep| {
+            if let Some(ref mut entry_points) = *ep.borrow_mut() {
+                entry_points.clear_new();
             }
-        })
+        });
+        PARTITION_NODE_COUNTS.with(|counts| {
+            if let Some(ref mut counts) = *counts.borrow_mut() {
+                counts.clear_new();
+            }
+        });
+
+        crate::backfill::start(crate::backfill::VECTOR_INDEX_JOB);
+        Ok(())
     }
 
-    fn vector_hash(vector: &[f32]) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        
-        let mut hasher = DefaultHasher::new();
-        // Hash first few dimensions for bucketing
-        for &val in vector.iter().take(10) {
-            ((val * 1000.0) as i32).hash(&mut hasher);
+    // One step of the rebuild: the next `limit` vectors after `cursor` in id
+    // order join their owner's id list and graph. Vectors added since the
+    // rebuild started are already in the graph and are skipped.
+    pub fn rebuild_index_batch(cursor: Option<&str>, limit: usize) -> (usize, Option<String>) {
+        let config = VECTOR_CONFIG.with(|c| c.borrow().clone());
+        let similarity = similarity_fn(&config.similarity_function);
+        let params = hnsw_params(&config);
+
+        let batch: Vec<(String, VectorEntry)> = VECTORS.with(|v| {
+            v.borrow()
+                .as_ref()
+                .map(|vectors| {
+                    let start = match cursor {
+                        Some(cursor) => Bound::Excluded(cursor.to_string()),
+                        None => Bound::Unbounded,
+                    };
+                    vectors.range((start, Bound::Unbounded)).take(limit).collect()
+                })
+                .unwrap_or_default()
+        });
+
+        for (id, entry) in &batch {
+            track_owner_vector(entry.owner, id);
+
+            let mut graph = StableGraph::of(entry);
+            if graph.node(id).is_none() {
+                hnsw::insert(&mut graph, id, &entry.vector, &params, &similarity);
+            }
         }
-        hasher.finish()
+
+        let next = if batch.len() < limit { None } else { batch.last().map(|(id, _)| id.clone()) };
+        (batch.len(), next)
     }
 
     // Batch operations for efficiency
-    pub fn add_vectors_batch(vectors: Vec<(String, Vec<f32>)>) -> Result<Vec<Result<(), String>>, String> {
+    pub fn add_vectors_batch(owner: Principal, vectors: Vec<(String, Vec<f32>)>, model: Option<&str>) -> Result<Vec<Result<(), String>>, String> {
         let mut results = Vec::new();
         
         for (id, vector) in vectors {
-            let result = Self::add_vector(id, vector);
+            let result = Self::add_vector(owner, id, vector, model);
             results.push(result);
         }
         
@@ -321,6 +594,7 @@ impl AdvancedVectorStore {
 
         VectorStoreStats {
             total_vectors: count,
+            indexed_vectors: Self::get_index_size(),
             dimension: config.dimension,
             similarity_function: config.similarity_function,
             avg_norm,
@@ -333,6 +607,7 @@ impl AdvancedVectorStore {
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct VectorStoreStats {
     pub total_vectors: usize,
+    pub indexed_vectors: usize,
     pub dimension: usize,
     pub similarity_function: SimilarityFunction,
     pub avg_norm: f32,
@@ -340,6 +615,179 @@ pub struct VectorStoreStats {
     pub max_norm: f32,
 }
 
+// Stable-memory backing for the HNSW graph of one owner and embedding model.
+// Vectors are read from the main vector map so the graph itself only stores
+// links; vectors outside the partition are invisible to it, so a stray link
+// can never lead a search to another owner's or model's vector.
+struct StableGraph {
+    owner: Principal,
+    model: Option<String>,
+}
+
+impl StableGraph {
+    fn of(entry: &VectorEntry) -> Self {
+        StableGraph {
+            owner: entry.owner,
+            model: vector_model(entry).map(str::to_string),
+        }
+    }
+
+    fn contains(&self, entry: &VectorEntry) -> bool {
+        entry.owner == self.owner && vector_model(entry) == self.model.as_deref()
+    }
+
+    fn adjust_node_count(&self, delta: i64) {
+        let key = partition_key(self.owner, self.model.as_deref());
+        PARTITION_NODE_COUNTS.with(|counts| {
+            if let Some(ref mut counts) = *counts.borrow_mut() {
+                let count = (counts.get(&key).unwrap_or(0) as i64 + delta).max(0) as u64;
+                if count == 0 {
+                    counts.remove(&key);
+                } else {
+                    counts.insert(key, count);
+                }
+            }
+        });
+    }
+}
+
+fn partition_key(owner: Principal, model: Option<&str>) -> String {
+    format!("{}/{}", owner.to_text(), model.unwrap_or(""))
+}
+
+fn partition_node_count(owner: Principal, model: Option<&str>) -> usize {
+    let key = partition_key(owner, model);
+    PARTITION_NODE_COUNTS.with(|counts| {
+        counts.borrow().as_ref().and_then(|counts| counts.get(&key)).unwrap_or(0) as usize
+    })
+}
+
+// Principal text never contains '/', so an owner's keys share the prefix "{owner}/"
+fn owner_vector_key(owner: Principal, id: &str) -> String {
+    format!("{}/{}", owner.to_text(), id)
+}
+
+// Record `id` under its owner; known ids are left alone so the count stays exact
+fn track_owner_vector(owner: Principal, id: &str) {
+    let added = USER_VECTORS.with(|uv| {
+        uv.borrow_mut()
+            .as_mut()
+            .is_some_and(|user_vectors| user_vectors.insert(owner_vector_key(owner, id), ()).is_none())
+    });
+    if added {
+        adjust_owner_vector_count(owner, 1);
+    }
+}
+
+fn untrack_owner_vector(owner: Principal, id: &str) {
+    let removed = USER_VECTORS.with(|uv| {
+        uv.borrow_mut()
+            .as_mut()
+            .is_some_and(|user_vectors| user_vectors.remove(&owner_vector_key(owner, id)).is_some())
+    });
+    if removed {
+        adjust_owner_vector_count(owner, -1);
+    }
+}
+
+fn adjust_owner_vector_count(owner: Principal, delta: i64) {
+    USER_VECTOR_COUNTS.with(|counts| {
+        if let Some(ref mut counts) = *counts.borrow_mut() {
+            let count = (counts.get(&owner).unwrap_or(0) as i64 + delta).max(0) as u64;
+            if count == 0 {
+                counts.remove(&owner);
+            } else {
+                counts.insert(owner, count);
+            }
+        }
+    });
+}
+
+impl GraphStore for StableGraph {
+    fn node(&self, id: &str) -> Option<HnswNode> {
+        HNSW_NODES.with(|n| n.borrow().as_ref().and_then(|nodes| nodes.get(&id.to_string())))
+    }
+
+    fn put_node(&mut self, id: &str, node: HnswNode) {
+        let added = HNSW_NODES.with(|n| {
+            n.borrow_mut().as_mut().is_some_and(|nodes| nodes.insert(id.to_string(), node).is_none())
+        });
+        if added {
+            self.adjust_node_count(1);
+        }
+    }
+
+    fn remove_node(&mut self, id: &str) {
+        let removed = HNSW_NODES.with(|n| {
+            n.borrow_mut().as_mut().is_some_and(|nodes| nodes.remove(&id.to_string()).is_some())
+        });
+        if removed {
+            self.adjust_node_count(-1);
+        }
+    }
+
+    fn vector(&self, id: &str) -> Option<Vec<f32>> {
+        VECTORS.with(|v| v.borrow().as_ref().and_then(|vectors| vectors.get(&id.to_string())))
+            .filter(|entry| self.contains(entry))
+            .map(|entry| entry.vector)
+    }
+
+    fn entry_point(&self) -> Option<EntryPoint> {
+        let key = partition_key(self.owner, self.model.as_deref());
+        HNSW_ENTRY_POINTS.with(|ep| ep.borrow().as_ref().and_then(|entry_points| entry_points.get(&key)))
+    }
+
+    fn set_entry_point(&mut self, entry_point: Option<EntryPoint>) {
+        HNSW_ENTRY_POINTS.with(|ep| {
+            if let Some(ref mut entry_points) = *ep.borrow_mut() {
+                let key = partition_key(self.owner, self.model.as_deref());
+                match entry_point {
+                    Some(entry_point) => entry_points.insert(key, entry_point),
+                    None => entry_points.remove(&key),
+                };
+            }
+        });
+    }
+
+    fn any_node(&self) -> Option<(String, HnswNode)> {
+        AdvancedVectorStore::get_user_vector_ids(self.owner)
+            .into_iter()
+            .filter(|id| self.vector(id).is_some())
+            .find_map(|id| self.node(&id).map(|node| (id, node)))
+    }
+}
+
+fn hnsw_params(config: &VectorStoreConfig) -> HnswParams {
+    HnswParams {
+        m: config.hnsw_m,
+        ef_construction: config.hnsw_ef_construction,
+    }
+}
+
+fn similarity_fn(function: &SimilarityFunction) -> impl Fn(&[f32], &[f32]) -> f32 {
+    let function = function.clone();
+    move |a: &[f32], b: &[f32]| match function {
+        SimilarityFunction::Cosine => cosine_similarity_normalized(a, b, compute_norm(a), compute_norm(b)),
+        SimilarityFunction::Euclidean => 1.0 / (1.0 + euclidean_distance(a, b)),
+        SimilarityFunction::DotProduct => dot_product(a, b),
+    }
+}
+
+// Key of the embedding model in `VectorEntry.metadata`
+const MODEL_METADATA_KEY: &str = "model";
+
+fn score(config: &VectorStoreConfig, query_vector: &[f32], query_norm: f32, vector: &[f32], norm: f32) -> f32 {
+    match config.similarity_function {
+        SimilarityFunction::Cosine => cosine_similarity_normalized(query_vector, vector, query_norm, norm),
+        SimilarityFunction::Euclidean => 1.0 / (1.0 + euclidean_distance(query_vector, vector)),
+        SimilarityFunction::DotProduct => dot_product(query_vector, vector),
+    }
+}
+
+fn vector_model(entry: &VectorEntry) -> Option<&str> {
+    entry.metadata.get(MODEL_METADATA_KEY).map(String::as_str)
+}
+
 // Similarity computation functions
 fn compute_norm(vector: &[f32]) -> f32 {
     vector.iter().map(|x| x * x).sum::<f32>().sqrt()
@@ -392,4 +840,92 @@ mod tests {
         let v = vec![3.0, 4.0];
         assert!((compute_norm(&v) - 5.0).abs() < 1e-6);
     }
-}
\ No newline at end of file
+
+    fn unit(axis: usize) -> Vec<f32> {
+        let mut v = vec![0.0; 4];
+        v[axis] = 1.0;
+        v
+    }
+
+    #[test]
+    fn test_search_is_unaffected_by_other_owners_vectors() {
+        AdvancedVectorStore::open();
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+
+        AdvancedVectorStore::put_vector(alice, "a1".to_string(), unit(0), Some("test-model"), 0).unwrap();
+        AdvancedVectorStore::put_vector(alice, "a2".to_string(), unit(1), Some("test-model"), 0).unwrap();
+        let before = AdvancedVectorStore::search_similar(alice, &unit(0), "test-model", 10, Some(f32::MIN)).unwrap();
+
+        for i in 0..50 {
+            AdvancedVectorStore::put_vector(bob, format!("b{}", i), unit(i % 4), Some("test-model"), 0).unwrap();
+        }
+        let after = AdvancedVectorStore::search_similar(alice, &unit(0), "test-model", 10, Some(f32::MIN)).unwrap();
+
+        assert_eq!(before, after);
+        assert_eq!(after.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["a1", "a2"]);
+        assert_eq!(AdvancedVectorStore::get_user_vector_count(alice), 2);
+        assert_eq!(AdvancedVectorStore::get_user_vector_count(bob), 50);
+
+        // Re-adding an id does not count it twice; removing it does not touch other owners
+        AdvancedVectorStore::put_vector(bob, "b0".to_string(), unit(1), Some("test-model"), 0).unwrap();
+        assert_eq!(AdvancedVectorStore::get_user_vector_count(bob), 50);
+        assert!(AdvancedVectorStore::take_vector("b1").is_some());
+        assert_eq!(AdvancedVectorStore::get_user_vector_count(bob), 49);
+        assert_eq!(AdvancedVectorStore::get_user_vector_ids(alice), vec!["a1", "a2"]);
+    }
+
+    #[test]
+    fn test_graph_nodes_are_counted_per_partition() {
+        AdvancedVectorStore::open();
+        let owner = Principal::from_slice(&[1]);
+
+        for i in 0..3 {
+            AdvancedVectorStore::put_vector(owner, format!("a{}", i), unit(i), Some("model-a"), 0).unwrap();
+        }
+        AdvancedVectorStore::put_vector(owner, "b0".to_string(), unit(0), Some("model-b"), 0).unwrap();
+        assert_eq!(partition_node_count(owner, Some("model-a")), 3);
+        assert_eq!(partition_node_count(owner, Some("model-b")), 1);
+
+        // Moving a vector to another model moves its node with it
+        AdvancedVectorStore::put_vector(owner, "a2".to_string(), unit(2), Some("model-b"), 0).unwrap();
+        assert_eq!(partition_node_count(owner, Some("model-a")), 2);
+        assert_eq!(partition_node_count(owner, Some("model-b")), 2);
+
+        AdvancedVectorStore::take_vector("a0");
+        assert_eq!(partition_node_count(owner, Some("model-a")), 1);
+        assert_eq!(AdvancedVectorStore::get_index_size(), 3);
+    }
+
+    #[test]
+    fn test_vectors_survive_reopening_the_store() {
+        AdvancedVectorStore::open();
+        let owner = Principal::from_slice(&[1]);
+        for i in 0..5 {
+            AdvancedVectorStore::put_vector(owner, format!("m{}", i), unit(i % 4), Some("test-model"), 0).unwrap();
+        }
+        let before = AdvancedVectorStore::search_similar(owner, &unit(0), "test-model", 10, Some(f32::MIN)).unwrap();
+
+        // An upgrade drops the heap; the maps are opened again on the same stable memory
+        VECTORS.with(|v| *v.borrow_mut() = None);
+        HNSW_NODES.with(|n| *n.borrow_mut() = None);
+        HNSW_ENTRY_POINTS.with(|ep| *ep.borrow_mut() = None);
+        USER_VECTORS.with(|uv| *uv.borrow_mut() = None);
+        USER_VECTOR_COUNTS.with(|counts| *counts.borrow_mut() = None);
+        PARTITION_NODE_COUNTS.with(|counts| *counts.borrow_mut() = None);
+        AdvancedVectorStore::open();
+
+        assert_eq!(AdvancedVectorStore::get_vector_count(), 5);
+        assert_eq!(AdvancedVectorStore::get_user_vector_count(owner), 5);
+        assert_eq!(AdvancedVectorStore::search_similar(owner, &unit(0), "test-model", 10, Some(f32::MIN)).unwrap(), before);
+
+        // The graph can be rebuilt from the stored vectors alone
+        HNSW_NODES.with(|n| n.borrow_mut().as_mut().unwrap().clear_new());
+        HNSW_ENTRY_POINTS.with(|ep| ep.borrow_mut().as_mut().unwrap().clear_new());
+        PARTITION_NODE_COUNTS.with(|counts| counts.borrow_mut().as_mut().unwrap().clear_new());
+        assert_eq!(AdvancedVectorStore::rebuild_index_batch(None, 100), (5, None));
+        assert_eq!(AdvancedVectorStore::get_index_size(), 5);
+        assert_eq!(partition_node_count(owner, Some("test-model")), 5);
+        assert_eq!(AdvancedVectorStore::search_similar(owner, &unit(0), "test-model", 10, Some(f32::MIN)).unwrap(), before);
+    }
+}